@@ -0,0 +1,110 @@
+use crate::clone;
+use crate::system::rfkill::{RadioKind, RadioState, Rfkill};
+use crate::toast::show_error_toast;
+use futures::prelude::*;
+use glib::MainContext;
+use glib::PRIORITY_DEFAULT_IDLE;
+use gtk::prelude::*;
+use std::cell::Cell;
+use std::rc::Rc;
+
+fn update_radio_button(button: &gtk::ModelButton, kind: RadioKind, state: &RadioState) {
+  match state.radio(kind) {
+    Some(radio) => {
+      button.set_property_active(!radio.is_blocked());
+      button.set_sensitive(!radio.hard_blocked);
+      if radio.hard_blocked {
+        button.set_label(&format!("{} (hardware switch)", kind.label()));
+        button.set_tooltip_text(Some("Turned off by a hardware switch"));
+      } else {
+        button.set_label(kind.label());
+        button.set_tooltip_text(None);
+      }
+      button.show();
+    }
+    None => button.hide(),
+  }
+}
+
+/// Airplane mode toggle followed by a toggle for each kind of radio.
+pub fn create_airplane_mode_section(c: MainContext, rfkill: Rc<Rfkill>) -> gtk::Box {
+  let section = gtk::Box::new(gtk::Orientation::Vertical, 0);
+
+  let airplane_mode_button = gtk::ModelButton::new();
+  airplane_mode_button.set_property_role(gtk::ButtonRole::Check);
+  airplane_mode_button.set_label("Airplane Mode");
+  section.add(&airplane_mode_button);
+
+  airplane_mode_button.connect_clicked(clone!(c, rfkill => move |button| {
+    let enabled = !button.get_property_active();
+    c.spawn_local(clone!(rfkill => async move {
+      if let Err(error) = rfkill.set_airplane_mode(enabled).await {
+        let verb = if enabled { "turn on airplane mode" } else { "turn off airplane mode" };
+        show_error_toast(verb, &error);
+      }
+    }));
+  }));
+
+  let radio_buttons = RadioKind::ALL
+    .iter()
+    .map(|&kind| {
+      let button = gtk::ModelButton::new();
+      button.set_property_role(gtk::ButtonRole::Check);
+      button.set_label(kind.label());
+      button.set_no_show_all(true);
+      section.add(&button);
+
+      button.connect_clicked(clone!(c, rfkill => move |button| {
+        let blocked = button.get_property_active();
+        c.spawn_local(clone!(rfkill => async move {
+          if let Err(error) = rfkill.set_blocked(Some(kind), blocked).await {
+            let verb = if blocked { "turn off" } else { "turn on" };
+            show_error_toast(&format!("{} {}", verb, kind.label()), &error);
+          }
+        }));
+      }));
+
+      (kind, button)
+    })
+    .collect::<Vec<_>>();
+
+  let destroyed = Rc::new(Cell::new(false));
+  section.connect_destroy(clone!(destroyed => move |_| {
+    destroyed.set(true);
+  }));
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    rfkill
+      .subscribe()
+      .take_while(move |_| future::ready(!destroyed.get()))
+      .for_each(move |state| {
+        airplane_mode_button.set_property_active(state.is_airplane_mode());
+        for (kind, button) in radio_buttons.iter() {
+          update_radio_button(button, *kind, &state);
+        }
+
+        future::ready(())
+      }),
+  );
+
+  section
+}
+
+/// Panel icon that is only visible while airplane mode is on.
+pub fn create_airplane_mode_icon(c: MainContext, rfkill: Rc<Rfkill>) -> gtk::Image {
+  let icon =
+    gtk::Image::new_from_icon_name(Some("airplane-mode-symbolic"), gtk::IconSize::SmallToolbar);
+  icon.set_tooltip_text(Some("Airplane mode"));
+  icon.set_no_show_all(true);
+
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    rfkill.subscribe().for_each(clone!(icon => move |state| {
+      icon.set_visible(state.is_airplane_mode());
+
+      future::ready(())
+    })),
+  );
+
+  icon
+}
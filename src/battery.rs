@@ -0,0 +1,411 @@
+use crate::clone;
+use crate::config::{data_dir, BatteryConfig, Config, CriticalBatteryAction};
+use crate::modal::{close_modal, create_modal};
+use crate::system::charge_limit::{ChargeLimit, ChargeThresholds};
+use crate::system::logind::{Logind, PowerAction};
+use crate::system::upower::{Device, DeviceKind, DeviceState, UPower};
+use crate::toast::{show_error_toast, show_toast};
+use crate::utils::{format_panel_text, spawn_command};
+use futures::prelude::*;
+use glib::MainContext;
+use glib::PRIORITY_DEFAULT_IDLE;
+use gtk::prelude::*;
+use log::{error, warn};
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+
+fn format_duration(duration: Duration) -> String {
+  let minutes = duration.as_secs() / 60;
+
+  if minutes >= 60 {
+    format!("{} h {} min", minutes / 60, minutes % 60)
+  } else {
+    format!("{} min", minutes)
+  }
+}
+
+fn device_name(device: &Device) -> String {
+  if !device.model.is_empty() {
+    return device.model.clone();
+  }
+
+  match device.kind {
+    DeviceKind::Battery => "Battery",
+    DeviceKind::Ups => "UPS",
+    DeviceKind::Mouse => "Mouse",
+    DeviceKind::Keyboard => "Keyboard",
+    DeviceKind::Phone => "Phone",
+    DeviceKind::Tablet => "Tablet",
+    DeviceKind::GamingInput => "Controller",
+    DeviceKind::Headset => "Headset",
+    DeviceKind::Headphones => "Headphones",
+    DeviceKind::LinePower | DeviceKind::Other => "Device",
+  }
+  .to_string()
+}
+
+fn device_status(device: &Device) -> String {
+  match (device.state, device.time_to_empty, device.time_to_full) {
+    (DeviceState::Discharging, Some(time_to_empty), _) => {
+      format!("{} remaining", format_duration(time_to_empty))
+    }
+    (DeviceState::Charging, _, Some(time_to_full)) => {
+      format!("{} until full", format_duration(time_to_full))
+    }
+    (DeviceState::Charging, _, _) => "Charging".to_string(),
+    (DeviceState::Discharging, _, _) => "Discharging".to_string(),
+    (DeviceState::FullyCharged, _, _) => "Fully charged".to_string(),
+    (DeviceState::Empty, _, _) => "Empty".to_string(),
+    (DeviceState::PendingCharge, _, _) | (DeviceState::PendingDischarge, _, _) => {
+      "Not charging".to_string()
+    }
+    (DeviceState::Unknown, _, _) => String::new(),
+  }
+}
+
+fn has_battery(device: &Device) -> bool {
+  device.is_present && device.kind == DeviceKind::Battery
+}
+
+/// Battery icon and percentage for the panel, hidden on machines without a
+/// battery.
+pub fn create_battery_indicator<S>(c: MainContext, upower: Rc<UPower>, refresh: S) -> gtk::Box
+where
+  S: Stream<Item = ()> + 'static,
+{
+  let indicator = gtk::Box::new(gtk::Orientation::Horizontal, 2);
+  let icon =
+    gtk::Image::new_from_icon_name(Some("battery-missing-symbolic"), gtk::IconSize::SmallToolbar);
+  let label = gtk::Label::new(None);
+  indicator.add(&icon);
+  indicator.add(&label);
+  icon.show();
+  label.show();
+  indicator.set_no_show_all(true);
+
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    upower
+      .subscribe_to_display_device(refresh)
+      .for_each(clone!(indicator => move |device| {
+        if has_battery(&device) {
+          icon.set_from_icon_name(Some(&device.icon_name), gtk::IconSize::SmallToolbar);
+          label.set_markup(&format_panel_text(format!("{:.0}%", device.percentage)));
+          indicator.set_tooltip_text(Some(&device_status(&device)));
+          indicator.show();
+        } else {
+          indicator.hide();
+        }
+
+        future::ready(())
+      })),
+  );
+
+  indicator
+}
+
+fn create_device_row(device: &Device) -> gtk::Box {
+  let row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+
+  let icon = gtk::Image::new_from_icon_name(Some(&device.icon_name), gtk::IconSize::Menu);
+  row.pack_start(&icon, false, false, 0);
+
+  let name = gtk::Label::new(None);
+  let status = device_status(device);
+  name.set_markup(&if status.is_empty() {
+    format!("{}", glib::markup_escape_text(&device_name(device)))
+  } else {
+    format!(
+      "{}\n<small>{}</small>",
+      glib::markup_escape_text(&device_name(device)),
+      status
+    )
+  });
+  name.set_xalign(0.0);
+  row.pack_start(&name, true, true, 0);
+
+  let percentage = gtk::Label::new(Some(&format!("{:.0}%", device.percentage)));
+  row.pack_end(&percentage, false, false, 0);
+
+  row
+}
+
+/// Lists every power device in the system menu.
+pub fn create_battery_section(c: MainContext, upower: Rc<UPower>) -> gtk::Box {
+  let section = gtk::Box::new(gtk::Orientation::Vertical, 4);
+  section.set_no_show_all(true);
+
+  let destroyed = Rc::new(Cell::new(false));
+  section.connect_destroy(clone!(destroyed => move |_| {
+    destroyed.set(true);
+  }));
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    upower
+      .subscribe_to_devices()
+      .take_while(move |_| future::ready(!destroyed.get()))
+      .for_each(clone!(section => move |devices| {
+        for child in section.get_children() {
+          section.remove(&child);
+        }
+
+        let devices = devices
+          .iter()
+          .filter(|device| device.is_present && device.kind != DeviceKind::LinePower)
+          .collect::<Vec<_>>();
+        for device in &devices {
+          let row = create_device_row(device);
+          section.add(&row);
+          row.show_all();
+        }
+        section.set_visible(!devices.is_empty());
+
+        future::ready(())
+      })),
+  );
+
+  section
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+enum BatteryLevel {
+  Normal,
+  Low,
+  Critical,
+}
+
+fn battery_level(device: &Device, config: &BatteryConfig) -> BatteryLevel {
+  if !has_battery(device) || device.is_charging() {
+    BatteryLevel::Normal
+  } else if device.percentage <= config.critical_percentage {
+    BatteryLevel::Critical
+  } else if device.percentage <= config.low_percentage {
+    BatteryLevel::Low
+  } else {
+    BatteryLevel::Normal
+  }
+}
+
+fn create_low_battery_warning(device: &Device) -> gtk::Box {
+  let content = gtk::Box::new(gtk::Orientation::Vertical, 16);
+
+  let icon = gtk::Image::new_from_icon_name(Some("battery-caution"), gtk::IconSize::Dialog);
+  content.add(&icon);
+
+  let label = gtk::Label::new(None);
+  let remaining = match device.time_to_empty {
+    Some(time_to_empty) => format!(" (about {})", format_duration(time_to_empty)),
+    None => String::new(),
+  };
+  label.set_markup(&format!(
+    "<b>Battery Low</b>\n{:.0}% remaining{}",
+    device.percentage, remaining
+  ));
+  label.set_justify(gtk::Justification::Center);
+  content.add(&label);
+
+  let ok_button = gtk::Button::new_with_label("OK");
+  ok_button.get_style_context().add_class("modal_button");
+  ok_button.connect_button_press_event(|ok_button, _| {
+    close_modal(ok_button);
+
+    Inhibit(false)
+  });
+  content.add(&ok_button);
+
+  content
+}
+
+fn run_critical_action(c: &MainContext, action: &CriticalBatteryAction, logind: Rc<Logind>) {
+  let power_action = match action {
+    CriticalBatteryAction::Nothing => return,
+    CriticalBatteryAction::Suspend => PowerAction::Suspend,
+    CriticalBatteryAction::Hibernate => PowerAction::Hibernate,
+    CriticalBatteryAction::PowerOff => PowerAction::PowerOff,
+    CriticalBatteryAction::Command(command) => {
+      if let Err(error) = spawn_command(command) {
+        error!("Failed to run \"{}\": {}", command, error);
+      }
+      return;
+    }
+  };
+
+  c.spawn_local(async move {
+    if let Err(error) = logind.perform(power_action).await {
+      show_error_toast("act on the critical battery level", &error);
+    }
+  });
+}
+
+/// Warns when the battery runs low and runs the configured action once it
+/// reaches the critical level. Nothing happens while charging.
+pub async fn watch_battery_level<S>(
+  config: Rc<Config>,
+  logind: Rc<Logind>,
+  upower: Rc<UPower>,
+  refresh: S,
+) where
+  S: Stream<Item = ()> + 'static,
+{
+  let c = MainContext::default();
+  let previous_level = Rc::new(Cell::new(BatteryLevel::Normal));
+  let warning = Rc::new(RefCell::new(None::<gtk::Box>));
+
+  upower
+    .subscribe_to_display_device(refresh)
+    .for_each(move |device| {
+      let level = battery_level(&device, &config.battery);
+
+      if level == BatteryLevel::Normal {
+        if let Some(warning) = warning.borrow_mut().take() {
+          close_modal(&warning);
+        }
+      } else if level > previous_level.get() {
+        match level {
+          BatteryLevel::Low => {
+            let content = create_low_battery_warning(&device);
+            let show_modal = create_modal(&content);
+            show_modal();
+            if let Some(previous_warning) = warning.borrow_mut().replace(content) {
+              close_modal(&previous_warning);
+            }
+          }
+          BatteryLevel::Critical => {
+            warn!("Battery critically low at {:.0}%", device.percentage);
+            show_toast(&format!("Battery critically low ({:.0}%)", device.percentage));
+            run_critical_action(&c, &config.battery.critical_action, logind.clone());
+          }
+          BatteryLevel::Normal => {}
+        }
+      }
+      previous_level.set(level);
+
+      future::ready(())
+    })
+    .await;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ChargeProfile {
+  Full,
+  Conservation,
+}
+
+fn charge_profile_thresholds(profile: ChargeProfile, config: &BatteryConfig) -> ChargeThresholds {
+  match profile {
+    ChargeProfile::Full => ChargeThresholds {
+      start: Some(0),
+      end: 100,
+    },
+    ChargeProfile::Conservation => ChargeThresholds {
+      start: Some(config.conservation_start_percentage),
+      end: config.conservation_end_percentage,
+    },
+  }
+}
+
+fn charge_profile_path() -> PathBuf {
+  data_dir().join("charge-profile")
+}
+
+fn load_charge_profile() -> Option<ChargeProfile> {
+  match fs::read_to_string(charge_profile_path()).ok()?.trim() {
+    "full" => Some(ChargeProfile::Full),
+    "conservation" => Some(ChargeProfile::Conservation),
+    _ => None,
+  }
+}
+
+fn save_charge_profile(profile: ChargeProfile) -> io::Result<()> {
+  let path = charge_profile_path();
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent)?;
+  }
+
+  fs::write(
+    path,
+    match profile {
+      ChargeProfile::Full => "full",
+      ChargeProfile::Conservation => "conservation",
+    },
+  )
+}
+
+/// Many drivers forget the charge thresholds on reboot, so reapply the last
+/// chosen profile.
+pub async fn restore_charge_profile(config: Rc<Config>, charge_limit: Rc<ChargeLimit>) {
+  if let Some(profile) = load_charge_profile() {
+    let thresholds = charge_profile_thresholds(profile, &config.battery);
+    if let Err(error) = charge_limit.set_thresholds(thresholds).await {
+      warn!("Could not restore the battery charge limit: {}", error);
+    }
+  }
+}
+
+fn format_thresholds(thresholds: ChargeThresholds) -> String {
+  match thresholds.start {
+    Some(start) if start > 0 => format!("Charges from {}% to {}%", start, thresholds.end),
+    _ => format!("Charges up to {}%", thresholds.end),
+  }
+}
+
+/// Shows the charge thresholds and toggles between charging fully and a
+/// conservation profile that is easier on the battery.
+pub fn create_charge_limit_row(
+  c: MainContext,
+  config: Rc<Config>,
+  charge_limit: Rc<ChargeLimit>,
+) -> gtk::Box {
+  let row = gtk::Box::new(gtk::Orientation::Vertical, 2);
+
+  let thresholds = charge_limit.thresholds().ok();
+
+  let conservation_button = gtk::ModelButton::new();
+  conservation_button.set_property_role(gtk::ButtonRole::Check);
+  conservation_button.set_label(&format!(
+    "Limit Charge to {}%",
+    config.battery.conservation_end_percentage
+  ));
+  conservation_button.set_property_active(
+    thresholds.map(|thresholds| thresholds.end < 100).unwrap_or(false),
+  );
+  row.add(&conservation_button);
+
+  let thresholds_label = gtk::Label::new(None);
+  thresholds_label.set_xalign(0.0);
+  if let Some(thresholds) = thresholds {
+    thresholds_label.set_markup(&format!("<small>{}</small>", format_thresholds(thresholds)));
+  }
+  row.add(&thresholds_label);
+
+  conservation_button.connect_clicked(move |button| {
+    let profile = if button.get_property_active() {
+      ChargeProfile::Full
+    } else {
+      ChargeProfile::Conservation
+    };
+    let thresholds = charge_profile_thresholds(profile, &config.battery);
+
+    c.spawn_local(clone!(button, charge_limit, thresholds_label => async move {
+      match charge_limit.set_thresholds(thresholds).await {
+        Ok(()) => {
+          button.set_property_active(profile == ChargeProfile::Conservation);
+          thresholds_label.set_markup(&format!("<small>{}</small>", format_thresholds(thresholds)));
+          if let Err(error) = save_charge_profile(profile) {
+            warn!("Could not save the battery charge profile: {}", error);
+          }
+        }
+        Err(error) => {
+          error!("Failed to change the battery charge limit: {}", error);
+          show_toast(&format!("Failed to change the charge limit: {}", error));
+        }
+      }
+    }));
+  });
+
+  row
+}
@@ -0,0 +1,378 @@
+use crate::clone;
+use crate::modal::{close_modal, create_confirm_prompt, create_modal, create_prompt};
+use crate::system::bluetooth::{
+  AgentPrompt, AgentRequest, Bluetooth, BluetoothDevice, BluetoothState,
+};
+use crate::toast::{show_error_toast, show_toast};
+use futures::prelude::*;
+use glib::MainContext;
+use glib::PRIORITY_DEFAULT_IDLE;
+use gtk::prelude::*;
+use log::warn;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+/// Panel icon showing whether Bluetooth is on, hidden without an adapter.
+pub fn create_bluetooth_icon(c: MainContext, bluetooth: Rc<Bluetooth>) -> gtk::Image {
+  let icon =
+    gtk::Image::new_from_icon_name(Some("bluetooth-active-symbolic"), gtk::IconSize::SmallToolbar);
+  icon.set_no_show_all(true);
+
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    bluetooth.subscribe().for_each(clone!(icon => move |state| {
+      match state.adapter {
+        Some(ref adapter) => {
+          let icon_name = if adapter.powered {
+            "bluetooth-active-symbolic"
+          } else {
+            "bluetooth-disabled-symbolic"
+          };
+          icon.set_from_icon_name(Some(icon_name), gtk::IconSize::SmallToolbar);
+
+          let connected = state
+            .connected_devices()
+            .map(|device| device.name.clone())
+            .collect::<Vec<_>>();
+          icon.set_tooltip_text(Some(&if !adapter.powered {
+            "Bluetooth is off".to_string()
+          } else if connected.is_empty() {
+            "Bluetooth is on".to_string()
+          } else {
+            format!("Connected to {}", connected.join(", "))
+          }));
+          icon.show();
+        }
+        None => icon.hide(),
+      }
+
+      future::ready(())
+    })),
+  );
+
+  icon
+}
+
+fn create_device_row(
+  c: &MainContext,
+  bluetooth: &Rc<Bluetooth>,
+  device: &BluetoothDevice,
+) -> gtk::Button {
+  let button = gtk::Button::new();
+  button.set_relief(gtk::ReliefStyle::None);
+
+  let row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+  let device_icon = gtk::Image::new_from_icon_name(
+    Some(&format!("{}-symbolic", device.icon_name)),
+    gtk::IconSize::Menu,
+  );
+  row.pack_start(&device_icon, false, false, 0);
+
+  let label = gtk::Label::new(None);
+  label.set_markup(&format!(
+    "{}\n<small>{}</small>",
+    glib::markup_escape_text(&device.name),
+    if device.connected { "Connected" } else { "Not connected" },
+  ));
+  label.set_xalign(0.0);
+  row.pack_start(&label, true, true, 0);
+
+  if let Some(battery) = device.battery {
+    let battery_label = gtk::Label::new(Some(&format!("{}%", battery)));
+    battery_label.set_tooltip_text(Some("Battery"));
+    row.pack_end(&battery_label, false, false, 0);
+  }
+
+  button.add(&row);
+
+  let device = device.clone();
+  button.connect_clicked(clone!(c, bluetooth => move |_| {
+    c.spawn_local(clone!(bluetooth, device => async move {
+      let result = if device.connected {
+        bluetooth.disconnect(&device).await
+      } else {
+        bluetooth.connect(&device).await
+      };
+      if let Err(error) = result {
+        let verb = if device.connected { "disconnect" } else { "connect to" };
+        show_error_toast(&format!("{} {}", verb, device.name), &error);
+      }
+    }));
+  }));
+
+  button
+}
+
+fn update_bluetooth_section(
+  c: &MainContext,
+  bluetooth: &Rc<Bluetooth>,
+  power_button: &gtk::ModelButton,
+  device_list: &gtk::Box,
+  state: &BluetoothState,
+) {
+  let powered = state.adapter.as_ref().map_or(false, |adapter| adapter.powered);
+  power_button.set_property_active(powered);
+
+  for child in device_list.get_children() {
+    device_list.remove(&child);
+  }
+
+  if powered {
+    for device in state.paired_devices() {
+      device_list.add(&create_device_row(c, bluetooth, device));
+    }
+  }
+
+  device_list.show_all();
+}
+
+/// Bluetooth power toggle and the paired devices, hidden without an adapter.
+pub fn create_bluetooth_section(c: MainContext, bluetooth: Rc<Bluetooth>) -> gtk::Box {
+  let section = gtk::Box::new(gtk::Orientation::Vertical, 0);
+  section.set_no_show_all(true);
+
+  let power_button = gtk::ModelButton::new();
+  power_button.set_property_role(gtk::ButtonRole::Check);
+  power_button.set_label("Bluetooth");
+  section.add(&power_button);
+
+  let device_list = gtk::Box::new(gtk::Orientation::Vertical, 2);
+  section.add(&device_list);
+
+  let pair_button = gtk::ModelButton::new();
+  pair_button.set_label("Pair New Device…");
+  pair_button.set_property_menu_name(Some("bluetooth-pairing"));
+  section.add(&pair_button);
+
+  let state = Rc::new(RefCell::new(BluetoothState::default()));
+  power_button.connect_clicked(clone!(c, bluetooth, state => move |button| {
+    let adapter = match state.borrow().adapter {
+      Some(ref adapter) => adapter.clone(),
+      None => return,
+    };
+    let powered = !button.get_property_active();
+    c.spawn_local(clone!(bluetooth => async move {
+      if let Err(error) = bluetooth.set_powered(&adapter, powered).await {
+        let verb = if powered { "turn on Bluetooth" } else { "turn off Bluetooth" };
+        show_error_toast(verb, &error);
+      }
+    }));
+  }));
+
+  let destroyed = Rc::new(Cell::new(false));
+  section.connect_destroy(clone!(destroyed => move |_| {
+    destroyed.set(true);
+  }));
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    bluetooth
+      .subscribe()
+      .take_while(move |_| future::ready(!destroyed.get()))
+      .for_each(clone!(c, bluetooth, section => move |new_state| {
+        update_bluetooth_section(&c, &bluetooth, &power_button, &device_list, &new_state);
+        pair_button.set_visible(new_state.adapter.as_ref().map_or(false, |a| a.powered));
+        power_button.show();
+        section.set_visible(new_state.adapter.is_some());
+        *state.borrow_mut() = new_state;
+
+        future::ready(())
+      })),
+  );
+
+  section
+}
+
+fn update_pairing_list(
+  c: &MainContext,
+  bluetooth: &Rc<Bluetooth>,
+  list: &gtk::Box,
+  state: &BluetoothState,
+) {
+  for child in list.get_children() {
+    list.remove(&child);
+  }
+
+  let devices = state
+    .devices
+    .iter()
+    .filter(|device| !device.paired && !device.name.is_empty())
+    .collect::<Vec<_>>();
+
+  if devices.is_empty() {
+    let label = gtk::Label::new(Some("Looking for devices…"));
+    list.add(&label);
+  }
+
+  for device in devices {
+    let button = gtk::ModelButton::new();
+    button.set_label(&device.name);
+    button.connect_clicked(clone!(c, bluetooth, device => move |_| {
+      c.spawn_local(clone!(bluetooth, device => async move {
+        if let Err(error) = bluetooth.pair(&device).await {
+          show_error_toast(&format!("pair with {}", device.name), &error);
+        }
+      }));
+    }));
+    list.add(&button);
+  }
+
+  list.show_all();
+}
+
+/// Submenu that makes the adapter discoverable while it is open and lists
+/// the devices found nearby.
+pub fn create_pairing_menu(c: MainContext, bluetooth: Rc<Bluetooth>) -> gtk::Box {
+  let menu = gtk::Box::new(gtk::Orientation::Vertical, 4);
+
+  let back_button = gtk::ModelButton::new();
+  back_button.set_label("Pair New Device");
+  back_button.set_property_menu_name(Some("main"));
+  back_button.set_property_inverted(true);
+  back_button.set_property_centered(true);
+  menu.add(&back_button);
+
+  let visible_as = gtk::Label::new(None);
+  visible_as.set_line_wrap(true);
+  menu.add(&visible_as);
+
+  let list = gtk::Box::new(gtk::Orientation::Vertical, 2);
+  menu.add(&list);
+
+  let state = Rc::new(RefCell::new(BluetoothState::default()));
+  let set_discoverable = Rc::new(clone!(c, bluetooth, state => move |discoverable| {
+    if let Some(adapter) = state.borrow().adapter.clone() {
+      c.spawn_local(clone!(bluetooth => async move {
+        if let Err(error) = bluetooth.set_discoverable(&adapter, discoverable).await {
+          warn!("Could not change Bluetooth discovery: {}", error);
+        }
+      }));
+    }
+  }));
+  menu.connect_map(clone!(set_discoverable => move |_| set_discoverable(true)));
+  menu.connect_unmap(move |_| set_discoverable(false));
+
+  let destroyed = Rc::new(Cell::new(false));
+  menu.connect_destroy(clone!(destroyed => move |_| {
+    destroyed.set(true);
+  }));
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    bluetooth
+      .subscribe()
+      .take_while(move |_| future::ready(!destroyed.get()))
+      .for_each(clone!(c, bluetooth => move |new_state| {
+        if let Some(ref adapter) = new_state.adapter {
+          visible_as.set_text(&format!("Visible to nearby devices as \"{}\"", adapter.name));
+        }
+        update_pairing_list(&c, &bluetooth, &list, &new_state);
+        *state.borrow_mut() = new_state;
+
+        future::ready(())
+      })),
+  );
+
+  menu
+}
+
+/// The device and label of the modal showing a passkey to type.
+type PasskeyDisplay = Rc<RefCell<Option<(String, gtk::Label)>>>;
+
+/// Shows the passkey in a single modal per device, updated as BlueZ reports
+/// the digits typed on the device.
+fn display_passkey(
+  display: &PasskeyDisplay,
+  request: &AgentRequest,
+  passkey: u32,
+  entered: u16,
+) -> Box<dyn Fn()> {
+  let message = format!(
+    "Enter {:06} on {} to pair\n{} of 6 digits typed",
+    passkey,
+    request.device_name,
+    entered.min(6)
+  );
+
+  let shown = display.borrow().clone().filter(|(device, label)| {
+    device == &request.device
+      && label
+        .get_toplevel()
+        .map_or(false, |toplevel| toplevel.is::<gtk::Window>() && toplevel.is_visible())
+  });
+  let label = match shown {
+    Some((_, label)) => {
+      label.set_text(&message);
+      label
+    }
+    None => {
+      let label = gtk::Label::new(Some(&message));
+      label.set_justify(gtk::Justification::Center);
+      let show_modal = create_modal(&label);
+      show_modal();
+      display.replace(Some((request.device.clone(), label.clone())));
+      label
+    }
+  };
+
+  Box::new(move || close_modal(&label))
+}
+
+fn prompt_for_pairing(passkey_display: &PasskeyDisplay, request: AgentRequest) -> Box<dyn Fn()> {
+  let device_name = request.device_name.clone();
+  let message = match request.prompt {
+    AgentPrompt::Confirmation(passkey) => format!(
+      "Confirm that {} shows the passkey {:06}",
+      device_name, passkey
+    ),
+    AgentPrompt::Authorization => format!("Pair with {}?", device_name),
+    AgentPrompt::ServiceAuthorization(ref uuid) => {
+      format!("Allow {} to use the service {}?", device_name, uuid)
+    }
+    AgentPrompt::PinCode => format!("Enter the PIN code for {}", device_name),
+    AgentPrompt::Passkey => format!("Enter the passkey for {}", device_name),
+    AgentPrompt::DisplayPinCode(ref pin_code) => {
+      show_toast(&format!("Enter {} on {} to pair", pin_code, device_name));
+      return Box::new(|| {});
+    }
+    AgentPrompt::DisplayPasskey(passkey, entered) => {
+      return display_passkey(passkey_display, &request, passkey, entered);
+    }
+  };
+
+  let confirm_label = match request.prompt {
+    AgentPrompt::ServiceAuthorization(_) => "Allow",
+    _ => "Pair",
+  };
+  let asks_for_text = match request.prompt {
+    AgentPrompt::PinCode | AgentPrompt::Passkey => true,
+    _ => false,
+  };
+  let request = RefCell::new(Some(request));
+  let reply = move |answer: Option<String>| {
+    if let Some(request) = request.borrow_mut().take() {
+      request.reply(answer);
+    }
+  };
+
+  let content = if asks_for_text {
+    create_prompt(&message, false, reply)
+  } else {
+    create_confirm_prompt(&message, confirm_label, move |confirmed| {
+      reply(if confirmed { Some(String::new()) } else { None });
+    })
+  };
+  let show_modal = create_modal(&content);
+
+  show_modal();
+
+  Box::new(move || close_modal(&content))
+}
+
+/// Makes the panel answer BlueZ pairing requests.
+pub async fn register_bluetooth_agent(bluetooth: Rc<Bluetooth>) {
+  let passkey_display = PasskeyDisplay::default();
+  let prompt = move |request| prompt_for_pairing(&passkey_display, request);
+
+  if let Err(error) = bluetooth.register_agent(prompt).await {
+    warn!("Could not register as a Bluetooth agent: {}", error);
+  }
+}
@@ -0,0 +1,105 @@
+use crate::clone;
+use crate::osd::Osd;
+use crate::system::backlight::Backlight;
+use crate::toast::show_error_toast;
+use futures::prelude::*;
+use glib::MainContext;
+use glib::PRIORITY_DEFAULT_IDLE;
+use gtk::prelude::*;
+use std::cell::Cell;
+use std::rc::Rc;
+
+const SCROLL_STEP: f64 = 0.05;
+
+fn brightness_icon(backlight: &Backlight) -> &'static str {
+  if backlight.is_keyboard() {
+    "keyboard-brightness-symbolic"
+  } else {
+    "display-brightness-symbolic"
+  }
+}
+
+fn set_brightness(c: &MainContext, backlight: Rc<Backlight>, brightness: f64) {
+  c.spawn_local(async move {
+    if let Err(error) = backlight.set_brightness(brightness).await {
+      show_error_toast("change the brightness", &error);
+    }
+  });
+}
+
+pub fn create_brightness_slider(c: MainContext, backlight: Rc<Backlight>) -> gtk::Box {
+  let slider_row = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+  let slider_icon =
+    gtk::Image::new_from_icon_name(Some(brightness_icon(&backlight)), gtk::IconSize::Menu);
+  let slider = gtk::Scale::new_with_range(gtk::Orientation::Horizontal, 0.0, 1.0, SCROLL_STEP);
+  slider.set_value(backlight.get_brightness());
+  slider.set_draw_value(false);
+  slider_row.pack_start(&slider_icon, false, false, 0);
+  slider_row.pack_end(&slider, false, false, 0);
+
+  let lock_slider = Rc::new(Cell::new(false));
+  slider.connect_value_changed(clone!(c, backlight, lock_slider => move |slider| {
+    if !lock_slider.get() {
+      set_brightness(&c, backlight.clone(), slider.get_value());
+    }
+  }));
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    backlight.subscribe().for_each(move |brightness| {
+      lock_slider.set(true);
+      slider.set_value(brightness);
+      lock_slider.set(false);
+
+      future::ready(())
+    }),
+  );
+
+  slider_row
+}
+
+/// Panel icon that changes the brightness when scrolled over.
+pub fn create_brightness_icon(c: MainContext, backlight: Rc<Backlight>) -> gtk::EventBox {
+  let icon = gtk::Image::new_from_icon_name(
+    Some(brightness_icon(&backlight)),
+    gtk::IconSize::SmallToolbar,
+  );
+
+  let event_box = gtk::EventBox::new();
+  event_box.add(&icon);
+  event_box.add_events(gdk::EventMask::SCROLL_MASK | gdk::EventMask::SMOOTH_SCROLL_MASK);
+
+  event_box.connect_scroll_event(move |_, event| {
+    let step = match event.get_direction() {
+      gdk::ScrollDirection::Up => SCROLL_STEP,
+      gdk::ScrollDirection::Down => -SCROLL_STEP,
+      gdk::ScrollDirection::Smooth => {
+        let (_, delta_y) = event.get_delta();
+        -delta_y * SCROLL_STEP
+      }
+      _ => 0.0,
+    };
+
+    if step != 0.0 {
+      let brightness = (backlight.get_brightness() + step).max(0.0).min(1.0);
+      set_brightness(&c, backlight.clone(), brightness);
+    }
+
+    Inhibit(true)
+  });
+
+  event_box
+}
+
+/// Shows the OSD whenever the brightness changes, no matter who changed it.
+pub async fn show_brightness_osd(osd: Rc<Osd>, backlight: Rc<Backlight>) {
+  let icon = brightness_icon(&backlight);
+
+  backlight
+    .subscribe()
+    .for_each(move |brightness| {
+      osd.show(icon, brightness);
+
+      future::ready(())
+    })
+    .await;
+}
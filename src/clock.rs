@@ -1,25 +1,49 @@
 use crate::clone;
+use crate::notification_center::{
+  create_notification_badge, create_notification_history, NotificationCenter,
+};
 use crate::popup::create_popup;
+use crate::system::logind::Logind;
 use crate::utils::format_panel_text;
 use chrono::Local;
+use futures::prelude::*;
+use glib::MainContext;
+use glib::PRIORITY_DEFAULT_IDLE;
 use gtk::prelude::*;
+use std::rc::Rc;
 
 fn current_time() -> String {
   format_panel_text(Local::now().format("%h %d %H:%M"))
 }
 
-fn create_time_menu() -> impl gtk::IsA<gtk::Widget> {
+fn create_time_menu(
+  c: MainContext,
+  notification_center: &Option<NotificationCenter>,
+) -> impl gtk::IsA<gtk::Widget> {
+  let time_menu = gtk::Box::new(gtk::Orientation::Horizontal, 16);
+
   let calendar = gtk::CalendarBuilder::new()
     .margin(16)
     .expand(true)
     .show_heading(true)
     .show_week_numbers(true)
     .build();
+  time_menu.add(&calendar);
+
+  if let Some(notification_center) = notification_center {
+    let separator = gtk::Separator::new(gtk::Orientation::Vertical);
+    time_menu.add(&separator);
+    time_menu.add(&create_notification_history(c, notification_center));
+  }
 
-  calendar
+  time_menu
 }
 
-pub fn create_clock() -> impl gtk::IsA<gtk::Widget> {
+pub fn create_clock(
+  c: MainContext,
+  logind: Rc<Logind>,
+  notification_center: Option<NotificationCenter>,
+) -> impl gtk::IsA<gtk::Widget> {
   let label = gtk::Label::new(None);
   label.set_margin_top(6);
   label.set_margin_bottom(6);
@@ -32,11 +56,30 @@ pub fn create_clock() -> impl gtk::IsA<gtk::Widget> {
 
   gtk::timeout_add_seconds(1, tick);
 
+  // Don't show the time from before the system went to sleep
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    logind.subscribe_to_resume().for_each(clone!(label => move |_| {
+      label.set_markup(&current_time());
+
+      future::ready(())
+    })),
+  );
+
+  let content = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+  content.set_halign(gtk::Align::Center);
+  content.add(&label);
+
+  if let Some(ref notification_center) = notification_center {
+    let badge = create_notification_badge(c.clone(), notification_center);
+    content.add(&badge);
+  }
+
   let time_button = gtk::EventBox::new();
-  time_button.add(&label);
+  time_button.add(&content);
 
   time_button.connect_button_press_event(move |time_button, _| {
-    let time_menu = create_time_menu();
+    let time_menu = create_time_menu(c.clone(), &notification_center);
     let show_popup = create_popup(time_button, &time_menu);
 
     show_popup();
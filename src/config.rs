@@ -0,0 +1,187 @@
+use crate::system::logind::PowerAction;
+use log::error;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+  /// Lock the session before the system goes to sleep.
+  pub lock_on_suspend: bool,
+  pub idle_inhibitor: IdleInhibitorConfig,
+  pub battery: BatteryConfig,
+  pub power: PowerConfig,
+  pub network_monitor: NetworkMonitorConfig,
+  pub notifications: NotificationsConfig,
+  pub tray: TrayConfig,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CriticalBatteryAction {
+  Nothing,
+  Suspend,
+  Hibernate,
+  PowerOff,
+  Command(String),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct BatteryConfig {
+  pub low_percentage: f64,
+  pub critical_percentage: f64,
+  /// Only notifies by default, suspending or hibernating is opt in.
+  pub critical_action: CriticalBatteryAction,
+  /// Charge thresholds of the conservation profile, for batteries that
+  /// support charge limits.
+  pub conservation_start_percentage: u32,
+  pub conservation_end_percentage: u32,
+}
+
+impl Default for BatteryConfig {
+  fn default() -> BatteryConfig {
+    BatteryConfig {
+      low_percentage: 10.0,
+      critical_percentage: 5.0,
+      critical_action: CriticalBatteryAction::Nothing,
+      conservation_start_percentage: 75,
+      conservation_end_percentage: 80,
+    }
+  }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct IdleInhibitorConfig {
+  /// Also keep the system from suspending, not just from idling.
+  pub sleep: bool,
+  pub expire_after_minutes: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct PowerConfig {
+  /// The buttons shown in the power modal, in order.
+  pub actions: Vec<PowerAction>,
+  /// Shell commands to run instead of asking logind.
+  pub commands: HashMap<PowerAction, String>,
+  /// Ask for confirmation before logging out, restarting or shutting down.
+  pub confirm: bool,
+  pub confirm_seconds: u32,
+}
+
+impl Default for PowerConfig {
+  fn default() -> PowerConfig {
+    PowerConfig {
+      actions: PowerAction::ALL.to_vec(),
+      commands: HashMap::new(),
+      confirm: true,
+      confirm_seconds: 60,
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct NetworkMonitorConfig {
+  /// Show the transfer rates in the panel.
+  pub enabled: bool,
+  pub interval_seconds: u32,
+  /// Number of samples shown in the graphs.
+  pub history_length: usize,
+  /// Interfaces to measure, all but loopback when empty.
+  pub interfaces: Vec<String>,
+}
+
+impl Default for NetworkMonitorConfig {
+  fn default() -> NetworkMonitorConfig {
+    NetworkMonitorConfig {
+      enabled: false,
+      interval_seconds: 2,
+      history_length: 60,
+      interfaces: vec![],
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct NotificationsConfig {
+  /// Act as the notification daemon of the session.
+  pub enabled: bool,
+  /// How long notifications are shown when the sender does not say.
+  pub default_timeout_seconds: u32,
+  /// Number of notifications kept in the history.
+  pub history_length: usize,
+  /// Turn on do not disturb every day between these times, like "18:00"
+  /// and "08:00".
+  pub do_not_disturb_start: Option<String>,
+  pub do_not_disturb_end: Option<String>,
+}
+
+impl Default for NotificationsConfig {
+  fn default() -> NotificationsConfig {
+    NotificationsConfig {
+      enabled: true,
+      default_timeout_seconds: 5,
+      history_length: 100,
+      do_not_disturb_start: None,
+      do_not_disturb_end: None,
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct TrayConfig {
+  /// Show the tray icons of applications, acting as the status notifier
+  /// watcher if no other is running.
+  pub enabled: bool,
+}
+
+impl Default for TrayConfig {
+  fn default() -> TrayConfig {
+    TrayConfig { enabled: true }
+  }
+}
+
+pub fn config_dir() -> PathBuf {
+  env::var_os("XDG_CONFIG_HOME")
+    .map(PathBuf::from)
+    .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    .unwrap_or_default()
+    .join("panel")
+}
+
+pub fn data_dir() -> PathBuf {
+  env::var_os("XDG_DATA_HOME")
+    .map(PathBuf::from)
+    .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+    .unwrap_or_default()
+    .join("panel")
+}
+
+impl Config {
+  /// Reads `$XDG_CONFIG_HOME/panel/config.toml`, falling back to the
+  /// defaults if it is missing or invalid.
+  pub fn load() -> Config {
+    let path = config_dir().join("config.toml");
+
+    match fs::read_to_string(&path) {
+      Ok(content) => toml::from_str(&content).unwrap_or_else(|error| {
+        error!("Invalid config file {}: {}", path.display(), error);
+        Config::default()
+      }),
+      Err(ref error) if error.kind() == io::ErrorKind::NotFound => Config::default(),
+      Err(error) => {
+        error!("Could not read config file {}: {}", path.display(), error);
+        Config::default()
+      }
+    }
+  }
+}
@@ -0,0 +1,153 @@
+use crate::clone;
+use crate::modal::create_confirm_modal;
+use crate::system::logind::{Inhibitor, Logind};
+use crate::toast::show_toast;
+use crate::utils::spawn_command;
+use glib::MainContext;
+use gtk::prelude::*;
+use log::{error, warn};
+use std::cell::Cell;
+use std::io;
+use std::rc::Rc;
+
+const REFRESH_INTERVAL_SECONDS: u32 = 2;
+
+/// Whether `pid` is a single process other than the panel. Zero and
+/// values that overflow `pid_t` would signal process groups instead.
+fn can_kill(pid: u32) -> bool {
+  pid != 0 && pid <= i32::max_value() as u32 && pid != std::process::id()
+}
+
+fn kill_process(pid: u32) -> io::Result<()> {
+  if !can_kill(pid) {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidInput,
+      format!("Refusing to end process {}", pid),
+    ));
+  }
+
+  if unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } == 0 {
+    Ok(())
+  } else {
+    Err(io::Error::last_os_error())
+  }
+}
+
+fn create_inhibitor_row(inhibitor: &Inhibitor) -> gtk::Box {
+  let row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+
+  let description = gtk::Label::new(None);
+  description.set_markup(&format!(
+    "<b>{}</b> (PID {})\n{}\n<small>{} ({})</small>",
+    glib::markup_escape_text(&inhibitor.who),
+    inhibitor.pid,
+    glib::markup_escape_text(&inhibitor.why),
+    glib::markup_escape_text(&inhibitor.what.replace(':', ", ")),
+    glib::markup_escape_text(&inhibitor.mode),
+  ));
+  description.set_xalign(0.0);
+  description.set_line_wrap(true);
+  row.pack_start(&description, true, true, 0);
+
+  let focus_button =
+    gtk::Button::new_from_icon_name(Some("go-jump-symbolic"), gtk::IconSize::Button);
+  focus_button.set_tooltip_text(Some("Go to application"));
+  let pid = inhibitor.pid;
+  focus_button.connect_button_press_event(move |_, _| {
+    let command = format!("swaymsg '[pid={}]' focus", pid);
+    if let Err(error) = spawn_command(&command) {
+      error!("Failed to run \"{}\": {}", command, error);
+    }
+
+    Inhibit(false)
+  });
+  row.pack_end(&focus_button, false, false, 0);
+
+  let kill_button =
+    gtk::Button::new_from_icon_name(Some("process-stop-symbolic"), gtk::IconSize::Button);
+  kill_button.set_tooltip_text(Some("End process"));
+  let message = format!(
+    "End {} (PID {})? It is inhibiting {} because: {}",
+    inhibitor.who,
+    inhibitor.pid,
+    inhibitor.what.replace(':', ", "),
+    inhibitor.why
+  );
+  kill_button.connect_button_press_event(move |_, _| {
+    let show_modal = create_confirm_modal(&message, "End Process", move || {
+      if let Err(error) = kill_process(pid) {
+        error!("Failed to end process {}: {}", pid, error);
+        show_toast(&format!("Failed to end process {}: {}", pid, error));
+      }
+    });
+
+    show_modal();
+
+    Inhibit(false)
+  });
+  if can_kill(pid) {
+    row.pack_end(&kill_button, false, false, 0);
+  }
+
+  row
+}
+
+fn update_inhibitor_list(list: &gtk::Box, inhibitors: &[Inhibitor]) {
+  for child in list.get_children() {
+    list.remove(&child);
+  }
+
+  if inhibitors.is_empty() {
+    let label = gtk::Label::new(Some("Nothing is inhibiting sleep or shutdown"));
+    list.add(&label);
+  }
+
+  for inhibitor in inhibitors {
+    list.add(&create_inhibitor_row(inhibitor));
+  }
+
+  list.show_all();
+}
+
+/// Submenu listing everything that currently holds a logind inhibitor.
+pub fn create_inhibitors_menu(c: MainContext, logind: Rc<Logind>) -> gtk::Box {
+  let menu = gtk::Box::new(gtk::Orientation::Vertical, 4);
+
+  let back_button = gtk::ModelButton::new();
+  back_button.set_label("Inhibitors");
+  back_button.set_property_menu_name(Some("main"));
+  back_button.set_property_inverted(true);
+  back_button.set_property_centered(true);
+  menu.add(&back_button);
+
+  let list = gtk::Box::new(gtk::Orientation::Vertical, 8);
+  menu.add(&list);
+
+  let refresh = Rc::new(clone!(c, logind, list => move || {
+    c.spawn_local(clone!(logind, list => async move {
+      match logind.list_inhibitors().await {
+        Ok(inhibitors) => update_inhibitor_list(&list, &inhibitors),
+        Err(error) => warn!("Could not list inhibitors: {}", error),
+      }
+    }));
+  }));
+  refresh();
+
+  // logind does not signal new inhibitors, so poll while the menu exists
+  let destroyed = Rc::new(Cell::new(false));
+  menu.connect_destroy(clone!(destroyed => move |_| {
+    destroyed.set(true);
+  }));
+  gtk::timeout_add_seconds(REFRESH_INTERVAL_SECONDS, move || {
+    if destroyed.get() {
+      return gtk::Continue(false);
+    }
+
+    if list.get_mapped() {
+      refresh();
+    }
+    gtk::Continue(true)
+  });
+
+  menu
+}
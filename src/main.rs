@@ -1,33 +1,111 @@
 #![feature(exclusive_range_pattern)]
 
+mod airplane_mode;
+mod battery;
+mod bluetooth;
+mod brightness;
 mod clock;
+mod config;
+mod inhibitors;
+mod media;
 mod modal;
+mod network;
+mod notification_center;
+mod notifications;
+mod osd;
 mod popup;
+mod power;
+mod power_profiles;
 mod settings;
 mod system;
+mod throughput;
+mod toast;
+mod tray;
 mod utils;
+mod workspaces;
 
+pub use crate::battery::{restore_charge_profile, watch_battery_level};
+pub use crate::bluetooth::register_bluetooth_agent;
+pub use crate::brightness::show_brightness_osd;
 pub use crate::clock::create_clock;
+pub use crate::config::Config;
+pub use crate::media::create_media_indicator;
+pub use crate::network::register_secret_agent;
+pub use crate::notification_center::{create_notification_badge, NotificationCenter};
+pub use crate::notifications::show_notification_popups;
+pub use crate::osd::Osd;
+pub use crate::power::create_scheduled_shutdown_indicator;
 pub use crate::settings::create_settings_button;
 pub use crate::system::audio::*;
+pub use crate::system::backlight::{Backlight, BacklightDevice};
+pub use crate::system::bluetooth::Bluetooth;
+pub use crate::system::charge_limit::ChargeLimit;
+pub use crate::system::bus::Bus;
+pub use crate::system::idle_inhibitor::IdleInhibitor;
+pub use crate::system::logind::{lock_before_sleep, Logind};
+pub use crate::system::mpris::Mpris;
+pub use crate::system::do_not_disturb::DoNotDisturb;
+pub use crate::system::network::NetworkManager;
+pub use crate::system::notification_history::{record_notifications, NotificationHistory};
+pub use crate::system::notifications::NotificationServer;
+pub use crate::system::power_profiles::PowerProfiles;
+pub use crate::system::rfkill::Rfkill;
+pub use crate::system::status_notifier::{StatusNotifierHost, StatusNotifierWatcher};
+pub use crate::system::sway::Sway;
+pub use crate::system::throughput::ThroughputMonitor;
+pub use crate::system::upower::UPower;
+pub use crate::system::Services;
+pub use crate::throughput::create_throughput_indicator;
+pub use crate::tray::{create_tray, register_tray_host};
 pub use crate::utils::set_window_background;
-use dbus::blocking::Connection;
+pub use crate::workspaces::create_workspaces;
 use gio::prelude::*;
 use glib::MainContext;
 use glib::*;
 use gtk::prelude::*;
 use gtk_layer_shell_rs as gtk_layer_shell;
+use log::warn;
 use std::env::args;
 use std::rc::Rc;
 
-fn activate(application: &gtk::Application, dbus: Rc<Connection>) {
+fn activate(application: &gtk::Application, system_bus: Bus, session_bus: Bus) {
   let c = MainContext::default();
 
+  let config = Rc::new(Config::load());
+
   let audio = c.block_on(Audio::new());
   c.spawn_local_with_priority(PRIORITY_DEFAULT_IDLE, audio.clone().subscribe());
 
   let audio = Rc::new(audio);
 
+  let logind = Rc::new(c.block_on(Logind::new(system_bus.clone())));
+  if config.lock_on_suspend {
+    c.spawn_local(lock_before_sleep(logind.clone()));
+  }
+
+  let idle_inhibitor = Rc::new(IdleInhibitor::new(logind.clone(), &config.idle_inhibitor));
+  let upower = Rc::new(UPower::new(system_bus.clone()));
+  c.spawn_local(watch_battery_level(
+    config.clone(),
+    logind.clone(),
+    upower.clone(),
+    logind.subscribe_to_resume(),
+  ));
+
+  let charge_limit = ChargeLimit::find().map(Rc::new);
+  if let Some(ref charge_limit) = charge_limit {
+    c.spawn_local(restore_charge_profile(config.clone(), charge_limit.clone()));
+  }
+
+  let osd = Rc::new(Osd::new());
+  let display_backlight = BacklightDevice::find_display()
+    .map(|device| Rc::new(Backlight::new(logind.clone(), device)));
+  let keyboard_backlight = BacklightDevice::find_keyboard()
+    .map(|device| Rc::new(Backlight::new(logind.clone(), device)));
+  for backlight in display_backlight.iter().chain(keyboard_backlight.iter()) {
+    c.spawn_local(show_brightness_osd(osd.clone(), backlight.clone()));
+  }
+
   let window = gtk::ApplicationWindowBuilder::new()
     .application(application)
     .show_menubar(false)
@@ -52,12 +130,87 @@ fn activate(application: &gtk::Application, dbus: Rc<Connection>) {
   let center = gtk::Box::new(gtk::Orientation::Horizontal, 8);
   let right = gtk::Box::new(gtk::Orientation::Horizontal, 8);
 
-  let clock = create_clock();
+  if let Some(sway) = Sway::connect_from_env() {
+    let workspaces = create_workspaces(c.clone(), Rc::new(sway));
+    left.add(&workspaces);
+  }
+
+  let notification_center = if config.notifications.enabled {
+    match c.block_on(NotificationServer::start(session_bus.clone(), &config.notifications)) {
+      Ok(server) => {
+        let server = Rc::new(server);
+        let history = Rc::new(NotificationHistory::load(config.notifications.history_length));
+        let do_not_disturb = Rc::new(DoNotDisturb::new(server.clone(), &config.notifications));
+        c.spawn_local(record_notifications(history.clone(), server.clone()));
+        c.spawn_local(show_notification_popups(server));
+
+        Some(NotificationCenter {
+          history,
+          do_not_disturb,
+        })
+      }
+      Err(error) => {
+        warn!("Could not start the notification server: {}", error);
+        None
+      }
+    }
+  } else {
+    None
+  };
+
+  let clock = create_clock(c.clone(), logind.clone(), notification_center.clone());
   clock.set_hexpand(true);
 
   center.add(&clock);
 
-  let settings_button = create_settings_button(c, audio, dbus);
+  let mpris = Rc::new(Mpris::new(session_bus.clone()));
+  let media_indicator = create_media_indicator(c.clone(), mpris);
+  center.add(&media_indicator);
+
+  let scheduled_shutdown_indicator =
+    create_scheduled_shutdown_indicator(c.clone(), logind.clone());
+  right.add(&scheduled_shutdown_indicator);
+
+  let network = Rc::new(NetworkManager::new(system_bus.clone()));
+  c.spawn_local(register_secret_agent(network.clone()));
+  let rfkill = Rfkill::open(network.clone()).map(Rc::new);
+
+  if config.network_monitor.enabled {
+    let monitor = Rc::new(ThroughputMonitor::new(&config.network_monitor));
+    let throughput_indicator = create_throughput_indicator(c.clone(), monitor, network.clone());
+    right.add(&throughput_indicator);
+  }
+
+  if config.tray.enabled {
+    // Another panel may already be the watcher, its items are shown anyway
+    if let Err(error) = c.block_on(StatusNotifierWatcher::start(session_bus.clone())) {
+      warn!("Could not start the status notifier watcher: {}", error);
+    }
+
+    let host = Rc::new(StatusNotifierHost::new(session_bus));
+    c.spawn_local(register_tray_host(host.clone()));
+    let tray = create_tray(c.clone(), host);
+    right.add(&tray);
+  }
+
+  let bluetooth = Rc::new(Bluetooth::new(system_bus.clone()));
+  c.spawn_local(register_bluetooth_agent(bluetooth.clone()));
+
+  let services = Rc::new(Services {
+    audio,
+    logind,
+    network,
+    bluetooth,
+    idle_inhibitor,
+    upower,
+    power_profiles: Rc::new(PowerProfiles::new(system_bus)),
+    display_backlight,
+    keyboard_backlight,
+    charge_limit,
+    rfkill,
+  });
+
+  let settings_button = create_settings_button(c, config, services);
   right.add(&settings_button);
 
   panel.pack_start(&left, true, true, 8);
@@ -106,14 +259,57 @@ scale {
   margin: 24px;
   margin-bottom: 16px;
 }
+
+.panel_osd {
+  padding: 16px 24px;
+  border-radius: 10px;
+  background-color: rgba(62, 65, 60, 0.9);
+}
+.panel_osd levelbar {
+  min-width: 200px;
+}
+
+.notification {
+  padding: 12px;
+  border-radius: 10px;
+  background-color: rgba(62, 65, 60, 0.9);
+  min-width: 300px;
+}
+.notification.critical {
+  border: 1px solid rgba(255, 80, 80, 0.8);
+}
+
+.workspace {
+  padding: 0 8px;
+  border-radius: 0;
+  color: #FFFFFF;
+}
+.workspace.visible {
+  background-color: rgba(255, 255, 255, 0.1);
+}
+.workspace.focused {
+  background-color: rgba(255, 255, 255, 0.25);
+}
+.workspace.urgent {
+  background-color: rgba(255, 80, 80, 0.6);
+}
+
+.toast {
+  padding: 12px 24px;
+  border-radius: 10px;
+  background-color: rgba(62, 65, 60, 0.9);
+}
 ";
 
 fn main() {
+  env_logger::init();
+
   let application =
     gtk::Application::new(Some("com.subgraph.gtk-layer-example"), Default::default())
       .expect("Initialization failed...");
 
-  let dbus = Rc::new(Connection::new_system().unwrap());
+  let system_bus = Bus::new_system().expect("Failed to connect to the system bus");
+  let session_bus = Bus::new_session().expect("Failed to connect to the session bus");
 
   application.connect_activate(move |app| {
     let provider = gtk::CssProvider::new();
@@ -126,7 +322,7 @@ fn main() {
       gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
     );
 
-    activate(app, dbus.clone());
+    activate(app, system_bus.clone(), session_bus.clone());
   });
 
   application.run(&args().collect::<Vec<_>>());
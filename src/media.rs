@@ -0,0 +1,317 @@
+use crate::clone;
+use crate::popup::create_popup;
+use crate::system::mpris::{MediaState, Mpris, Player};
+use crate::toast::show_error_toast;
+use crate::utils::format_panel_text;
+use futures::prelude::*;
+use gdk_pixbuf::Pixbuf;
+use glib::MainContext;
+use glib::PRIORITY_DEFAULT_IDLE;
+use gtk::prelude::*;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+const ART_SIZE: i32 = 128;
+
+#[derive(Clone, Copy)]
+enum PlayerAction {
+  PlayPause,
+  Next,
+  Previous,
+}
+
+fn control_player(c: &MainContext, mpris: &Rc<Mpris>, player: Player, action: PlayerAction) {
+  c.spawn_local(clone!(mpris => async move {
+    let result = match action {
+      PlayerAction::PlayPause => mpris.play_pause(&player).await,
+      PlayerAction::Next => mpris.next(&player).await,
+      PlayerAction::Previous => mpris.previous(&player).await,
+    };
+
+    if let Err(error) = result {
+      show_error_toast(&format!("control {}", player.identity), &error);
+    }
+  }));
+}
+
+/// Formats microseconds as `m:ss`.
+fn format_duration(microseconds: i64) -> String {
+  let seconds = microseconds.max(0) / 1_000_000;
+
+  format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+fn format_track(player: &Player) -> String {
+  match player.title {
+    Some(ref title) if player.artists.is_empty() => title.clone(),
+    Some(ref title) => format!("{} – {}", title, player.artists.join(", ")),
+    None => player.identity.clone(),
+  }
+}
+
+fn playback_icon(player: &Player) -> &'static str {
+  if player.is_playing() {
+    "media-playback-pause-symbolic"
+  } else {
+    "media-playback-start-symbolic"
+  }
+}
+
+fn create_control_button(icon_name: &str, tooltip: &str) -> (gtk::Button, gtk::Image) {
+  let image = gtk::Image::new_from_icon_name(Some(icon_name), gtk::IconSize::Button);
+  let button = gtk::Button::new();
+  button.set_image(Some(&image));
+  button.set_relief(gtk::ReliefStyle::None);
+  button.set_tooltip_text(Some(tooltip));
+
+  (button, image)
+}
+
+fn update_position(scale: &gtk::Scale, label: &gtk::Label, player: &Player, position: i64) {
+  if let Some(length) = player.length {
+    scale.set_value(position as f64 / 1_000_000.0);
+    label.set_text(&format!(
+      "{} / {}",
+      format_duration(position),
+      format_duration(length)
+    ));
+  }
+}
+
+/// Popup with the controls, track and seek bar of the active player.
+fn create_media_menu(c: MainContext, mpris: Rc<Mpris>) -> gtk::Box {
+  let menu = gtk::Box::new(gtk::Orientation::Vertical, 8);
+  menu.set_margin_top(16);
+  menu.set_margin_bottom(16);
+  menu.set_margin_start(16);
+  menu.set_margin_end(16);
+
+  let current: Rc<RefCell<Option<Player>>> = Rc::new(RefCell::new(None));
+
+  let selector = gtk::ComboBoxText::new();
+  selector.set_no_show_all(true);
+  menu.add(&selector);
+  // Rebuilding the list should not count as the user picking a player
+  let updating_selector = Rc::new(Cell::new(false));
+  selector.connect_changed(clone!(mpris, updating_selector => move |selector| {
+    if !updating_selector.get() {
+      if let Some(name) = selector.get_active_id() {
+        mpris.select(&name);
+      }
+    }
+  }));
+
+  let art = gtk::Image::new();
+  art.set_no_show_all(true);
+  menu.add(&art);
+
+  let title = gtk::Label::new(None);
+  title.set_line_wrap(true);
+  title.set_max_width_chars(30);
+  title.set_justify(gtk::Justification::Center);
+  menu.add(&title);
+  let details = gtk::Label::new(None);
+  details.set_line_wrap(true);
+  details.set_max_width_chars(30);
+  details.set_justify(gtk::Justification::Center);
+  menu.add(&details);
+
+  let seek_row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+  seek_row.set_no_show_all(true);
+  let seek_bar = gtk::Scale::new_with_range(gtk::Orientation::Horizontal, 0.0, 1.0, 1.0);
+  seek_bar.set_draw_value(false);
+  seek_bar.set_hexpand(true);
+  seek_row.add(&seek_bar);
+  let position_label = gtk::Label::new(None);
+  seek_row.add(&position_label);
+  seek_bar.show();
+  position_label.show();
+  menu.add(&seek_row);
+
+  // Only user changes, setting the value while playing does not seek
+  seek_bar.connect_change_value(clone!(c, mpris, current => move |_, _, value| {
+    if let Some(player) = current.borrow().clone() {
+      let position = (value * 1_000_000.0) as i64;
+      c.spawn_local(clone!(mpris => async move {
+        if let Err(error) = mpris.set_position(&player, position).await {
+          show_error_toast(&format!("seek in {}", player.identity), &error);
+        }
+      }));
+    }
+
+    Inhibit(false)
+  }));
+
+  let controls = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+  controls.set_halign(gtk::Align::Center);
+  let (previous_button, _) = create_control_button("media-skip-backward-symbolic", "Previous");
+  let (play_button, play_image) = create_control_button("media-playback-start-symbolic", "Play");
+  let (next_button, _) = create_control_button("media-skip-forward-symbolic", "Next");
+  controls.add(&previous_button);
+  controls.add(&play_button);
+  controls.add(&next_button);
+  menu.add(&controls);
+
+  for (button, action) in vec![
+    (&previous_button, PlayerAction::Previous),
+    (&play_button, PlayerAction::PlayPause),
+    (&next_button, PlayerAction::Next),
+  ] {
+    button.connect_clicked(clone!(c, mpris, current => move |_| {
+      if let Some(player) = current.borrow().clone() {
+        control_player(&c, &mpris, player, action);
+      }
+    }));
+  }
+
+  let destroyed = Rc::new(Cell::new(false));
+  menu.connect_destroy(clone!(destroyed => move |_| {
+    destroyed.set(true);
+  }));
+
+  let refresh_position = Rc::new(clone!(c, mpris, current, seek_bar, position_label => move || {
+    if let Some(player) = current.borrow().clone() {
+      if player.length.is_some() {
+        c.spawn_local(clone!(mpris, seek_bar, position_label => async move {
+          if let Ok(position) = mpris.get_position(&player).await {
+            update_position(&seek_bar, &position_label, &player, position);
+          }
+        }));
+      }
+    }
+  }));
+
+  // Players do not signal the position as it moves
+  gtk::timeout_add_seconds(
+    1,
+    clone!(destroyed, current, refresh_position => move || {
+      if current.borrow().as_ref().map_or(false, Player::is_playing) {
+        refresh_position();
+      }
+      gtk::Continue(!destroyed.get())
+    }),
+  );
+
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    mpris
+      .subscribe()
+      .take_while(move |_| future::ready(!destroyed.get()))
+      .for_each(move |state: MediaState| {
+        updating_selector.set(true);
+        selector.remove_all();
+        for player in &state.players {
+          selector.append(Some(player.name.as_str()), &player.identity);
+        }
+        selector.set_active_id(state.active.as_ref().map(String::as_str));
+        selector.set_visible(state.players.len() > 1);
+        updating_selector.set(false);
+
+        let player = state.active_player().cloned();
+        match player {
+          Some(ref player) => {
+            title.set_markup(&format!(
+              "<b>{}</b>",
+              glib::markup_escape_text(player.title.as_ref().unwrap_or(&player.identity))
+            ));
+            let mut text = player.artists.join(", ");
+            if let Some(ref album) = player.album {
+              if !text.is_empty() {
+                text.push_str(" — ");
+              }
+              text.push_str(album);
+            }
+            details.set_text(&text);
+            details.set_visible(!text.is_empty());
+
+            let pixbuf = player
+              .art
+              .as_ref()
+              .and_then(|path| Pixbuf::new_from_file_at_size(path, ART_SIZE, ART_SIZE).ok());
+            art.set_from_pixbuf(pixbuf.as_ref());
+            art.set_visible(pixbuf.is_some());
+
+            if let Some(length) = player.length {
+              seek_bar.set_range(0.0, length as f64 / 1_000_000.0);
+            }
+            seek_row.set_visible(player.length.is_some());
+            seek_bar.set_sensitive(player.can_seek);
+
+            play_image.set_from_icon_name(Some(playback_icon(player)), gtk::IconSize::Button);
+            play_button.set_tooltip_text(Some(if player.is_playing() { "Pause" } else { "Play" }));
+            play_button.set_sensitive(player.can_play || player.can_pause);
+            previous_button.set_sensitive(player.can_go_previous);
+            next_button.set_sensitive(player.can_go_next);
+          }
+          None => {
+            title.set_text("Nothing is playing");
+            details.hide();
+            art.hide();
+            seek_row.hide();
+            play_button.set_sensitive(false);
+            previous_button.set_sensitive(false);
+            next_button.set_sensitive(false);
+          }
+        }
+        current.replace(player);
+        refresh_position();
+
+        future::ready(())
+      }),
+  );
+
+  menu
+}
+
+/// Panel label with the track of the active player, hidden when there are
+/// no players.
+pub fn create_media_indicator(c: MainContext, mpris: Rc<Mpris>) -> gtk::EventBox {
+  let content = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+  let icon = gtk::Image::new_from_icon_name(
+    Some("media-playback-start-symbolic"),
+    gtk::IconSize::SmallToolbar,
+  );
+  content.add(&icon);
+  let label = gtk::Label::new(None);
+  label.set_ellipsize(pango::EllipsizeMode::End);
+  label.set_max_width_chars(40);
+  content.add(&label);
+
+  let media_button = gtk::EventBox::new();
+  media_button.set_no_show_all(true);
+  media_button.add(&content);
+  content.show_all();
+
+  media_button.connect_button_press_event(clone!(c, mpris => move |media_button, _| {
+    let media_menu = create_media_menu(c.clone(), mpris.clone());
+    let show_popup = create_popup(media_button, &media_menu);
+
+    show_popup();
+    Inhibit(false)
+  }));
+
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    mpris
+      .subscribe()
+      .for_each(clone!(media_button => move |state| {
+        match state.active_player() {
+          Some(player) => {
+            let icon_name = if player.is_playing() {
+              "media-playback-start-symbolic"
+            } else {
+              "media-playback-pause-symbolic"
+            };
+            icon.set_from_icon_name(Some(icon_name), gtk::IconSize::SmallToolbar);
+            label.set_markup(&format_panel_text(glib::markup_escape_text(&format_track(player))));
+            media_button.show();
+          }
+          None => media_button.hide(),
+        }
+
+        future::ready(())
+      })),
+  );
+
+  media_button
+}
@@ -2,6 +2,8 @@ use crate::clone;
 use crate::utils::set_window_background;
 use gtk::prelude::*;
 use gtk_layer_shell_rs as gtk_layer_shell;
+use std::cell::Cell;
+use std::rc::Rc;
 
 pub fn create_modal<T>(content: &T) -> impl Fn() -> ()
 where
@@ -54,3 +56,142 @@ where
     window.show_all();
   }
 }
+
+/// Closes the modal that `content` is shown in.
+pub fn close_modal<T>(content: &T)
+where
+  T: gtk::IsA<gtk::Widget>,
+{
+  if let Some(window) = content
+    .get_toplevel()
+    .and_then(|toplevel| toplevel.downcast::<gtk::Window>().ok())
+  {
+    window.close();
+  }
+}
+
+/// Creates a modal asking the user to confirm an action.
+pub fn create_confirm_modal<F>(message: &str, confirm_label: &str, on_confirm: F) -> impl Fn() -> ()
+where
+  F: 'static,
+  F: Fn() -> (),
+{
+  let content = create_confirm_prompt(message, confirm_label, move |confirmed| {
+    if confirmed {
+      on_confirm();
+    }
+  });
+
+  create_modal(&content)
+}
+
+/// Creates the content of a modal asking the user a yes or no question.
+///
+/// `on_response` is called exactly once, with `false` if the modal is closed
+/// without confirming.
+pub fn create_confirm_prompt<F>(message: &str, confirm_label: &str, on_response: F) -> gtk::Box
+where
+  F: 'static,
+  F: Fn(bool) -> (),
+{
+  let content = gtk::Box::new(gtk::Orientation::Vertical, 16);
+
+  let label = gtk::Label::new(Some(message));
+  label.set_line_wrap(true);
+  content.add(&label);
+
+  let button_row = gtk::Box::new(gtk::Orientation::Horizontal, 12);
+  button_row.set_homogeneous(true);
+  let cancel_button = gtk::Button::new_with_label("Cancel");
+  cancel_button.get_style_context().add_class("modal_button");
+  let confirm_button = gtk::Button::new_with_label(confirm_label);
+  confirm_button.get_style_context().add_class("modal_button");
+  button_row.add(&cancel_button);
+  button_row.add(&confirm_button);
+  content.add(&button_row);
+
+  let responded = Cell::new(false);
+  let respond = Rc::new(move |confirmed: bool| {
+    if !responded.replace(true) {
+      on_response(confirmed);
+    }
+  });
+
+  cancel_button.connect_button_press_event(|cancel_button, _| {
+    close_modal(cancel_button);
+
+    Inhibit(false)
+  });
+
+  confirm_button.connect_button_press_event(clone!(respond => move |confirm_button, _| {
+    respond(true);
+    close_modal(confirm_button);
+
+    Inhibit(false)
+  }));
+
+  content.connect_destroy(move |_| respond(false));
+
+  content
+}
+
+/// Creates the content of a modal asking the user for some text, e.g. a
+/// password.
+///
+/// `on_response` is called exactly once, with `None` if the modal is closed
+/// without submitting.
+pub fn create_prompt<F>(message: &str, secret: bool, on_response: F) -> gtk::Box
+where
+  F: 'static,
+  F: Fn(Option<String>) -> (),
+{
+  let content = gtk::Box::new(gtk::Orientation::Vertical, 16);
+
+  let label = gtk::Label::new(Some(message));
+  label.set_line_wrap(true);
+  content.add(&label);
+
+  let entry = gtk::Entry::new();
+  entry.set_visibility(!secret);
+  content.add(&entry);
+
+  let button_row = gtk::Box::new(gtk::Orientation::Horizontal, 12);
+  button_row.set_homogeneous(true);
+  let cancel_button = gtk::Button::new_with_label("Cancel");
+  cancel_button.get_style_context().add_class("modal_button");
+  let submit_button = gtk::Button::new_with_label("OK");
+  submit_button.get_style_context().add_class("modal_button");
+  button_row.add(&cancel_button);
+  button_row.add(&submit_button);
+  content.add(&button_row);
+
+  let responded = Cell::new(false);
+  let respond = Rc::new(move |response: Option<String>| {
+    if !responded.replace(true) {
+      on_response(response);
+    }
+  });
+
+  let submit = Rc::new(clone!(entry, respond => move || {
+    respond(Some(entry.get_text().map(|text| text.to_string()).unwrap_or_default()));
+    close_modal(&entry);
+  }));
+
+  entry.connect_activate(clone!(submit => move |_| submit()));
+
+  submit_button.connect_button_press_event(move |_, _| {
+    submit();
+
+    Inhibit(false)
+  });
+
+  cancel_button.connect_button_press_event(|cancel_button, _| {
+    close_modal(cancel_button);
+
+    Inhibit(false)
+  });
+
+  content.connect_destroy(move |_| respond(None));
+
+  content
+}
@@ -0,0 +1,271 @@
+use crate::clone;
+use crate::modal::{close_modal, create_modal, create_prompt};
+use crate::system::network::{AccessPoint, NetworkManager, SecretRequest, VpnConnection};
+use crate::toast::show_error_toast;
+use futures::prelude::*;
+use glib::MainContext;
+use glib::PRIORITY_DEFAULT_IDLE;
+use gtk::prelude::*;
+use log::warn;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+/// Panel icon reflecting the primary network connection, with a lock badge
+/// while a VPN is up.
+pub fn create_network_icon(c: MainContext, network: Rc<NetworkManager>) -> gtk::Overlay {
+  let overlay = gtk::Overlay::new();
+  let icon =
+    gtk::Image::new_from_icon_name(Some("network-offline-symbolic"), gtk::IconSize::SmallToolbar);
+  overlay.add(&icon);
+
+  let vpn_badge =
+    gtk::Image::new_from_icon_name(Some("changes-prevent-symbolic"), gtk::IconSize::Menu);
+  vpn_badge.set_pixel_size(8);
+  vpn_badge.set_halign(gtk::Align::End);
+  vpn_badge.set_valign(gtk::Align::End);
+  vpn_badge.set_no_show_all(true);
+  overlay.add_overlay(&vpn_badge);
+
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    network.subscribe().for_each(move |state| {
+      icon.set_from_icon_name(Some(state.icon_name()), gtk::IconSize::SmallToolbar);
+      vpn_badge.set_visible(state.is_vpn_active());
+
+      let mut description = state.description();
+      if state.is_vpn_active() {
+        description.push_str(&format!("\nVPN: {}", state.active_vpns.join(", ")));
+      }
+      overlay.set_tooltip_text(Some(&description));
+
+      future::ready(())
+    }),
+  );
+
+  overlay
+}
+
+fn strength_icon_name(strength: u8) -> &'static str {
+  match strength {
+    0..=19 => "network-wireless-signal-none-symbolic",
+    20..=39 => "network-wireless-signal-weak-symbolic",
+    40..=59 => "network-wireless-signal-ok-symbolic",
+    60..=79 => "network-wireless-signal-good-symbolic",
+    _ => "network-wireless-signal-excellent-symbolic",
+  }
+}
+
+fn connect(c: &MainContext, network: Rc<NetworkManager>, access_point: AccessPoint) {
+  let needs_password =
+    access_point.known_connection.is_none() && access_point.security.needs_password();
+  let message = format!("Enter the password for \"{}\"", access_point.ssid);
+
+  let c = c.clone();
+  let spawn_connect = move |password: Option<String>| {
+    c.spawn_local(clone!(network, access_point => async move {
+      if let Err(error) = network.connect(&access_point, password).await {
+        show_error_toast(&format!("connect to {}", access_point.ssid), &error);
+      }
+    }));
+  };
+
+  if needs_password {
+    let content = create_prompt(&message, true, move |password| {
+      if let Some(password) = password {
+        spawn_connect(Some(password));
+      }
+    });
+    let show_modal = create_modal(&content);
+
+    show_modal();
+  } else {
+    spawn_connect(None);
+  }
+}
+
+fn create_access_point_row(
+  c: &MainContext,
+  network: &Rc<NetworkManager>,
+  access_point: &AccessPoint,
+) -> gtk::Button {
+  let button = gtk::Button::new();
+  button.set_relief(gtk::ReliefStyle::None);
+
+  let row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+  let strength_icon = gtk::Image::new_from_icon_name(
+    Some(strength_icon_name(access_point.strength)),
+    gtk::IconSize::Menu,
+  );
+  row.pack_start(&strength_icon, false, false, 0);
+
+  let label = gtk::Label::new(None);
+  if access_point.active {
+    label.set_markup(&format!("<b>{}</b>", glib::markup_escape_text(&access_point.ssid)));
+  } else {
+    label.set_text(&access_point.ssid);
+  }
+  label.set_xalign(0.0);
+  row.pack_start(&label, true, true, 0);
+
+  if access_point.security.is_secured() {
+    let lock_icon = gtk::Image::new_from_icon_name(
+      Some("network-wireless-encrypted-symbolic"),
+      gtk::IconSize::Menu,
+    );
+    lock_icon.set_tooltip_text(Some("Secured"));
+    row.pack_end(&lock_icon, false, false, 0);
+  }
+
+  button.add(&row);
+  button.set_tooltip_text(Some(&format!("Signal strength {}%", access_point.strength)));
+
+  if !access_point.active {
+    let access_point = access_point.clone();
+    button.connect_clicked(clone!(c, network => move |_| {
+      connect(&c, network.clone(), access_point.clone());
+    }));
+  }
+
+  button
+}
+
+fn update_access_point_list(
+  c: &MainContext,
+  network: &Rc<NetworkManager>,
+  list: &gtk::Box,
+  access_points: &[AccessPoint],
+) {
+  for child in list.get_children() {
+    list.remove(&child);
+  }
+
+  if access_points.is_empty() {
+    let label = gtk::Label::new(Some("No Wi-Fi networks found"));
+    list.add(&label);
+  }
+
+  for access_point in access_points {
+    list.add(&create_access_point_row(c, network, access_point));
+  }
+
+  list.show_all();
+}
+
+/// Submenu listing the visible Wi-Fi networks.
+pub fn create_wifi_menu(c: MainContext, network: Rc<NetworkManager>) -> gtk::Box {
+  let menu = gtk::Box::new(gtk::Orientation::Vertical, 4);
+
+  let back_button = gtk::ModelButton::new();
+  back_button.set_label("Wi-Fi Networks");
+  back_button.set_property_menu_name(Some("main"));
+  back_button.set_property_inverted(true);
+  back_button.set_property_centered(true);
+  menu.add(&back_button);
+
+  let list = gtk::Box::new(gtk::Orientation::Vertical, 2);
+  menu.add(&list);
+
+  // Scan when the submenu is opened so the list is fresh
+  menu.connect_map(clone!(c, network => move |_| {
+    c.spawn_local(clone!(network => async move {
+      if let Err(error) = network.request_scan().await {
+        warn!("Could not scan for Wi-Fi networks: {}", error);
+      }
+    }));
+  }));
+
+  let destroyed = Rc::new(Cell::new(false));
+  menu.connect_destroy(clone!(destroyed => move |_| {
+    destroyed.set(true);
+  }));
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    network
+      .subscribe_to_access_points()
+      .take_while(move |_| future::ready(!destroyed.get()))
+      .for_each(clone!(c, network => move |access_points| {
+        update_access_point_list(&c, &network, &list, &access_points);
+
+        future::ready(())
+      })),
+  );
+
+  menu
+}
+
+fn update_vpn_section(
+  c: &MainContext,
+  network: &Rc<NetworkManager>,
+  section: &gtk::Box,
+  vpn_connections: &[VpnConnection],
+) {
+  for child in section.get_children() {
+    section.remove(&child);
+  }
+
+  for vpn_connection in vpn_connections {
+    let button = gtk::ModelButton::new();
+    button.set_property_role(gtk::ButtonRole::Check);
+    button.set_label(&vpn_connection.name);
+    button.set_property_active(vpn_connection.is_active());
+    button.connect_clicked(clone!(c, network, vpn_connection => move |_| {
+      c.spawn_local(clone!(network, vpn_connection => async move {
+        if let Err(error) = network.toggle_vpn(&vpn_connection).await {
+          let verb = if vpn_connection.is_active() { "disconnect" } else { "connect" };
+          show_error_toast(&format!("{} {}", verb, vpn_connection.name), &error);
+        }
+      }));
+    }));
+    section.add(&button);
+    button.show();
+  }
+
+  section.set_visible(!vpn_connections.is_empty());
+}
+
+/// Toggles for the saved VPN and WireGuard connections, hidden when there
+/// are none.
+pub fn create_vpn_section(c: MainContext, network: Rc<NetworkManager>) -> gtk::Box {
+  let section = gtk::Box::new(gtk::Orientation::Vertical, 0);
+  section.set_no_show_all(true);
+
+  let destroyed = Rc::new(Cell::new(false));
+  section.connect_destroy(clone!(destroyed => move |_| {
+    destroyed.set(true);
+  }));
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    network
+      .subscribe_to_vpn_connections()
+      .take_while(move |_| future::ready(!destroyed.get()))
+      .for_each(clone!(c, network, section => move |vpn_connections| {
+        update_vpn_section(&c, &network, &section, &vpn_connections);
+
+        future::ready(())
+      })),
+  );
+
+  section
+}
+
+fn prompt_for_secret(request: SecretRequest) -> Box<dyn Fn()> {
+  let message = format!("Enter the password for \"{}\"", request.connection_name);
+  let request = RefCell::new(Some(request));
+  let content = create_prompt(&message, true, move |password| {
+    if let Some(request) = request.borrow_mut().take() {
+      request.reply(password);
+    }
+  });
+  let show_modal = create_modal(&content);
+
+  show_modal();
+
+  Box::new(move || close_modal(&content))
+}
+
+/// Makes the panel answer NetworkManager's password requests.
+pub async fn register_secret_agent(network: Rc<NetworkManager>) {
+  if let Err(error) = network.register_secret_agent(prompt_for_secret).await {
+    warn!("Could not register as a NetworkManager secret agent: {}", error);
+  }
+}
@@ -0,0 +1,310 @@
+use crate::clone;
+use crate::notifications::sanitize_markup;
+use crate::system::do_not_disturb::{DoNotDisturb, DoNotDisturbMode};
+use crate::system::notification_history::{HistoryEntry, NotificationHistory};
+use crate::utils::format_panel_text;
+use chrono::{Local, TimeZone};
+use futures::prelude::*;
+use glib::MainContext;
+use glib::PRIORITY_DEFAULT_IDLE;
+use gtk::prelude::*;
+use std::cell::Cell;
+use std::rc::Rc;
+
+/// The notification history and do not disturb, available when the panel
+/// is the notification daemon.
+#[derive(Clone)]
+pub struct NotificationCenter {
+  pub history: Rc<NotificationHistory>,
+  pub do_not_disturb: Rc<DoNotDisturb>,
+}
+
+fn format_entry_time(time: i64) -> String {
+  let time = Local.timestamp(time, 0);
+
+  if time.date() == Local::today() {
+    time.format("%H:%M").to_string()
+  } else {
+    time.format("%h %d %H:%M").to_string()
+  }
+}
+
+fn create_entry_row(history: &Rc<NotificationHistory>, entry: &HistoryEntry) -> gtk::Box {
+  let row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+
+  if let Some(ref icon) = entry.icon {
+    let image = if icon.starts_with('/') {
+      gtk::Image::new_from_file(icon)
+    } else {
+      gtk::Image::new_from_icon_name(Some(icon.as_str()), gtk::IconSize::Dnd)
+    };
+    image.set_pixel_size(32);
+    image.set_valign(gtk::Align::Start);
+    row.pack_start(&image, false, false, 0);
+  }
+
+  let text = gtk::Label::new(None);
+  let mut markup = format!(
+    "<b>{}</b>  <small>{}</small>",
+    glib::markup_escape_text(&entry.summary),
+    format_entry_time(entry.time)
+  );
+  if !entry.body.is_empty() {
+    markup.push('\n');
+    markup.push_str(&sanitize_markup(&entry.body));
+  }
+  text.set_markup(&markup);
+  text.set_xalign(0.0);
+  text.set_line_wrap(true);
+  text.set_max_width_chars(40);
+  row.pack_start(&text, true, true, 0);
+
+  let dismiss_button =
+    gtk::Button::new_from_icon_name(Some("window-close-symbolic"), gtk::IconSize::Menu);
+  dismiss_button.set_relief(gtk::ReliefStyle::None);
+  dismiss_button.set_valign(gtk::Align::Start);
+  dismiss_button.set_tooltip_text(Some("Dismiss"));
+  let key = entry.key;
+  dismiss_button.connect_clicked(clone!(history => move |_| {
+    history.dismiss(key);
+  }));
+  row.pack_end(&dismiss_button, false, false, 0);
+
+  row
+}
+
+/// Groups entries by application, with the application that notified most
+/// recently first.
+fn group_by_app(entries: &[HistoryEntry]) -> Vec<(&str, Vec<&HistoryEntry>)> {
+  let mut groups: Vec<(&str, Vec<&HistoryEntry>)> = vec![];
+
+  for entry in entries {
+    match groups.iter_mut().find(|(app_name, _)| *app_name == entry.app_name) {
+      Some((_, group)) => group.push(entry),
+      None => groups.push((entry.app_name.as_str(), vec![entry])),
+    }
+  }
+
+  groups
+}
+
+fn update_history_list(
+  history: &Rc<NotificationHistory>,
+  list: &gtk::Box,
+  entries: &[HistoryEntry],
+) {
+  for child in list.get_children() {
+    list.remove(&child);
+  }
+
+  if entries.is_empty() {
+    let label = gtk::Label::new(Some("No notifications"));
+    label.set_vexpand(true);
+    list.add(&label);
+  }
+
+  for (app_name, group) in group_by_app(entries) {
+    let header = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    let app_label = gtk::Label::new(None);
+    app_label.set_markup(&format!(
+      "<b>{}</b> ({})",
+      glib::markup_escape_text(if app_name.is_empty() { "Unknown" } else { app_name }),
+      group.len()
+    ));
+    app_label.set_xalign(0.0);
+    header.pack_start(&app_label, true, true, 0);
+
+    let clear_button = gtk::Button::new_with_label("Clear");
+    clear_button.set_relief(gtk::ReliefStyle::None);
+    let app_name = app_name.to_string();
+    clear_button.connect_clicked(clone!(history => move |_| {
+      history.dismiss_app(&app_name);
+    }));
+    header.pack_end(&clear_button, false, false, 0);
+    list.add(&header);
+
+    for entry in group {
+      list.add(&create_entry_row(history, entry));
+    }
+  }
+
+  list.show_all();
+}
+
+fn do_not_disturb_label(do_not_disturb: &DoNotDisturb) -> String {
+  match do_not_disturb.mode() {
+    DoNotDisturbMode::Until(until) => format!("Do Not Disturb (until {})", until.format("%H:%M")),
+    _ => "Do Not Disturb".to_string(),
+  }
+}
+
+fn create_do_not_disturb_section(c: &MainContext, do_not_disturb: &Rc<DoNotDisturb>) -> gtk::Box {
+  let section = gtk::Box::new(gtk::Orientation::Vertical, 0);
+
+  let toggle_button = gtk::ModelButton::new();
+  toggle_button.set_property_role(gtk::ButtonRole::Check);
+  section.add(&toggle_button);
+  toggle_button.connect_clicked(clone!(do_not_disturb => move |_| {
+    if do_not_disturb.is_active() {
+      do_not_disturb.turn_off();
+    } else {
+      do_not_disturb.set_mode(DoNotDisturbMode::On);
+    }
+  }));
+
+  let until_tomorrow_button = gtk::ModelButton::new();
+  until_tomorrow_button.set_label("Until Tomorrow");
+  section.add(&until_tomorrow_button);
+  until_tomorrow_button.connect_clicked(clone!(do_not_disturb => move |_| {
+    do_not_disturb.enable_until_tomorrow();
+  }));
+
+  let schedule_button = gtk::ModelButton::new();
+  schedule_button.set_property_role(gtk::ButtonRole::Check);
+  schedule_button.set_no_show_all(true);
+  if let Some((start, end)) = do_not_disturb.schedule() {
+    schedule_button.set_label(&format!(
+      "Every Day {}–{}",
+      start.format("%H:%M"),
+      end.format("%H:%M")
+    ));
+    schedule_button.show();
+  }
+  section.add(&schedule_button);
+  schedule_button.connect_clicked(clone!(do_not_disturb => move |_| {
+    do_not_disturb.set_schedule_enabled(!do_not_disturb.is_schedule_enabled());
+  }));
+
+  let destroyed = Rc::new(Cell::new(false));
+  section.connect_destroy(clone!(destroyed => move |_| {
+    destroyed.set(true);
+  }));
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    do_not_disturb
+      .subscribe()
+      .take_while(move |_| future::ready(!destroyed.get()))
+      .for_each(clone!(do_not_disturb => move |active| {
+        toggle_button.set_label(&do_not_disturb_label(&do_not_disturb));
+        toggle_button.set_property_active(active);
+        until_tomorrow_button.set_visible(!active);
+        schedule_button.set_property_active(do_not_disturb.is_schedule_enabled());
+
+        future::ready(())
+      })),
+  );
+
+  section
+}
+
+/// The notification history with do not disturb controls, shown next to
+/// the calendar.
+pub fn create_notification_history(c: MainContext, center: &NotificationCenter) -> gtk::Box {
+  let history = center.history.clone();
+  let panel = gtk::Box::new(gtk::Orientation::Vertical, 8);
+  panel.set_margin_top(16);
+  panel.set_margin_bottom(16);
+  panel.set_margin_end(16);
+
+  let header = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+  let title = gtk::Label::new(None);
+  title.set_markup("<b>Notifications</b>");
+  title.set_xalign(0.0);
+  header.pack_start(&title, true, true, 0);
+  let clear_all_button = gtk::Button::new_with_label("Clear All");
+  clear_all_button.connect_clicked(clone!(history => move |_| {
+    history.clear();
+  }));
+  header.pack_end(&clear_all_button, false, false, 0);
+  panel.add(&header);
+
+  let scrolled_window =
+    gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+  scrolled_window.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+  scrolled_window.set_min_content_height(300);
+  scrolled_window.set_min_content_width(350);
+  let list = gtk::Box::new(gtk::Orientation::Vertical, 8);
+  scrolled_window.add(&list);
+  panel.pack_start(&scrolled_window, true, true, 0);
+
+  let separator = gtk::Separator::new(gtk::Orientation::Horizontal);
+  panel.add(&separator);
+  panel.add(&create_do_not_disturb_section(&c, &center.do_not_disturb));
+
+  // Seeing the history counts as reading it
+  panel.connect_map(clone!(history => move |_| {
+    history.mark_all_read();
+  }));
+
+  let destroyed = Rc::new(Cell::new(false));
+  panel.connect_destroy(clone!(destroyed => move |_| {
+    destroyed.set(true);
+  }));
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    history
+      .subscribe()
+      .take_while(move |_| future::ready(!destroyed.get()))
+      .for_each(clone!(history => move |entries| {
+        update_history_list(&history, &list, &entries);
+        clear_all_button.set_sensitive(!entries.is_empty());
+
+        future::ready(())
+      })),
+  );
+
+  panel
+}
+
+/// Panel badge with the number of unread notifications, which also shows
+/// when do not disturb is on.
+pub fn create_notification_badge(c: MainContext, center: &NotificationCenter) -> gtk::Box {
+  let badge = gtk::Box::new(gtk::Orientation::Horizontal, 2);
+  badge.set_no_show_all(true);
+  let icon = gtk::Image::new_from_icon_name(
+    Some("preferences-system-notifications-symbolic"),
+    gtk::IconSize::SmallToolbar,
+  );
+  badge.add(&icon);
+  let count = gtk::Label::new(None);
+  badge.add(&count);
+
+  let update = Rc::new(clone!(badge, icon, count, center => move || {
+    let unread = center.history.unread_count();
+    let do_not_disturb = center.do_not_disturb.is_active();
+
+    let icon_name = if do_not_disturb {
+      "notifications-disabled-symbolic"
+    } else {
+      "preferences-system-notifications-symbolic"
+    };
+    icon.set_from_icon_name(Some(icon_name), gtk::IconSize::SmallToolbar);
+    icon.set_tooltip_text(if do_not_disturb { Some("Do not disturb") } else { None });
+    count.set_markup(&format_panel_text(unread));
+    count.set_visible(unread > 0);
+    icon.show();
+    badge.set_visible(unread > 0 || do_not_disturb);
+  }));
+
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    center
+      .history
+      .subscribe()
+      .for_each(clone!(update => move |_| {
+        update();
+
+        future::ready(())
+      })),
+  );
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    center.do_not_disturb.subscribe().for_each(move |_| {
+      update();
+
+      future::ready(())
+    }),
+  );
+
+  badge
+}
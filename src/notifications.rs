@@ -0,0 +1,227 @@
+use crate::clone;
+use crate::system::notifications::{
+  CloseReason, Notification, NotificationEvent, NotificationImage, NotificationServer, Urgency,
+};
+use crate::utils::set_window_background;
+use futures::prelude::*;
+use gdk_pixbuf::{Colorspace, InterpType, Pixbuf};
+use gtk::prelude::*;
+use gtk_layer_shell_rs as gtk_layer_shell;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+const IMAGE_SIZE: i32 = 48;
+const ALLOWED_TAGS: [&str; 3] = ["b", "i", "u"];
+
+/// Keeps the body markup that both the spec and Pango allow, dropping other
+/// tags such as links and images.
+pub fn sanitize_markup(body: &str) -> String {
+  let mut markup = String::new();
+  let mut rest = body;
+
+  while let Some(start) = rest.find('<') {
+    markup.push_str(&rest[..start]);
+    let end = match rest[start..].find('>') {
+      Some(end) => start + end,
+      None => {
+        markup.push_str(&glib::markup_escape_text(&rest[start..]));
+        rest = "";
+        break;
+      }
+    };
+
+    let tag = &rest[start + 1..end];
+    if ALLOWED_TAGS.contains(&tag.trim_start_matches('/')) {
+      markup.push_str(&rest[start..=end]);
+    }
+    rest = &rest[end + 1..];
+  }
+  markup.push_str(rest);
+
+  if pango::parse_markup(&markup, '\0').is_ok() {
+    markup
+  } else {
+    glib::markup_escape_text(body).to_string()
+  }
+}
+
+pub fn create_notification_image(image: &NotificationImage) -> Option<gtk::Image> {
+  let pixbuf = match image {
+    NotificationImage::IconName(icon_name) => {
+      let image = gtk::Image::new_from_icon_name(Some(icon_name), gtk::IconSize::Dialog);
+      image.set_pixel_size(IMAGE_SIZE);
+      return Some(image);
+    }
+    NotificationImage::Path(path) => {
+      Pixbuf::new_from_file_at_size(path, IMAGE_SIZE, IMAGE_SIZE).ok()?
+    }
+    NotificationImage::Data(data) => {
+      let size = data.byte_size()?;
+      let pixbuf = Pixbuf::new_from_bytes(
+        &glib::Bytes::from(data.data.get(..size)?),
+        Colorspace::Rgb,
+        data.has_alpha,
+        data.bits_per_sample,
+        data.width,
+        data.height,
+        data.rowstride,
+      );
+      let scale = f64::from(IMAGE_SIZE) / f64::from(data.width.max(data.height));
+      pixbuf.scale_simple(
+        (f64::from(data.width) * scale).round() as i32,
+        (f64::from(data.height) * scale).round() as i32,
+        InterpType::Bilinear,
+      )?
+    }
+  };
+
+  Some(gtk::Image::new_from_pixbuf(Some(&pixbuf)))
+}
+
+fn create_notification_card(
+  server: &Rc<NotificationServer>,
+  notification: &Notification,
+) -> gtk::EventBox {
+  let id = notification.id;
+  let card = gtk::EventBox::new();
+  card.get_style_context().add_class("notification");
+  if notification.urgency == Urgency::Critical {
+    card.get_style_context().add_class("critical");
+  }
+
+  let row = gtk::Box::new(gtk::Orientation::Horizontal, 12);
+  if let Some(image) = notification.image.as_ref().and_then(create_notification_image) {
+    image.set_valign(gtk::Align::Start);
+    row.pack_start(&image, false, false, 0);
+  }
+
+  let text = gtk::Box::new(gtk::Orientation::Vertical, 4);
+  row.pack_start(&text, true, true, 0);
+
+  let header = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+  let app_name = gtk::Label::new(None);
+  app_name.set_markup(&format!(
+    "<small>{}</small>",
+    glib::markup_escape_text(&notification.app_name)
+  ));
+  app_name.set_xalign(0.0);
+  header.pack_start(&app_name, true, true, 0);
+  let close_button =
+    gtk::Button::new_from_icon_name(Some("window-close-symbolic"), gtk::IconSize::Menu);
+  close_button.set_relief(gtk::ReliefStyle::None);
+  close_button.connect_clicked(clone!(server => move |_| {
+    server.close(id, CloseReason::Dismissed);
+  }));
+  header.pack_end(&close_button, false, false, 0);
+  text.add(&header);
+
+  let summary = gtk::Label::new(None);
+  summary.set_markup(&format!(
+    "<b>{}</b>",
+    glib::markup_escape_text(&notification.summary)
+  ));
+  summary.set_xalign(0.0);
+  summary.set_line_wrap(true);
+  summary.set_max_width_chars(40);
+  text.add(&summary);
+
+  if !notification.body.is_empty() {
+    let body = gtk::Label::new(None);
+    body.set_markup(&sanitize_markup(&notification.body));
+    body.set_xalign(0.0);
+    body.set_line_wrap(true);
+    body.set_max_width_chars(40);
+    text.add(&body);
+  }
+
+  let actions = notification
+    .actions
+    .iter()
+    .filter(|(key, _)| key != "default")
+    .collect::<Vec<_>>();
+  if !actions.is_empty() {
+    let action_row = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+    action_row.set_homogeneous(true);
+    for (key, label) in actions {
+      let button = gtk::Button::new_with_label(label);
+      let key = key.clone();
+      button.connect_clicked(clone!(server => move |_| {
+        server.invoke_action(id, &key);
+      }));
+      action_row.add(&button);
+    }
+    text.add(&action_row);
+  }
+
+  card.add(&row);
+
+  let has_default_action = notification.has_default_action();
+  card.connect_button_press_event(clone!(server => move |_, _| {
+    if has_default_action {
+      server.invoke_action(id, "default");
+    } else {
+      server.close(id, CloseReason::Dismissed);
+    }
+
+    Inhibit(false)
+  }));
+
+  card
+}
+
+/// Shows notifications as a stack of popups in the top right corner until
+/// they are closed.
+pub async fn show_notification_popups(server: Rc<NotificationServer>) {
+  let window = gtk::Window::new(gtk::WindowType::Toplevel);
+
+  set_window_background(&window, 0.0, 0.0, 0.0, 0.0);
+
+  gtk_layer_shell::init_for_window(&window);
+  gtk_layer_shell::set_layer(&window, gtk_layer_shell::Layer::Overlay);
+  gtk_layer_shell::set_anchor(&window, gtk_layer_shell::Edge::Top, true);
+  gtk_layer_shell::set_anchor(&window, gtk_layer_shell::Edge::Right, true);
+  gtk_layer_shell::set_margin(&window, gtk_layer_shell::Edge::Top, 8);
+  gtk_layer_shell::set_margin(&window, gtk_layer_shell::Edge::Right, 8);
+
+  let stack = gtk::Box::new(gtk::Orientation::Vertical, 8);
+  window.add(&stack);
+
+  let cards: Rc<RefCell<HashMap<u32, gtk::EventBox>>> = Rc::default();
+
+  server
+    .subscribe()
+    .for_each(move |event| {
+      match event {
+        NotificationEvent::Show(notification) => {
+          let card = create_notification_card(&server, &notification);
+          stack.add(&card);
+          // Replacements keep their place, new notifications go on top
+          let position = match cards.borrow_mut().insert(notification.id, card.clone()) {
+            Some(previous) => {
+              let position = stack.child_get_property(&previous, "position");
+              stack.remove(&previous);
+              position.get::<i32>().unwrap_or(0)
+            }
+            None => 0,
+          };
+          stack.reorder_child(&card, position);
+          window.show_all();
+        }
+        NotificationEvent::Received(_) => {}
+        NotificationEvent::Close(id, _) => {
+          if let Some(card) = cards.borrow_mut().remove(&id) {
+            stack.remove(&card);
+          }
+          if cards.borrow().is_empty() {
+            window.hide();
+          }
+          // Shrink the window to the remaining notifications
+          window.resize(1, 1);
+        }
+      }
+
+      future::ready(())
+    })
+    .await;
+}
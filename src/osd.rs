@@ -0,0 +1,65 @@
+use crate::utils::set_window_background;
+use gtk::prelude::*;
+use gtk_layer_shell_rs as gtk_layer_shell;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const OSD_TIMEOUT_SECONDS: u32 = 2;
+
+/// On-screen display for showing levels such as the brightness.
+pub struct Osd {
+  window: gtk::Window,
+  icon: gtk::Image,
+  level: gtk::LevelBar,
+  hide_timeout: Rc<RefCell<Option<glib::SourceId>>>,
+}
+
+impl Osd {
+  pub fn new() -> Osd {
+    let window = gtk::Window::new(gtk::WindowType::Toplevel);
+
+    set_window_background(&window, 0.0, 0.0, 0.0, 0.0);
+
+    gtk_layer_shell::init_for_window(&window);
+    gtk_layer_shell::set_layer(&window, gtk_layer_shell::Layer::Overlay);
+    gtk_layer_shell::set_anchor(&window, gtk_layer_shell::Edge::Bottom, true);
+    gtk_layer_shell::set_margin(&window, gtk_layer_shell::Edge::Bottom, 96);
+
+    let content = gtk::Box::new(gtk::Orientation::Horizontal, 16);
+    content.get_style_context().add_class("panel_osd");
+    let icon = gtk::Image::new();
+    let level = gtk::LevelBar::new_for_interval(0.0, 1.0);
+    level.set_valign(gtk::Align::Center);
+    content.pack_start(&icon, false, false, 0);
+    content.pack_start(&level, true, true, 0);
+    window.add(&content);
+
+    Osd {
+      window,
+      icon,
+      level,
+      hide_timeout: Rc::new(RefCell::new(None)),
+    }
+  }
+
+  pub fn show(&self, icon: &str, value: f64) {
+    self
+      .icon
+      .set_from_icon_name(Some(icon), gtk::IconSize::Dialog);
+    self.level.set_value(value);
+    self.window.show_all();
+
+    if let Some(hide_timeout) = self.hide_timeout.borrow_mut().take() {
+      glib::source_remove(hide_timeout);
+    }
+
+    let window = self.window.clone();
+    let hide_timeout = self.hide_timeout.clone();
+    let source = gtk::timeout_add_seconds(OSD_TIMEOUT_SECONDS, move || {
+      hide_timeout.borrow_mut().take();
+      window.hide();
+      gtk::Continue(false)
+    });
+    *self.hide_timeout.borrow_mut() = Some(source);
+  }
+}
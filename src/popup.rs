@@ -7,6 +7,21 @@ pub fn create_popup<T, U>(relative_to: &T, content: &U) -> impl Fn() -> ()
 where
   T: gtk::IsA<gtk::Widget>,
   U: gtk::IsA<gtk::Widget>,
+{
+  create_popup_with_submenus::<T, U, &str>(relative_to, content, vec![])
+}
+
+/// Like `create_popup` but with named submenus that can be opened from the
+/// content by a `gtk::ModelButton` with a matching `menu-name`.
+pub fn create_popup_with_submenus<T, U, N>(
+  relative_to: &T,
+  content: &U,
+  submenus: Vec<(N, gtk::Widget)>,
+) -> impl Fn() -> ()
+where
+  T: gtk::IsA<gtk::Widget>,
+  U: gtk::IsA<gtk::Widget>,
+  N: ToString,
 {
   let window = gtk::Window::new(gtk::WindowType::Toplevel);
 
@@ -30,6 +45,10 @@ where
   let popover = gtk::PopoverMenu::new();
   popover.set_relative_to(Some(&top_left));
   popover.add(content);
+  for (name, submenu) in submenus {
+    popover.add(&submenu);
+    popover.child_set_property(&submenu, "submenu", &name.to_string());
+  }
 
   window.connect_property_has_toplevel_focus_notify(clone!(popover => move |window| {
     if !window.get_property_has_toplevel_focus() {
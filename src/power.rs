@@ -0,0 +1,667 @@
+use crate::clone;
+use crate::config::{Config, PowerConfig};
+use crate::modal::close_modal;
+use crate::system::logind::{
+  Capability, Inhibitor, Logind, PowerAction, ScheduledShutdown, ShutdownKind,
+};
+use crate::toast::{show_error_toast, show_toast};
+use crate::utils::{format_panel_text, spawn_command};
+use chrono::{Local, TimeZone, Timelike};
+use futures::future::join_all;
+use futures::prelude::*;
+use glib::MainContext;
+use glib::PRIORITY_DEFAULT_IDLE;
+use gtk::prelude::*;
+use log::{error, warn};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+
+fn label(action: PowerAction) -> &'static str {
+  match action {
+    PowerAction::LogOut => "Log Out",
+    PowerAction::Suspend => "Suspend",
+    PowerAction::Hibernate => "Hibernate",
+    PowerAction::HybridSleep => "Hybrid Sleep",
+    PowerAction::SuspendThenHibernate => "Suspend then Hibernate",
+    PowerAction::Reboot => "Restart",
+    PowerAction::PowerOff => "Shutdown",
+  }
+}
+
+fn icon(action: PowerAction) -> &'static str {
+  match action {
+    PowerAction::LogOut => "system-log-out",
+    PowerAction::Suspend => "system-suspend",
+    PowerAction::Hibernate => "system-hibernate",
+    PowerAction::HybridSleep => "system-suspend-hibernate",
+    PowerAction::SuspendThenHibernate => "system-suspend-hibernate",
+    PowerAction::Reboot => "system-restart",
+    PowerAction::PowerOff => "system-shutdown",
+  }
+}
+
+/// Used in error messages, e.g. "Not authorized to shut down".
+fn verb(action: PowerAction) -> &'static str {
+  match action {
+    PowerAction::LogOut => "log out",
+    PowerAction::Suspend => "suspend",
+    PowerAction::Hibernate => "hibernate",
+    PowerAction::HybridSleep => "hybrid sleep",
+    PowerAction::SuspendThenHibernate => "suspend then hibernate",
+    PowerAction::Reboot => "restart",
+    PowerAction::PowerOff => "shut down",
+  }
+}
+
+fn create_power_modal_button<F>(
+  label: &str,
+  icon: &str,
+  needs_authentication: bool,
+  on_click: F,
+) -> gtk::Button
+where
+  F: 'static,
+  F: Fn() -> (),
+{
+  let button_icon = gtk::Image::new_from_icon_name(Some(icon), gtk::IconSize::Dialog);
+  let button_label = gtk::Label::new(Some(label));
+
+  let label_row = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+  label_row.set_halign(gtk::Align::Center);
+  if needs_authentication {
+    let lock_icon =
+      gtk::Image::new_from_icon_name(Some("changes-prevent-symbolic"), gtk::IconSize::Menu);
+    label_row.add(&lock_icon);
+  }
+  label_row.add(&button_label);
+
+  let button_content = gtk::Box::new(gtk::Orientation::Vertical, 0);
+  button_content.add(&button_icon);
+  button_content.add(&label_row);
+
+  let button = gtk::Button::new();
+  button.add(&button_content);
+  button.get_style_context().add_class("modal_button");
+  if needs_authentication {
+    button.set_tooltip_text(Some("Requires authentication"));
+  }
+
+  button.connect_button_press_event(move |_, _| {
+    on_click();
+
+    Inhibit(false)
+  });
+
+  button
+}
+
+async fn capability(
+  config: &PowerConfig,
+  logind: &Logind,
+  action: PowerAction,
+) -> Result<Capability, dbus::Error> {
+  if config.commands.contains_key(&action) {
+    Ok(Capability::Yes)
+  } else {
+    logind.can(action).await
+  }
+}
+
+async fn available_actions(
+  config: &PowerConfig,
+  logind: &Logind,
+) -> Vec<(PowerAction, Capability)> {
+  let capabilities = join_all(
+    config
+      .actions
+      .iter()
+      .map(|action| capability(config, logind, *action)),
+  )
+  .await;
+
+  config
+    .actions
+    .iter()
+    .zip(capabilities)
+    .filter_map(|(action, capability)| match capability {
+      Ok(capability) if capability.is_available() => Some((*action, capability)),
+      Ok(_) => None,
+      Err(error) => {
+        warn!("Could not check if {:?} is available: {}", action, error);
+        None
+      }
+    })
+    .collect()
+}
+
+/// Runs the configured command for `action`, or asks logind, returning
+/// whether it succeeded.
+async fn run_action(command: Option<String>, logind: Rc<Logind>, action: PowerAction) -> bool {
+  match command {
+    Some(command) => match spawn_command(&command) {
+      Ok(()) => true,
+      Err(error) => {
+        error!("Failed to run \"{}\": {}", command, error);
+        show_toast(&format!("Failed to {}: {}", verb(action), error));
+        false
+      }
+    },
+    None => match logind.perform(action).await {
+      Ok(()) => true,
+      Err(error) => {
+        show_error_toast(verb(action), &error);
+        false
+      }
+    },
+  }
+}
+
+fn perform(c: &MainContext, config: &PowerConfig, logind: Rc<Logind>, action: PowerAction) {
+  let command = config.commands.get(&action).cloned();
+
+  c.spawn_local(run_action(command, logind, action).map(|_| ()));
+}
+
+fn is_destructive(action: PowerAction) -> bool {
+  match action {
+    PowerAction::LogOut | PowerAction::Reboot | PowerAction::PowerOff => true,
+    _ => false,
+  }
+}
+
+fn countdown_text(action: PowerAction, seconds: u32) -> String {
+  let progressive = match action {
+    PowerAction::LogOut => "Logging out",
+    PowerAction::Reboot => "Restarting",
+    PowerAction::PowerOff => "Shutting down",
+    PowerAction::Hibernate => "Hibernating",
+    _ => "Suspending",
+  };
+
+  format!("{} in {}s", progressive, seconds)
+}
+
+fn create_inhibitor_row(inhibitor: &Inhibitor) -> gtk::Box {
+  let row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+  let icon = gtk::Image::new_from_icon_name(Some("dialog-warning-symbolic"), gtk::IconSize::Menu);
+  let label = gtk::Label::new(None);
+  label.set_markup(&format!(
+    "<b>{}</b> {}: {}",
+    glib::markup_escape_text(&inhibitor.who),
+    if inhibitor.mode == "delay" {
+      "is delaying"
+    } else {
+      "is blocking"
+    },
+    glib::markup_escape_text(&inhibitor.why),
+  ));
+  label.set_line_wrap(true);
+  label.set_xalign(0.0);
+  row.pack_start(&icon, false, false, 0);
+  row.pack_start(&label, true, true, 0);
+
+  row
+}
+
+fn show_confirmation<F>(
+  c: &MainContext,
+  stack: &gtk::Stack,
+  config: &Config,
+  logind: Rc<Logind>,
+  action: PowerAction,
+  on_confirm: F,
+) where
+  F: 'static,
+  F: Fn() -> (),
+{
+  let confirmation = gtk::Box::new(gtk::Orientation::Vertical, 16);
+
+  let remaining = Rc::new(Cell::new(config.power.confirm_seconds));
+  let countdown = gtk::Label::new(Some(&countdown_text(action, remaining.get())));
+  confirmation.add(&countdown);
+
+  let inhibitor_list = gtk::Box::new(gtk::Orientation::Vertical, 4);
+  confirmation.add(&inhibitor_list);
+
+  let button_row = gtk::Box::new(gtk::Orientation::Horizontal, 12);
+  button_row.set_homogeneous(true);
+  let cancel_button = gtk::Button::new_with_label("Cancel");
+  cancel_button.get_style_context().add_class("modal_button");
+  let confirm_button = gtk::Button::new_with_label(&format!("{} Now", label(action)));
+  confirm_button.get_style_context().add_class("modal_button");
+  button_row.add(&cancel_button);
+  button_row.add(&confirm_button);
+  confirmation.add(&button_row);
+
+  let previous_page = stack.get_visible_child_name();
+  stack.add_named(&confirmation, "confirmation");
+  confirmation.show_all();
+  stack.set_visible_child(&confirmation);
+
+  let done = Rc::new(Cell::new(false));
+  let act = Rc::new(clone!(confirmation, done => move || {
+    if !done.replace(true) {
+      on_confirm();
+      close_modal(&confirmation);
+    }
+  }));
+
+  cancel_button.connect_button_press_event(clone!(stack, confirmation, done => move |_, _| {
+    done.set(true);
+    if let Some(ref previous_page) = previous_page {
+      stack.set_visible_child_name(previous_page);
+    }
+    stack.remove(&confirmation);
+
+    Inhibit(false)
+  }));
+
+  confirm_button.connect_button_press_event(clone!(act => move |_, _| {
+    act();
+
+    Inhibit(false)
+  }));
+
+  // Closing the modal cancels the countdown
+  confirmation.connect_unmap(clone!(done => move |_| {
+    done.set(true);
+  }));
+
+  gtk::timeout_add_seconds(1, clone!(countdown, remaining, done, act => move || {
+    if done.get() {
+      return gtk::Continue(false);
+    }
+
+    let seconds = remaining.get().saturating_sub(1);
+    remaining.set(seconds);
+    if seconds == 0 {
+      act();
+      gtk::Continue(false)
+    } else {
+      countdown.set_text(&countdown_text(action, seconds));
+      gtk::Continue(true)
+    }
+  }));
+
+  if action == PowerAction::Reboot || action == PowerAction::PowerOff {
+    c.spawn_local(async move {
+      match logind.list_inhibitors().await {
+        Ok(inhibitors) => {
+          for inhibitor in inhibitors.iter().filter(|inhibitor| inhibitor.inhibits("shutdown")) {
+            inhibitor_list.add(&create_inhibitor_row(inhibitor));
+          }
+          inhibitor_list.show_all();
+        }
+        Err(error) => warn!("Could not list inhibitors: {}", error),
+      }
+    });
+  }
+}
+
+#[derive(Clone, Debug)]
+enum RebootTarget {
+  FirmwareSetup,
+  BootLoaderEntry(String),
+}
+
+fn target_label(target: &RebootTarget) -> String {
+  match target {
+    RebootTarget::FirmwareSetup => "Firmware Setup".to_string(),
+    RebootTarget::BootLoaderEntry(entry) => entry.trim_end_matches(".conf").to_string(),
+  }
+}
+
+async fn reboot_targets(logind: &Logind) -> Vec<RebootTarget> {
+  let mut targets = vec![];
+
+  match logind.can_reboot_to_firmware_setup().await {
+    Ok(capability) if capability.is_available() => targets.push(RebootTarget::FirmwareSetup),
+    Ok(_) => {}
+    Err(error) => warn!("Could not check for firmware setup support: {}", error),
+  }
+
+  match logind.can_reboot_to_boot_loader_entry().await {
+    Ok(capability) if capability.is_available() => match logind.boot_loader_entries().await {
+      Ok(entries) => targets.extend(entries.into_iter().map(RebootTarget::BootLoaderEntry)),
+      Err(error) => warn!("Could not list boot loader entries: {}", error),
+    },
+    Ok(_) => {}
+    Err(error) => warn!("Could not check for boot loader entry support: {}", error),
+  }
+
+  targets
+}
+
+fn reboot_into(c: &MainContext, config: &PowerConfig, logind: Rc<Logind>, target: RebootTarget) {
+  let command = config.commands.get(&PowerAction::Reboot).cloned();
+
+  c.spawn_local(async move {
+    let prepared = match target {
+      RebootTarget::FirmwareSetup => logind.set_reboot_to_firmware_setup(true).await,
+      RebootTarget::BootLoaderEntry(ref entry) => {
+        logind.set_reboot_to_boot_loader_entry(entry).await
+      }
+    };
+    if let Err(error) = prepared {
+      show_error_toast("restart", &error);
+      return;
+    }
+
+    if run_action(command, logind.clone(), PowerAction::Reboot).await {
+      return;
+    }
+
+    // Otherwise the next restart, maybe much later, would use the target
+    let reset = match target {
+      RebootTarget::FirmwareSetup => logind.set_reboot_to_firmware_setup(false).await,
+      RebootTarget::BootLoaderEntry(_) => logind.set_reboot_to_boot_loader_entry("").await,
+    };
+    if let Err(error) = reset {
+      warn!("Could not reset the restart target: {}", error);
+    }
+  });
+}
+
+fn create_restart_into_page(
+  c: &MainContext,
+  stack: &gtk::Stack,
+  config: Rc<Config>,
+  logind: Rc<Logind>,
+  targets: Vec<RebootTarget>,
+) -> gtk::Box {
+  let page = gtk::Box::new(gtk::Orientation::Vertical, 8);
+
+  let title = gtk::Label::new(Some("Restart into…"));
+  page.add(&title);
+
+  for target in targets {
+    let button = gtk::Button::new_with_label(&target_label(&target));
+    button.get_style_context().add_class("modal_button");
+    button.connect_button_press_event(clone!(c, stack, config, logind => move |_, _| {
+      let on_confirm = clone!(c, config, logind, target => move || {
+        reboot_into(&c, &config.power, logind.clone(), target.clone());
+      });
+      if config.power.confirm {
+        show_confirmation(&c, &stack, &config, logind.clone(), PowerAction::Reboot, on_confirm);
+      } else {
+        on_confirm();
+        close_modal(&stack);
+      }
+
+      Inhibit(false)
+    }));
+    page.add(&button);
+  }
+
+  let back_button = gtk::Button::new_with_label("Back");
+  back_button.get_style_context().add_class("modal_button");
+  back_button.connect_button_press_event(clone!(stack => move |_, _| {
+    stack.set_visible_child_name("actions");
+
+    Inhibit(false)
+  }));
+  page.add(&back_button);
+
+  page
+}
+
+/// The time to shut down at, `None` when the local time is skipped by a
+/// change to summer time.
+fn schedule_time(in_minutes: bool, minutes: u32, hour: u32, minute: u32) -> Option<SystemTime> {
+  if in_minutes {
+    return Some(SystemTime::now() + Duration::from_secs(u64::from(minutes) * 60));
+  }
+
+  let now = Local::now();
+  let mut date = now.naive_local().date();
+  let mut time = Local
+    .from_local_datetime(&date.and_hms_opt(hour, minute, 0)?)
+    .earliest()?;
+  if time <= now {
+    date = date.succ();
+    time = Local
+      .from_local_datetime(&date.and_hms_opt(hour, minute, 0)?)
+      .earliest()?;
+  }
+
+  Some(SystemTime::from(time))
+}
+
+fn create_schedule_page(c: &MainContext, stack: &gtk::Stack, logind: Rc<Logind>) -> gtk::Box {
+  let page = gtk::Box::new(gtk::Orientation::Vertical, 8);
+
+  let kind_combo = gtk::ComboBoxText::new();
+  kind_combo.append(Some("poweroff"), "Shutdown");
+  kind_combo.append(Some("reboot"), "Restart");
+  kind_combo.set_active_id(Some("poweroff"));
+  page.add(&kind_combo);
+
+  let in_row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+  let in_radio = gtk::RadioButton::new_with_label("In");
+  let minutes_spin = gtk::SpinButton::new_with_range(1.0, 24.0 * 60.0, 1.0);
+  minutes_spin.set_value(30.0);
+  in_row.add(&in_radio);
+  in_row.add(&minutes_spin);
+  in_row.add(&gtk::Label::new(Some("minutes")));
+  page.add(&in_row);
+
+  let at_row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+  let at_radio = gtk::RadioButton::new_with_label_from_widget(&in_radio, "At");
+  let hour_spin = gtk::SpinButton::new_with_range(0.0, 23.0, 1.0);
+  let minute_spin = gtk::SpinButton::new_with_range(0.0, 59.0, 1.0);
+  hour_spin.set_value(f64::from(Local::now().hour()));
+  at_row.add(&at_radio);
+  at_row.add(&hour_spin);
+  at_row.add(&gtk::Label::new(Some(":")));
+  at_row.add(&minute_spin);
+  page.add(&at_row);
+
+  let button_row = gtk::Box::new(gtk::Orientation::Horizontal, 12);
+  button_row.set_homogeneous(true);
+  let back_button = gtk::Button::new_with_label("Back");
+  back_button.get_style_context().add_class("modal_button");
+  let schedule_button = gtk::Button::new_with_label("Schedule");
+  schedule_button.get_style_context().add_class("modal_button");
+  button_row.add(&back_button);
+  button_row.add(&schedule_button);
+  page.add(&button_row);
+
+  back_button.connect_button_press_event(clone!(stack => move |_, _| {
+    stack.set_visible_child_name("actions");
+
+    Inhibit(false)
+  }));
+
+  schedule_button.connect_button_press_event(clone!(c, stack => move |_, _| {
+    let kind = match kind_combo.get_active_id().as_ref().map(|id| id.as_str()) {
+      Some("reboot") => ShutdownKind::Reboot,
+      _ => ShutdownKind::PowerOff,
+    };
+    let time = match schedule_time(
+      in_radio.get_active(),
+      minutes_spin.get_value_as_int() as u32,
+      hour_spin.get_value_as_int() as u32,
+      minute_spin.get_value_as_int() as u32,
+    ) {
+      Some(time) => time,
+      None => {
+        show_toast("That time does not exist because of the change to summer time");
+        return Inhibit(false);
+      }
+    };
+
+    c.spawn_local(clone!(logind => async move {
+      if let Err(error) = logind.schedule_shutdown(kind, time).await {
+        show_error_toast("schedule the shutdown", &error);
+      }
+    }));
+    close_modal(&stack);
+
+    Inhibit(false)
+  }));
+
+  page
+}
+
+pub fn create_power_modal(c: MainContext, config: Rc<Config>, logind: Rc<Logind>) -> gtk::Stack {
+  let stack = gtk::Stack::new();
+  stack.set_transition_type(gtk::StackTransitionType::Crossfade);
+
+  let button_row = gtk::Box::new(gtk::Orientation::Horizontal, 12);
+  stack.add_named(&button_row, "actions");
+
+  let populate = clone!(c, config, stack, button_row => async move {
+    let actions = available_actions(&config.power, &logind).await;
+    let can_reboot = actions.iter().any(|(action, _)| *action == PowerAction::Reboot);
+    let can_power_off = actions.iter().any(|(action, _)| *action == PowerAction::PowerOff);
+
+    if actions.is_empty() {
+      let label = gtk::Label::new(Some("No power actions are available"));
+      button_row.add(&label);
+    }
+
+    for (action, capability) in actions {
+      let button = create_power_modal_button(
+        label(action),
+        icon(action),
+        capability == Capability::Challenge,
+        clone!(c, config, logind, stack => move || {
+          let on_confirm = clone!(c, config, logind => move || {
+            perform(&c, &config.power, logind.clone(), action);
+          });
+          if config.power.confirm && is_destructive(action) {
+            show_confirmation(&c, &stack, &config, logind.clone(), action, on_confirm);
+          } else {
+            on_confirm();
+            close_modal(&stack);
+          }
+        }),
+      );
+      button_row.add(&button);
+    }
+
+    if can_reboot || can_power_off {
+      let schedule_page = create_schedule_page(&c, &stack, logind.clone());
+      stack.add_named(&schedule_page, "schedule");
+      schedule_page.show_all();
+
+      let button = create_power_modal_button(
+        "Schedule…",
+        "alarm",
+        false,
+        clone!(stack => move || {
+          stack.set_visible_child_name("schedule");
+        }),
+      );
+      button_row.add(&button);
+    }
+
+    if can_reboot {
+      let targets = reboot_targets(&logind).await;
+      if !targets.is_empty() {
+        let restart_into_page =
+          create_restart_into_page(&c, &stack, config.clone(), logind.clone(), targets);
+        stack.add_named(&restart_into_page, "restart_into");
+        restart_into_page.show_all();
+
+        let button = create_power_modal_button(
+          "Restart Into…",
+          "system-restart",
+          false,
+          clone!(stack => move || {
+            stack.set_visible_child_name("restart_into");
+          }),
+        );
+        button_row.add(&button);
+      }
+    }
+
+    button_row.show_all();
+  });
+  c.spawn_local(populate);
+
+  stack
+}
+
+fn format_remaining(remaining: Duration) -> String {
+  let seconds = remaining.as_secs();
+  let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+
+  if hours > 0 {
+    format!("{}:{:02}:{:02}", hours, minutes, seconds)
+  } else {
+    format!("{}:{:02}", minutes, seconds)
+  }
+}
+
+/// Shows a countdown in the panel while a shutdown is scheduled.
+///
+/// Clicking it cancels the scheduled shutdown.
+pub fn create_scheduled_shutdown_indicator(c: MainContext, logind: Rc<Logind>) -> gtk::EventBox {
+  let row = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+  let icon =
+    gtk::Image::new_from_icon_name(Some("system-shutdown-symbolic"), gtk::IconSize::SmallToolbar);
+  let label = gtk::Label::new(None);
+  row.add(&icon);
+  row.add(&label);
+
+  let indicator = gtk::EventBox::new();
+  indicator.add(&row);
+  indicator.set_tooltip_text(Some("Click to cancel"));
+  indicator.set_no_show_all(true);
+
+  let scheduled = Rc::new(RefCell::new(None::<ScheduledShutdown>));
+
+  let update = clone!(indicator, row, label, scheduled => move || {
+    match *scheduled.borrow() {
+      Some(ref scheduled_shutdown) => {
+        let remaining = scheduled_shutdown
+          .time
+          .duration_since(SystemTime::now())
+          .unwrap_or_default();
+        let kind = match scheduled_shutdown.kind {
+          ShutdownKind::PowerOff => "Shutdown",
+          ShutdownKind::Reboot => "Restart",
+        };
+        label.set_markup(&format_panel_text(format!(
+          "{} in {}",
+          kind,
+          format_remaining(remaining)
+        )));
+        row.show_all();
+        indicator.show();
+      }
+      None => indicator.hide(),
+    }
+  });
+
+  gtk::timeout_add_seconds(1, clone!(update => move || {
+    update();
+    gtk::Continue(true)
+  }));
+
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    logind
+      .subscribe_to_scheduled_shutdown()
+      .for_each(move |scheduled_shutdown| {
+        *scheduled.borrow_mut() = scheduled_shutdown;
+        update();
+
+        future::ready(())
+      }),
+  );
+
+  indicator.connect_button_press_event(move |_, _| {
+    c.spawn_local(clone!(logind => async move {
+      if let Err(error) = logind.cancel_scheduled_shutdown().await {
+        show_error_toast("cancel the scheduled shutdown", &error);
+      }
+    }));
+
+    Inhibit(false)
+  });
+
+  indicator
+}
@@ -0,0 +1,136 @@
+use crate::clone;
+use crate::system::power_profiles::{PowerProfiles, PowerProfilesState};
+use crate::toast::show_error_toast;
+use futures::prelude::*;
+use glib::MainContext;
+use glib::PRIORITY_DEFAULT_IDLE;
+use gtk::prelude::*;
+use std::cell::Cell;
+use std::rc::Rc;
+
+fn profile_icon(profile: &str) -> String {
+  format!("power-profile-{}-symbolic", profile)
+}
+
+fn profile_label(profile: &str) -> String {
+  match profile {
+    "power-saver" => "Power Saver".to_string(),
+    "balanced" => "Balanced".to_string(),
+    "performance" => "Performance".to_string(),
+    profile => profile.to_string(),
+  }
+}
+
+/// Icon in the panel showing the active power profile.
+pub fn create_power_profile_icon(c: MainContext, power_profiles: Rc<PowerProfiles>) -> gtk::Image {
+  let icon = gtk::Image::new();
+  icon.set_no_show_all(true);
+
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    power_profiles
+      .subscribe()
+      .for_each(clone!(icon => move |state| {
+        icon.set_from_icon_name(
+          Some(&profile_icon(&state.active_profile)),
+          gtk::IconSize::SmallToolbar,
+        );
+        icon.set_tooltip_text(Some(&profile_label(&state.active_profile)));
+        icon.show();
+
+        future::ready(())
+      })),
+  );
+
+  icon
+}
+
+fn update_switcher(
+  c: &MainContext,
+  switcher: &gtk::Box,
+  power_profiles: &Rc<PowerProfiles>,
+  updating: &Rc<Cell<bool>>,
+  state: &PowerProfilesState,
+) {
+  updating.set(true);
+
+  for child in switcher.get_children() {
+    switcher.remove(&child);
+  }
+
+  let mut group: Option<gtk::RadioButton> = None;
+  for profile in &state.profiles {
+    let button = match group {
+      Some(ref group) => gtk::RadioButton::new_from_widget(group),
+      None => gtk::RadioButton::new(),
+    };
+    button.set_mode(false);
+    button.set_image(Some(&gtk::Image::new_from_icon_name(
+      Some(&profile_icon(profile)),
+      gtk::IconSize::Button,
+    )));
+    button.set_tooltip_text(Some(&profile_label(profile)));
+    button.set_active(*profile == state.active_profile);
+
+    if profile == "performance" && !state.performance_degraded.is_empty() {
+      button.set_tooltip_text(Some(&format!(
+        "Performance (degraded: {})",
+        state.performance_degraded
+      )));
+    }
+
+    let profile = profile.clone();
+    button.connect_toggled(clone!(c, power_profiles, updating => move |button| {
+      if updating.get() || !button.get_active() {
+        return;
+      }
+
+      c.spawn_local(clone!(power_profiles, profile => async move {
+        if let Err(error) = power_profiles.set_active_profile(&profile).await {
+          show_error_toast("change the power profile", &error);
+        }
+      }));
+    }));
+
+    switcher.add(&button);
+    button.show_all();
+    if group.is_none() {
+      group = Some(button);
+    }
+  }
+
+  updating.set(false);
+}
+
+/// Segmented power-saver / balanced / performance toggle for the system
+/// menu.
+pub fn create_power_profile_switcher(
+  c: MainContext,
+  power_profiles: Rc<PowerProfiles>,
+) -> gtk::Box {
+  let switcher = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+  switcher.set_homogeneous(true);
+  switcher.get_style_context().add_class("linked");
+  switcher.set_no_show_all(true);
+
+  let updating = Rc::new(Cell::new(false));
+
+  let destroyed = Rc::new(Cell::new(false));
+  switcher.connect_destroy(clone!(destroyed => move |_| {
+    destroyed.set(true);
+  }));
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    power_profiles
+      .subscribe()
+      .take_while(move |_| future::ready(!destroyed.get()))
+      .for_each(clone!(c, switcher => move |state| {
+        update_switcher(&c, &switcher, &power_profiles, &updating, &state);
+        switcher.show();
+
+        future::ready(())
+      })),
+  );
+
+  switcher
+}
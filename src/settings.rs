@@ -1,97 +1,35 @@
+use crate::airplane_mode::{create_airplane_mode_icon, create_airplane_mode_section};
+use crate::battery::{create_battery_indicator, create_battery_section, create_charge_limit_row};
+use crate::bluetooth::{create_bluetooth_icon, create_bluetooth_section, create_pairing_menu};
+use crate::brightness::{create_brightness_icon, create_brightness_slider};
 use crate::clone;
+use crate::config::Config;
+use crate::inhibitors::create_inhibitors_menu;
 use crate::modal::create_modal;
-use crate::popup::create_popup;
-use crate::system::audio::*;
+use crate::network::{create_network_icon, create_vpn_section, create_wifi_menu};
+use crate::popup::create_popup_with_submenus;
+use crate::power::create_power_modal;
+use crate::power_profiles::{create_power_profile_icon, create_power_profile_switcher};
+use crate::system::Services;
+use crate::toast::show_error_toast;
 use crate::utils::format_panel_text;
-use dbus::blocking::BlockingSender;
-use dbus::blocking::Connection;
-use dbus::channel::Sender;
-use dbus::strings::Path;
-use dbus::Message;
 use futures::prelude::*;
 use glib::MainContext;
 use glib::PRIORITY_DEFAULT_IDLE;
 use gtk::prelude::*;
-use std::process;
+use std::cell::Cell;
 use std::rc::Rc;
 use std::sync::{Arc, RwLock};
-use std::time::Duration;
-
-fn create_power_modal_button<F>(label: &str, icon: &str, on_click: F) -> gtk::Button
-where
-  F: 'static,
-  F: Fn() -> (),
-{
-  let button_icon = gtk::Image::new_from_icon_name(Some(icon), gtk::IconSize::Dialog);
-  let button = gtk::Button::new_with_label(label);
-  button.set_always_show_image(true);
-  button.set_image_position(gtk::PositionType::Top);
-  button.set_image(Some(&button_icon));
-  button.get_style_context().add_class("modal_button");
-
-  button.connect_button_press_event(move |_, _| {
-    on_click();
 
-    Inhibit(false)
-  });
-
-  button
-}
-
-fn create_power_modal(dbus: Rc<Connection>) -> gtk::Box {
-  let button_row = gtk::Box::new(gtk::Orientation::Horizontal, 12);
-
-  let suspend_button = create_power_modal_button(
-    "Suspend",
-    "system-suspend",
-    clone!(dbus => move || {
-      let m = Message::call_with_args(
-        "org.freedesktop.login1",
-        "/org/freedesktop/login1",
-        "org.freedesktop.login1.Manager",
-        "Suspend",
-        (true,),
-      );
-      dbus.send(m).unwrap();
-    }),
-  );
-  let restart_button = create_power_modal_button(
-    "Restart",
-    "system-restart",
-    clone!(dbus => move || {
-      let m = Message::call_with_args(
-        "org.freedesktop.login1",
-        "/org/freedesktop/login1",
-        "org.freedesktop.login1.Manager",
-        "Restart",
-        (true,),
-      );
-      dbus.send(m).unwrap();
-    }),
-  );
-  let power_button = create_power_modal_button(
-    "Shutdown",
-    "system-shutdown",
-    clone!(dbus => move || {
-      let m = Message::call_with_args(
-        "org.freedesktop.login1",
-        "/org/freedesktop/login1",
-        "org.freedesktop.login1.Manager",
-        "Shutdown",
-        (true,),
-      );
-      dbus.send(m).unwrap();
-    }),
-  );
-
-  button_row.add(&suspend_button);
-  button_row.add(&restart_button);
-  button_row.add(&power_button);
-
-  button_row
-}
+fn create_system_menu(
+  c: MainContext,
+  config: Rc<Config>,
+  services: Rc<Services>,
+) -> (gtk::Box, Vec<(&'static str, gtk::Widget)>) {
+  let audio = services.audio.clone();
+  let logind = services.logind.clone();
+  let idle_inhibitor = services.idle_inhibitor.clone();
 
-fn create_system_menu(c: MainContext, audio: Rc<Audio>, dbus: Rc<Connection>) -> gtk::Box {
   let system_volume = c.block_on(audio.get_system_volume()).unwrap_or(0.0);
 
   let system_menu = gtk::Box::new(gtk::Orientation::Vertical, 2);
@@ -106,16 +44,62 @@ fn create_system_menu(c: MainContext, audio: Rc<Audio>, dbus: Rc<Connection>) ->
   volume_slider_row.pack_end(&volume_slider, false, false, 0);
   system_menu.pack_start(&volume_slider_row, false, false, 6);
 
+  for backlight in services
+    .display_backlight
+    .iter()
+    .chain(services.keyboard_backlight.iter())
+  {
+    let brightness_slider_row = create_brightness_slider(c.clone(), backlight.clone());
+    system_menu.pack_start(&brightness_slider_row, false, false, 6);
+  }
+
+  let battery_section = create_battery_section(c.clone(), services.upower.clone());
+  system_menu.pack_start(&battery_section, false, false, 6);
+
+  if let Some(ref charge_limit) = services.charge_limit {
+    let charge_limit_row = create_charge_limit_row(c.clone(), config.clone(), charge_limit.clone());
+    system_menu.pack_start(&charge_limit_row, false, false, 0);
+  }
+
+  let power_profile_switcher =
+    create_power_profile_switcher(c.clone(), services.power_profiles.clone());
+  system_menu.pack_start(&power_profile_switcher, false, false, 6);
+
   let separator = gtk::Separator::new(gtk::Orientation::Horizontal);
   system_menu.add(&separator);
 
-  let label1 = gtk::ModelButton::new();
-  label1.set_label("Test1");
-  system_menu.add(&label1);
+  let idle_inhibitor_button = gtk::ModelButton::new();
+  idle_inhibitor_button.set_property_role(gtk::ButtonRole::Check);
+  idle_inhibitor_button.set_label(&match idle_inhibitor.expire_after_minutes() {
+    Some(minutes) => format!("Keep Awake ({} min)", minutes),
+    None => "Keep Awake".to_string(),
+  });
+  idle_inhibitor_button.set_property_active(idle_inhibitor.is_active());
+  system_menu.add(&idle_inhibitor_button);
+
+  if let Some(ref rfkill) = services.rfkill {
+    let airplane_mode_section = create_airplane_mode_section(c.clone(), rfkill.clone());
+    system_menu.add(&airplane_mode_section);
+  }
+
+  let wifi_button = gtk::ModelButton::new();
+  wifi_button.set_label("Wi-Fi Networks");
+  wifi_button.set_property_menu_name(Some("wifi"));
+  system_menu.add(&wifi_button);
+  let wifi_menu = create_wifi_menu(c.clone(), services.network.clone());
+
+  let vpn_section = create_vpn_section(c.clone(), services.network.clone());
+  system_menu.add(&vpn_section);
+
+  let bluetooth_section = create_bluetooth_section(c.clone(), services.bluetooth.clone());
+  system_menu.add(&bluetooth_section);
+  let pairing_menu = create_pairing_menu(c.clone(), services.bluetooth.clone());
 
-  let label2 = gtk::ModelButton::new();
-  label2.set_label("Test2");
-  system_menu.add(&label2);
+  let inhibitors_button = gtk::ModelButton::new();
+  inhibitors_button.set_label("Inhibitors");
+  inhibitors_button.set_property_menu_name(Some("inhibitors"));
+  system_menu.add(&inhibitors_button);
+  let inhibitors_menu = create_inhibitors_menu(c.clone(), logind.clone());
 
   let separator = gtk::Separator::new(gtk::Orientation::Horizontal);
   system_menu.add(&separator);
@@ -132,44 +116,49 @@ fn create_system_menu(c: MainContext, audio: Rc<Audio>, dbus: Rc<Connection>) ->
   button_row.pack_end(&power_button, false, false, 32);
   system_menu.pack_end(&button_row, false, false, 6);
 
-  let m = Message::call_with_args(
-    "org.freedesktop.login1",
-    "/org/freedesktop/login1",
-    "org.freedesktop.login1.Manager",
-    "GetSessionByPID",
-    (process::id(),),
-  );
-  match dbus
-    .send_with_reply_and_block(m, Duration::from_millis(50))
-    .map(|result| result.read1::<Path>())
-  {
-    Ok(Ok(session_path)) => {
-      let session_path = session_path.into_static();
-      lock_button.connect_button_press_event(clone!(dbus, session_path => move |_, _| {
-        let m = Message::new_method_call(
-          "org.freedesktop.login1",
-          &session_path,
-          "org.freedesktop.login1.Session",
-          "Lock",
-        )
-        .unwrap();
-        dbus.send(m).unwrap();
-
-        Inhibit(true)
+  if logind.has_session() {
+    lock_button.connect_button_press_event(clone!(c, logind => move |_, _| {
+      c.spawn_local(clone!(logind => async move {
+        if let Err(error) = logind.lock_session().await {
+          show_error_toast("lock the screen", &error);
+        }
       }));
-    }
-    Ok(Err(error)) => {
-      println!("error {:?}", error);
-      lock_button.set_sensitive(false);
-    }
-    Err(error) => {
-      println!("error {:?}", error);
-      lock_button.set_sensitive(false);
-    }
+
+      Inhibit(true)
+    }));
+  } else {
+    lock_button.set_sensitive(false);
   }
 
-  power_button.connect_button_press_event(clone!(dbus => move |_, _| {
-    let modal_content = create_power_modal(dbus.clone());
+  idle_inhibitor_button.connect_clicked(clone!(c, idle_inhibitor => move |_| {
+    if idle_inhibitor.is_active() {
+      idle_inhibitor.disable();
+    } else {
+      c.spawn_local(clone!(idle_inhibitor => async move {
+        if let Err(error) = idle_inhibitor.enable().await {
+          show_error_toast("keep the system awake", &error);
+        }
+      }));
+    }
+  }));
+  let destroyed = Rc::new(Cell::new(false));
+  idle_inhibitor_button.connect_destroy(clone!(destroyed => move |_| {
+    destroyed.set(true);
+  }));
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    idle_inhibitor
+      .subscribe()
+      .take_while(move |_| future::ready(!destroyed.get()))
+      .for_each(move |active| {
+        idle_inhibitor_button.set_property_active(active);
+
+        future::ready(())
+      }),
+  );
+
+  power_button.connect_button_press_event(clone!(c, config, logind => move |_, _| {
+    let modal_content = create_power_modal(c.clone(), config.clone(), logind.clone());
     let show_modal = create_modal(&modal_content);
 
     show_modal();
@@ -196,32 +185,65 @@ fn create_system_menu(c: MainContext, audio: Rc<Audio>, dbus: Rc<Connection>) ->
   );
   audio.update_subscribers();
 
-  system_menu
+  (
+    system_menu,
+    vec![
+      ("wifi", wifi_menu.upcast::<gtk::Widget>()),
+      ("bluetooth-pairing", pairing_menu.upcast::<gtk::Widget>()),
+      ("inhibitors", inhibitors_menu.upcast::<gtk::Widget>()),
+    ],
+  )
 }
 
 pub fn create_settings_button(
   c: MainContext,
-  audio: Rc<Audio>,
-  dbus: Rc<Connection>,
+  config: Rc<Config>,
+  services: Rc<Services>,
 ) -> gtk::EventBox {
+  let audio = services.audio.clone();
+  let idle_inhibitor = services.idle_inhibitor.clone();
+
   let settings_label = gtk::Label::new(None);
   settings_label.set_margin_top(6);
   settings_label.set_margin_bottom(6);
   settings_label.set_markup(&format_panel_text("Settings"));
 
   let system_button_row = gtk::Box::new(gtk::Orientation::Horizontal, 4);
-  let network_icon = gtk::Image::new_from_icon_name(
-    Some("network-wireless-signal-good-symbolic"),
-    gtk::IconSize::SmallToolbar,
-  );
+  let network_icon = create_network_icon(c.clone(), services.network.clone());
   let volume_icon =
     gtk::Image::new_from_icon_name(Some("audio-volume-muted"), gtk::IconSize::SmallToolbar);
   let power_icon =
     gtk::Image::new_from_icon_name(Some("system-shutdown"), gtk::IconSize::SmallToolbar);
+  let idle_inhibitor_icon =
+    gtk::Image::new_from_icon_name(Some("view-reveal-symbolic"), gtk::IconSize::SmallToolbar);
+  idle_inhibitor_icon.set_tooltip_text(Some("Keeping the system awake"));
+  idle_inhibitor_icon.set_no_show_all(true);
+  idle_inhibitor_icon.set_visible(idle_inhibitor.is_active());
+  system_button_row.add(&idle_inhibitor_icon);
+  if let Some(ref rfkill) = services.rfkill {
+    let airplane_mode_icon = create_airplane_mode_icon(c.clone(), rfkill.clone());
+    system_button_row.add(&airplane_mode_icon);
+  }
   system_button_row.add(&network_icon);
+  let bluetooth_icon = create_bluetooth_icon(c.clone(), services.bluetooth.clone());
+  system_button_row.add(&bluetooth_icon);
   system_button_row.add(&volume_icon);
+  if let Some(ref display_backlight) = services.display_backlight {
+    let brightness_icon = create_brightness_icon(c.clone(), display_backlight.clone());
+    system_button_row.add(&brightness_icon);
+  }
   system_button_row.add(&power_icon);
 
+  let power_profile_icon = create_power_profile_icon(c.clone(), services.power_profiles.clone());
+  system_button_row.add(&power_profile_icon);
+
+  let battery_indicator = create_battery_indicator(
+    c.clone(),
+    services.upower.clone(),
+    services.logind.subscribe_to_resume(),
+  );
+  system_button_row.add(&battery_indicator);
+
   let system_volume_stream = audio.subscribe_to_system_volume();
 
   c.spawn_local_with_priority(
@@ -240,12 +262,21 @@ pub fn create_settings_button(
   );
   audio.update_subscribers();
 
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    idle_inhibitor.subscribe().for_each(move |active| {
+      idle_inhibitor_icon.set_visible(active);
+
+      future::ready(())
+    }),
+  );
+
   let system_button = gtk::EventBox::new();
   system_button.add(&system_button_row);
 
   system_button.connect_button_press_event(clone!(c => move |system_button, _| {
-    let system_menu = create_system_menu(c.clone(), audio.clone(), dbus.clone());
-    let show_popup = create_popup(system_button, &system_menu);
+    let (system_menu, submenus) = create_system_menu(c.clone(), config.clone(), services.clone());
+    let show_popup = create_popup_with_submenus(system_button, &system_menu, submenus);
 
     show_popup();
     Inhibit(false)
@@ -1 +1,45 @@
 pub mod audio;
+pub mod backlight;
+pub mod bluetooth;
+pub mod bus;
+pub mod charge_limit;
+pub mod do_not_disturb;
+pub mod idle_inhibitor;
+pub mod logind;
+pub mod mpris;
+pub mod network;
+pub mod notification_history;
+pub mod notifications;
+pub mod power_profiles;
+pub mod rfkill;
+pub mod status_notifier;
+pub mod sway;
+pub mod throughput;
+pub mod upower;
+
+use crate::system::audio::Audio;
+use crate::system::backlight::Backlight;
+use crate::system::bluetooth::Bluetooth;
+use crate::system::charge_limit::ChargeLimit;
+use crate::system::idle_inhibitor::IdleInhibitor;
+use crate::system::logind::Logind;
+use crate::system::network::NetworkManager;
+use crate::system::power_profiles::PowerProfiles;
+use crate::system::rfkill::Rfkill;
+use crate::system::upower::UPower;
+use std::rc::Rc;
+
+/// The system services shared by the panel's widgets.
+pub struct Services {
+  pub audio: Rc<Audio>,
+  pub logind: Rc<Logind>,
+  pub network: Rc<NetworkManager>,
+  pub bluetooth: Rc<Bluetooth>,
+  pub idle_inhibitor: Rc<IdleInhibitor>,
+  pub upower: Rc<UPower>,
+  pub power_profiles: Rc<PowerProfiles>,
+  pub display_backlight: Option<Rc<Backlight>>,
+  pub keyboard_backlight: Option<Rc<Backlight>>,
+  pub charge_limit: Option<Rc<ChargeLimit>>,
+  pub rfkill: Option<Rc<Rfkill>>,
+}
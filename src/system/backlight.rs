@@ -0,0 +1,218 @@
+use crate::system::logind::Logind;
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use futures::prelude::*;
+use glib::IOCondition;
+use inotify::{Inotify, WatchMask};
+use log::warn;
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+#[derive(Clone, Debug)]
+pub struct BacklightDevice {
+  subsystem: &'static str,
+  name: String,
+  path: PathBuf,
+  /// `actual_brightness` when the device has it, as it also follows
+  /// changes made by the kernel or firmware, like brightness keys.
+  level_path: PathBuf,
+  max_brightness: u32,
+}
+
+fn read_u32(path: &Path) -> io::Result<u32> {
+  fs::read_to_string(path)?
+    .trim()
+    .parse()
+    .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+impl BacklightDevice {
+  fn find<F>(subsystem: &'static str, filter: F) -> Option<BacklightDevice>
+  where
+    F: Fn(&str) -> bool,
+  {
+    let mut entries = fs::read_dir(Path::new("/sys/class").join(subsystem))
+      .ok()?
+      .filter_map(|entry| entry.ok())
+      .filter(|entry| filter(&entry.file_name().to_string_lossy()))
+      .collect::<Vec<_>>();
+    entries.sort_by_key(|entry| entry.file_name());
+
+    entries.into_iter().find_map(|entry| {
+      let path = entry.path();
+      let max_brightness = read_u32(&path.join("max_brightness")).ok()?;
+      let actual_brightness_path = path.join("actual_brightness");
+      let level_path = if actual_brightness_path.exists() {
+        actual_brightness_path
+      } else {
+        path.join("brightness")
+      };
+
+      Some(BacklightDevice {
+        subsystem,
+        name: entry.file_name().to_string_lossy().into_owned(),
+        path,
+        level_path,
+        max_brightness,
+      })
+    })
+  }
+
+  pub fn find_display() -> Option<BacklightDevice> {
+    BacklightDevice::find("backlight", |_| true)
+  }
+
+  pub fn find_keyboard() -> Option<BacklightDevice> {
+    BacklightDevice::find("leds", |name| name.ends_with("kbd_backlight"))
+  }
+
+  fn brightness(&self) -> io::Result<f64> {
+    Ok(self.to_fraction(read_u32(&self.level_path)?))
+  }
+
+  fn to_fraction(&self, level: u32) -> f64 {
+    f64::from(level) / f64::from(self.max_brightness.max(1))
+  }
+}
+
+fn send_brightness(subscribers: &RefCell<Vec<UnboundedSender<f64>>>, brightness: f64) {
+  for subscriber in subscribers.borrow().iter() {
+    if !subscriber.is_closed() {
+      subscriber.unbounded_send(brightness).unwrap();
+    }
+  }
+}
+
+/// A display or keyboard backlight.
+///
+/// Writes go through logind so that no root or udev rules are needed.
+pub struct Backlight {
+  logind: Rc<Logind>,
+  device: BacklightDevice,
+  subscribers: Rc<RefCell<Vec<UnboundedSender<f64>>>>,
+}
+
+impl Backlight {
+  pub fn new(logind: Rc<Logind>, device: BacklightDevice) -> Backlight {
+    let backlight = Backlight {
+      logind,
+      device,
+      subscribers: Rc::new(RefCell::new(vec![])),
+    };
+    backlight.watch();
+    backlight.watch_level();
+
+    backlight
+  }
+
+  pub fn is_keyboard(&self) -> bool {
+    self.device.subsystem == "leds"
+  }
+
+  pub fn get_brightness(&self) -> f64 {
+    self.device.brightness().unwrap_or(0.0)
+  }
+
+  pub async fn set_brightness(&self, brightness: f64) -> Result<(), dbus::Error> {
+    let max_brightness = f64::from(self.device.max_brightness);
+    // Turning the display backlight fully off leaves a black screen
+    let min_brightness = if self.is_keyboard() { 0.0 } else { 1.0 };
+    let value = (brightness * max_brightness)
+      .round()
+      .max(min_brightness)
+      .min(max_brightness);
+
+    self
+      .logind
+      .set_brightness(self.device.subsystem, &self.device.name, value as u32)
+      .await
+  }
+
+  fn watch(&self) {
+    let mut inotify = match Inotify::init() {
+      Ok(inotify) => inotify,
+      Err(error) => {
+        warn!("Could not watch the {} brightness: {}", self.device.name, error);
+        return;
+      }
+    };
+    let brightness_path = self.device.path.join("brightness");
+    if let Err(error) = inotify.add_watch(brightness_path, WatchMask::MODIFY) {
+      warn!("Could not watch the {} brightness: {}", self.device.name, error);
+      return;
+    }
+
+    let device = self.device.clone();
+    let subscribers = self.subscribers.clone();
+    let mut buffer = [0; 1024];
+
+    glib::unix_fd_add_local(inotify.as_raw_fd(), IOCondition::IN, move |_, _| {
+      // Drain the queued events, they all mean the same thing
+      while let Ok(mut events) = inotify.read_events(&mut buffer) {
+        if events.next().is_none() {
+          break;
+        }
+      }
+
+      if let Ok(brightness) = device.brightness() {
+        send_brightness(&subscribers, brightness);
+      }
+
+      glib::Continue(true)
+    });
+  }
+
+  /// Follows changes of `actual_brightness` that do not go through a write,
+  /// which sysfs announces as an urgent condition instead of to inotify.
+  fn watch_level(&self) {
+    if !self.device.level_path.ends_with("actual_brightness") {
+      return;
+    }
+
+    let mut file = match File::open(&self.device.level_path) {
+      Ok(file) => file,
+      Err(error) => {
+        warn!("Could not watch the {} brightness: {}", self.device.name, error);
+        return;
+      }
+    };
+    // Sysfs only notifies about attributes that have been read
+    let mut content = String::new();
+    let _ = file.read_to_string(&mut content);
+
+    let device = self.device.clone();
+    let subscribers = self.subscribers.clone();
+
+    glib::unix_fd_add_local(
+      file.as_raw_fd(),
+      IOCondition::PRI | IOCondition::ERR,
+      move |_, _| {
+        let mut content = String::new();
+        let read = file
+          .seek(SeekFrom::Start(0))
+          .and_then(|_| file.read_to_string(&mut content));
+        if let Err(error) = read {
+          warn!("Could not read the {} brightness: {}", device.name, error);
+          return glib::Continue(false);
+        }
+
+        if let Ok(level) = content.trim().parse() {
+          send_brightness(&subscribers, device.to_fraction(level));
+        }
+
+        glib::Continue(true)
+      },
+    );
+  }
+
+  /// Emits the brightness, between 0 and 1, whenever it changes.
+  pub fn subscribe(&self) -> impl Stream<Item = f64> {
+    let (sink, stream) = unbounded::<f64>();
+    self.subscribers.borrow_mut().push(sink);
+
+    stream
+  }
+}
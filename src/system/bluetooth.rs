@@ -0,0 +1,413 @@
+use crate::clone;
+use crate::system::bus::{prop_bool, prop_str, prop_u64, Bus, Properties};
+use dbus::strings::Path;
+use dbus::Message;
+use futures::prelude::*;
+use futures::stream;
+use glib::MainContext;
+use log::warn;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+const BLUEZ: &str = "org.bluez";
+const BLUEZ_PATH: &str = "/org/bluez";
+const OBJECT_MANAGER: &str = "org.freedesktop.DBus.ObjectManager";
+const ADAPTER: &str = "org.bluez.Adapter1";
+const DEVICE: &str = "org.bluez.Device1";
+const BATTERY: &str = "org.bluez.Battery1";
+const AGENT_MANAGER: &str = "org.bluez.AgentManager1";
+const AGENT_PATH: &str = "/org/bluez/agent/panel";
+const AGENT_CAPABILITY: &str = "DisplayYesNo";
+const REJECTED: &str = "org.bluez.Error.Rejected";
+/// Properties that change all the time while discovering, without changing
+/// anything the panel shows.
+const IGNORED_PROPERTIES: &[&str] = &["RSSI", "TxPower", "ManufacturerData", "ServiceData"];
+/// How long changes are collected before the objects are read again.
+const CHANGE_DELAY_MS: u32 = 250;
+
+type ManagedObjects = HashMap<Path<'static>, HashMap<String, Properties>>;
+
+#[derive(Clone, Debug)]
+pub struct Adapter {
+  pub path: String,
+  pub name: String,
+  pub powered: bool,
+  pub discoverable: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct BluetoothDevice {
+  pub path: String,
+  pub name: String,
+  pub icon_name: String,
+  pub paired: bool,
+  pub connected: bool,
+  /// Battery level in percent, for devices that report it.
+  pub battery: Option<u8>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BluetoothState {
+  /// The first adapter, `None` when the system has no Bluetooth.
+  pub adapter: Option<Adapter>,
+  pub devices: Vec<BluetoothDevice>,
+}
+
+impl BluetoothState {
+  pub fn paired_devices(&self) -> impl Iterator<Item = &BluetoothDevice> {
+    self.devices.iter().filter(|device| device.paired)
+  }
+
+  pub fn connected_devices(&self) -> impl Iterator<Item = &BluetoothDevice> {
+    self.devices.iter().filter(|device| device.connected)
+  }
+}
+
+async fn read_state(bus: Bus) -> Result<BluetoothState, dbus::Error> {
+  let (objects,): (ManagedObjects,) = bus
+    .call(BLUEZ, "/", OBJECT_MANAGER, "GetManagedObjects", ())
+    .await?;
+
+  let mut adapters: Vec<Adapter> = objects
+    .iter()
+    .filter_map(|(path, interfaces)| {
+      let properties = interfaces.get(ADAPTER)?;
+
+      Some(Adapter {
+        path: path.to_string(),
+        name: prop_str(properties, "Alias").unwrap_or_default(),
+        powered: prop_bool(properties, "Powered").unwrap_or(false),
+        discoverable: prop_bool(properties, "Discoverable").unwrap_or(false),
+      })
+    })
+    .collect();
+  adapters.sort_by(|a, b| a.path.cmp(&b.path));
+  let adapter = adapters.into_iter().next();
+
+  let adapter_path = adapter.as_ref().map(|adapter| adapter.path.clone());
+  let mut devices: Vec<BluetoothDevice> = objects
+    .iter()
+    .filter_map(|(path, interfaces)| {
+      let properties = interfaces.get(DEVICE)?;
+      if prop_str(properties, "Adapter") != adapter_path {
+        return None;
+      }
+
+      Some(BluetoothDevice {
+        path: path.to_string(),
+        name: prop_str(properties, "Alias").unwrap_or_default(),
+        icon_name: prop_str(properties, "Icon").unwrap_or_else(|| "bluetooth".to_string()),
+        paired: prop_bool(properties, "Paired").unwrap_or(false),
+        connected: prop_bool(properties, "Connected").unwrap_or(false),
+        battery: interfaces
+          .get(BATTERY)
+          .and_then(|battery| prop_u64(battery, "Percentage"))
+          .map(|percentage| percentage as u8),
+      })
+    })
+    .collect();
+  devices.sort_by(|a, b| b.connected.cmp(&a.connected).then(a.name.cmp(&b.name)));
+
+  Ok(BluetoothState { adapter, devices })
+}
+
+#[derive(Clone, Debug)]
+pub enum AgentPrompt {
+  /// Asks the user to confirm that the device shows the same passkey.
+  Confirmation(u32),
+  /// Asks the user to allow pairing without a passkey.
+  Authorization,
+  /// Asks the user to allow the device to use a service, by UUID.
+  ServiceAuthorization(String),
+  PinCode,
+  Passkey,
+  /// Shows a code that should be entered on the device.
+  DisplayPinCode(String),
+  /// Shows a passkey that should be typed on the device, along with how
+  /// many of its digits have been typed. Sent again on every key press.
+  DisplayPasskey(u32, u16),
+}
+
+impl AgentPrompt {
+  fn from_message(message: &Message) -> Option<AgentPrompt> {
+    let member = message.member()?;
+
+    match &*member {
+      "RequestConfirmation" => Some(AgentPrompt::Confirmation(
+        message.read2::<Path, u32>().ok()?.1,
+      )),
+      "RequestAuthorization" => Some(AgentPrompt::Authorization),
+      "AuthorizeService" => Some(AgentPrompt::ServiceAuthorization(
+        message.read2::<Path, String>().ok()?.1,
+      )),
+      "RequestPinCode" => Some(AgentPrompt::PinCode),
+      "RequestPasskey" => Some(AgentPrompt::Passkey),
+      "DisplayPinCode" => Some(AgentPrompt::DisplayPinCode(
+        message.read2::<Path, String>().ok()?.1,
+      )),
+      "DisplayPasskey" => {
+        let (_, passkey, entered) = message.read3::<Path, u32, u16>().ok()?;
+        Some(AgentPrompt::DisplayPasskey(passkey, entered))
+      }
+      _ => None,
+    }
+  }
+
+  /// Whether the prompt only shows something, without waiting for an answer.
+  pub fn is_display(&self) -> bool {
+    match self {
+      AgentPrompt::DisplayPinCode(_) | AgentPrompt::DisplayPasskey(..) => true,
+      _ => false,
+    }
+  }
+}
+
+/// A request from BlueZ for the user to take part in pairing.
+pub struct AgentRequest {
+  pub prompt: AgentPrompt,
+  /// Object path of the device.
+  pub device: String,
+  pub device_name: String,
+  bus: Bus,
+  message: Message,
+}
+
+impl AgentRequest {
+  /// Replies to BlueZ, `None` meaning that the user refused.
+  ///
+  /// For confirmations and authorizations any `Some` accepts, for PIN codes
+  /// and passkeys it is the entered value.
+  pub fn reply(self, answer: Option<String>) {
+    let reply = match (&self.prompt, answer) {
+      (AgentPrompt::DisplayPinCode(_), _) | (AgentPrompt::DisplayPasskey(..), _) => return,
+      (_, None) => rejected_reply(&self.message),
+      (AgentPrompt::PinCode, Some(pin_code)) => self.message.method_return().append1(pin_code),
+      (AgentPrompt::Passkey, Some(passkey)) => match passkey.trim().parse::<u32>() {
+        Ok(passkey) => self.message.method_return().append1(passkey),
+        Err(_) => rejected_reply(&self.message),
+      },
+      (_, Some(_)) => self.message.method_return(),
+    };
+
+    if let Err(error) = self.bus.send(reply) {
+      warn!("Could not reply to BlueZ: {}", error);
+    }
+  }
+}
+
+/// Whether a PropertiesChanged signal changes anything the panel shows.
+fn is_relevant_change(message: &Message) -> bool {
+  match message.read3::<&str, Properties, Vec<String>>() {
+    Ok((_, changed, invalidated)) => changed
+      .keys()
+      .chain(invalidated.iter())
+      .any(|name| !IGNORED_PROPERTIES.contains(&name.as_str())),
+    Err(_) => true,
+  }
+}
+
+fn rejected_reply(message: &Message) -> Message {
+  Message::new_error(message, REJECTED, "Rejected by the user")
+    .expect("Could not create D-Bus error reply")
+}
+
+pub struct Bluetooth {
+  bus: Bus,
+  /// Closes the prompt shown for the latest agent request.
+  cancel_prompt: Rc<RefCell<Option<Box<dyn Fn()>>>>,
+}
+
+impl Bluetooth {
+  pub fn new(bus: Bus) -> Bluetooth {
+    Bluetooth {
+      bus,
+      cancel_prompt: Rc::default(),
+    }
+  }
+
+  pub async fn set_powered(&self, adapter: &Adapter, powered: bool) -> Result<(), dbus::Error> {
+    self
+      .bus
+      .set_property(BLUEZ, &adapter.path, ADAPTER, "Powered", powered)
+      .await
+  }
+
+  /// Makes the adapter visible to and scan for new devices, or stops.
+  pub async fn set_discoverable(
+    &self,
+    adapter: &Adapter,
+    discoverable: bool,
+  ) -> Result<(), dbus::Error> {
+    self
+      .bus
+      .set_property(BLUEZ, &adapter.path, ADAPTER, "Discoverable", discoverable)
+      .await?;
+    self
+      .bus
+      .set_property(BLUEZ, &adapter.path, ADAPTER, "Pairable", discoverable)
+      .await?;
+
+    let method = if discoverable { "StartDiscovery" } else { "StopDiscovery" };
+    self.bus.call(BLUEZ, &adapter.path, ADAPTER, method, ()).await
+  }
+
+  pub async fn connect(&self, device: &BluetoothDevice) -> Result<(), dbus::Error> {
+    self.bus.call(BLUEZ, &device.path, DEVICE, "Connect", ()).await
+  }
+
+  pub async fn disconnect(&self, device: &BluetoothDevice) -> Result<(), dbus::Error> {
+    self
+      .bus
+      .call(BLUEZ, &device.path, DEVICE, "Disconnect", ())
+      .await
+  }
+
+  /// Pairs with `device`, trusts it so it may reconnect on its own and
+  /// connects to it.
+  pub async fn pair(&self, device: &BluetoothDevice) -> Result<(), dbus::Error> {
+    let result = self.bus.call(BLUEZ, &device.path, DEVICE, "Pair", ()).await;
+    // BlueZ does not cancel displayed codes once pairing is over
+    let cancel = self.cancel_prompt.borrow_mut().take();
+    if let Some(cancel) = cancel {
+      cancel();
+    }
+    result?;
+    self
+      .bus
+      .set_property(BLUEZ, &device.path, DEVICE, "Trusted", true)
+      .await?;
+
+    self.connect(device).await
+  }
+
+  /// Emits at most once per `CHANGE_DELAY_MS`, as devices nearby change
+  /// many times a second while discovering.
+  fn subscribe_to_changes(&self) -> impl Stream<Item = ()> {
+    let properties_changed = self
+      .bus
+      .subscribe_to_signal(BLUEZ, None, "org.freedesktop.DBus.Properties", "PropertiesChanged")
+      .filter(|message| future::ready(is_relevant_change(message)))
+      .map(|_| ());
+    let changes = stream::select(
+      properties_changed,
+      stream::select(
+        self
+          .bus
+          .subscribe_to_signal(BLUEZ, Some("/"), OBJECT_MANAGER, "InterfacesAdded"),
+        self
+          .bus
+          .subscribe_to_signal(BLUEZ, Some("/"), OBJECT_MANAGER, "InterfacesRemoved"),
+      )
+      .map(|_| ()),
+    );
+
+    stream::unfold(Box::pin(changes), |mut changes| {
+      async move {
+        changes.next().await?;
+        glib::timeout_future(CHANGE_DELAY_MS).await;
+        while let Some(Some(())) = changes.next().now_or_never() {}
+
+        Some(((), changes))
+      }
+    })
+  }
+
+  /// Emits the adapter and its devices immediately and then whenever
+  /// anything in BlueZ changes.
+  pub fn subscribe(&self) -> impl Stream<Item = BluetoothState> {
+    let bus = self.bus.clone();
+
+    stream::once(future::ready(()))
+      .chain(self.subscribe_to_changes())
+      .then(move |_| read_state(bus.clone()))
+      .filter_map(|state| {
+        future::ready(match state {
+          Ok(state) => Some(state),
+          Err(error) => {
+            warn!("Could not read the Bluetooth state: {}", error);
+            None
+          }
+        })
+      })
+  }
+
+  /// Registers the panel as the default BlueZ agent so that pairing prompts
+  /// are shown by the panel.
+  ///
+  /// `prompt` is called for every request and returns a function that
+  /// cancels the prompt if BlueZ no longer needs an answer.
+  pub async fn register_agent<F>(&self, prompt: F) -> Result<(), dbus::Error>
+  where
+    F: Fn(AgentRequest) -> Box<dyn Fn()> + 'static,
+  {
+    let bus = self.bus.clone();
+    let prompt = Rc::new(prompt);
+    let cancel_current = self.cancel_prompt.clone();
+
+    self.bus.register_object(AGENT_PATH, move |message| {
+      let prompt_kind = match AgentPrompt::from_message(&message) {
+        Some(prompt_kind) => prompt_kind,
+        None => {
+          if message.member().map_or(false, |member| &*member == "Cancel") {
+            if let Some(cancel) = cancel_current.borrow_mut().take() {
+              cancel();
+            }
+          }
+          // Release and Cancel need no more than an empty reply
+          if let Err(error) = bus.send(message.method_return()) {
+            warn!("Could not reply to BlueZ: {}", error);
+          }
+          return;
+        }
+      };
+
+      if prompt_kind.is_display() {
+        if let Err(error) = bus.send(message.method_return()) {
+          warn!("Could not reply to BlueZ: {}", error);
+        }
+      }
+
+      let device = message
+        .read1::<Path>()
+        .map(|device| device.to_string())
+        .unwrap_or_default();
+      MainContext::default().spawn_local(clone!(bus, prompt, cancel_current => async move {
+        let device_name: String = bus
+          .get_property(BLUEZ, &device, DEVICE, "Alias")
+          .await
+          .unwrap_or_else(|_| "Unknown device".to_string());
+        let cancel = prompt(AgentRequest {
+          prompt: prompt_kind,
+          device,
+          device_name,
+          bus,
+          message,
+        });
+        *cancel_current.borrow_mut() = Some(cancel);
+      }));
+    });
+
+    let agent_path = Path::from(AGENT_PATH);
+    self
+      .bus
+      .call(
+        BLUEZ,
+        BLUEZ_PATH,
+        AGENT_MANAGER,
+        "RegisterAgent",
+        (agent_path.clone(), AGENT_CAPABILITY),
+      )
+      .await?;
+    self
+      .bus
+      .call(
+        BLUEZ,
+        BLUEZ_PATH,
+        AGENT_MANAGER,
+        "RequestDefaultAgent",
+        (agent_path,),
+      )
+      .await
+  }
+}
@@ -0,0 +1,337 @@
+use dbus::arg::{Append, AppendAll, Arg, Get, ReadAll, RefArg, Variant};
+use dbus::channel::{BusType, Channel, MatchingReceiver, Sender, Token};
+use dbus::message::{MatchRule, MessageType};
+use dbus::nonblock::{LocalConnection, Process, Proxy};
+use dbus::Message;
+use futures::channel::mpsc::{unbounded, UnboundedReceiver};
+use futures::prelude::*;
+use glib::{IOCondition, MainContext};
+use log::warn;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+pub type Properties = HashMap<String, Variant<Box<dyn RefArg>>>;
+
+/// A D-Bus connection driven by the glib main loop.
+///
+/// All calls are asynchronous so that a slow or unresponsive service never
+/// blocks the panel.
+#[derive(Clone)]
+pub struct Bus {
+  connection: Rc<LocalConnection>,
+}
+
+impl Bus {
+  pub fn new_system() -> Result<Bus, dbus::Error> {
+    Bus::new(BusType::System)
+  }
+
+  pub fn new_session() -> Result<Bus, dbus::Error> {
+    Bus::new(BusType::Session)
+  }
+
+  fn new(bus_type: BusType) -> Result<Bus, dbus::Error> {
+    let mut channel = Channel::get_private(bus_type)?;
+    channel.set_watch_enabled(true);
+    let watch = channel.watch();
+
+    let connection = Rc::new(LocalConnection::from(channel));
+
+    let process_connection = connection.clone();
+    glib::unix_fd_add_local(
+      watch.fd,
+      IOCondition::IN | IOCondition::HUP | IOCondition::ERR,
+      move |_, _| {
+        process_connection.process_all();
+        glib::Continue(true)
+      },
+    );
+
+    Ok(Bus { connection })
+  }
+
+  fn flush(&self) {
+    self.connection.channel().flush();
+  }
+
+  pub async fn call<A, R>(
+    &self,
+    destination: &str,
+    path: &str,
+    interface: &str,
+    method: &str,
+    args: A,
+  ) -> Result<R, dbus::Error>
+  where
+    A: AppendAll,
+    R: ReadAll + 'static,
+  {
+    let proxy = Proxy::new(destination, path, self.connection.clone());
+    let reply = proxy.method_call(interface, method, args);
+    self.flush();
+
+    reply.await
+  }
+
+  pub async fn get_property<T>(
+    &self,
+    destination: &str,
+    path: &str,
+    interface: &str,
+    name: &str,
+  ) -> Result<T, dbus::Error>
+  where
+    T: for<'b> Get<'b> + 'static,
+  {
+    let (value,): (Variant<T>,) = self
+      .call(
+        destination,
+        path,
+        "org.freedesktop.DBus.Properties",
+        "Get",
+        (interface, name),
+      )
+      .await?;
+
+    Ok(value.0)
+  }
+
+  pub async fn get_all_properties(
+    &self,
+    destination: &str,
+    path: &str,
+    interface: &str,
+  ) -> Result<Properties, dbus::Error> {
+    let (properties,): (Properties,) = self
+      .call(
+        destination,
+        path,
+        "org.freedesktop.DBus.Properties",
+        "GetAll",
+        (interface,),
+      )
+      .await?;
+
+    Ok(properties)
+  }
+
+  pub async fn set_property<T>(
+    &self,
+    destination: &str,
+    path: &str,
+    interface: &str,
+    name: &str,
+    value: T,
+  ) -> Result<(), dbus::Error>
+  where
+    T: Arg + Append,
+  {
+    self
+      .call(
+        destination,
+        path,
+        "org.freedesktop.DBus.Properties",
+        "Set",
+        (interface, name, Variant(value)),
+      )
+      .await
+  }
+
+  pub fn send(&self, message: Message) -> Result<(), dbus::Error> {
+    self
+      .connection
+      .send(message)
+      .map_err(|_| dbus::Error::new_custom("org.freedesktop.DBus.Error.Failed", "Send failed"))?;
+    self.flush();
+
+    Ok(())
+  }
+
+  /// Takes ownership of the well-known `name`, returning `false` if another
+  /// process already owns it.
+  pub async fn request_name(&self, name: &str) -> Result<bool, dbus::Error> {
+    const DO_NOT_QUEUE: u32 = 4;
+    const PRIMARY_OWNER: u32 = 1;
+    const ALREADY_OWNER: u32 = 4;
+
+    let (reply,): (u32,) = self
+      .call(
+        "org.freedesktop.DBus",
+        "/org/freedesktop/DBus",
+        "org.freedesktop.DBus",
+        "RequestName",
+        (name, DO_NOT_QUEUE),
+      )
+      .await?;
+
+    Ok(reply == PRIMARY_OWNER || reply == ALREADY_OWNER)
+  }
+
+  /// Calls `handler` with every method call made to `path`.
+  ///
+  /// The handler is responsible for sending a reply, which allows replying
+  /// asynchronously, e.g. after asking the user.
+  pub fn register_object<F>(&self, path: &str, handler: F)
+  where
+    F: Fn(Message) + 'static,
+  {
+    let mut rule = MatchRule::new();
+    rule.msg_type = Some(MessageType::MethodCall);
+    rule.path = Some(path.to_string().into());
+
+    self.connection.start_receive(
+      rule,
+      Box::new(move |message, _| {
+        handler(message);
+        true
+      }),
+    );
+  }
+
+  /// Streams every message matching `rule` until the stream is dropped.
+  pub fn subscribe(&self, rule: MatchRule<'static>) -> impl Stream<Item = Message> {
+    let match_str = rule.match_str();
+
+    self.subscribe_with_match_str(rule, match_str)
+  }
+
+  /// Asks the bus to route messages matching `match_str` to us and streams
+  /// those that match `rule` locally.
+  fn subscribe_with_match_str(&self, rule: MatchRule<'static>, match_str: String) -> Subscription {
+    let (sink, messages) = unbounded::<Message>();
+
+    let token = self.connection.start_receive(
+      rule,
+      Box::new(move |message, _| sink.unbounded_send(message).is_ok()),
+    );
+
+    self.call_match_method("AddMatch", match_str.clone());
+
+    Subscription {
+      bus: self.clone(),
+      token,
+      match_str,
+      messages,
+    }
+  }
+
+  fn call_match_method(&self, method: &'static str, match_str: String) {
+    let bus = self.clone();
+    MainContext::default().spawn_local(async move {
+      let result: Result<(), _> = bus
+        .call(
+          "org.freedesktop.DBus",
+          "/org/freedesktop/DBus",
+          "org.freedesktop.DBus",
+          method,
+          (match_str,),
+        )
+        .await;
+      if let Err(error) = result {
+        warn!("Failed to call {} for a D-Bus match rule: {}", method, error);
+      }
+    });
+  }
+
+  pub fn subscribe_to_signal(
+    &self,
+    sender: &str,
+    path: Option<&str>,
+    interface: &str,
+    member: &str,
+  ) -> impl Stream<Item = Message> {
+    let mut rule = MatchRule::new_signal(interface.to_string(), member.to_string());
+    rule.path = path.map(|path| path.to_string().into());
+
+    self.subscribe_from(sender, rule)
+  }
+
+  /// Streams every message from `sender` matching `rule`, which may be a
+  /// well-known name, until the stream is dropped.
+  pub fn subscribe_from(
+    &self,
+    sender: &str,
+    mut rule: MatchRule<'static>,
+  ) -> impl Stream<Item = Message> {
+    rule.sender = Some(sender.to_string().into());
+    let match_str = rule.match_str();
+
+    // Signals carry the unique name of their sender, so a well-known name is
+    // only matched by the bus. The bus daemon is the one that sends from its
+    // well-known name.
+    if !sender.starts_with(':') && sender != "org.freedesktop.DBus" {
+      rule.sender = None;
+    }
+
+    self.subscribe_with_match_str(rule, match_str)
+  }
+
+  /// Emits whenever a property changes on `path` (or any path when `None`).
+  pub fn subscribe_to_properties(
+    &self,
+    sender: &str,
+    path: Option<&str>,
+  ) -> impl Stream<Item = ()> {
+    self
+      .subscribe_to_signal(
+        sender,
+        path,
+        "org.freedesktop.DBus.Properties",
+        "PropertiesChanged",
+      )
+      .map(|_| ())
+  }
+}
+
+/// Messages matching a rule, which is removed again when dropped.
+struct Subscription {
+  bus: Bus,
+  token: Token,
+  match_str: String,
+  messages: UnboundedReceiver<Message>,
+}
+
+impl Stream for Subscription {
+  type Item = Message;
+
+  fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Message>> {
+    self.messages.poll_next_unpin(cx)
+  }
+}
+
+impl Drop for Subscription {
+  fn drop(&mut self) {
+    self.bus.connection.stop_receive(self.token);
+
+    let match_str = std::mem::replace(&mut self.match_str, String::new());
+    self.bus.call_match_method("RemoveMatch", match_str);
+  }
+}
+
+pub fn prop_str(properties: &Properties, key: &str) -> Option<String> {
+  properties
+    .get(key)
+    .and_then(|value| value.0.as_str())
+    .map(|value| value.to_string())
+}
+
+pub fn prop_u64(properties: &Properties, key: &str) -> Option<u64> {
+  properties.get(key).and_then(|value| value.0.as_u64())
+}
+
+pub fn prop_i64(properties: &Properties, key: &str) -> Option<i64> {
+  properties.get(key).and_then(|value| value.0.as_i64())
+}
+
+pub fn prop_f64(properties: &Properties, key: &str) -> Option<f64> {
+  properties.get(key).and_then(|value| value.0.as_f64())
+}
+
+pub fn prop_bool(properties: &Properties, key: &str) -> Option<bool> {
+  properties
+    .get(key)
+    .and_then(|value| value.0.as_any().downcast_ref::<bool>())
+    .cloned()
+}
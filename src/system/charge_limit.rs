@@ -0,0 +1,140 @@
+use futures::channel::oneshot;
+use log::warn;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+
+const START_THRESHOLD: &str = "charge_control_start_threshold";
+const END_THRESHOLD: &str = "charge_control_end_threshold";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChargeThresholds {
+  /// Not every driver supports a start threshold.
+  pub start: Option<u32>,
+  pub end: u32,
+}
+
+fn read_threshold(path: &Path) -> io::Result<u32> {
+  fs::read_to_string(path)?
+    .trim()
+    .parse()
+    .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+/// Writes directly if possible and otherwise asks polkit for permission
+/// through pkexec.
+fn write_threshold(path: &Path, value: u32) -> io::Result<()> {
+  match fs::write(path, value.to_string()) {
+    Err(ref error) if error.kind() == io::ErrorKind::PermissionDenied => {
+      let mut child = Command::new("pkexec")
+        .arg("tee")
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+      if let Some(ref mut stdin) = child.stdin {
+        stdin.write_all(value.to_string().as_bytes())?;
+      }
+      child.stdin.take();
+
+      let status = child.wait()?;
+      if status.success() {
+        Ok(())
+      } else {
+        Err(io::Error::new(
+          io::ErrorKind::PermissionDenied,
+          "Not authorized to change the charge limit",
+        ))
+      }
+    }
+    result => result,
+  }
+}
+
+fn write_thresholds(
+  battery: &Path,
+  current: ChargeThresholds,
+  new: ChargeThresholds,
+) -> io::Result<()> {
+  // The kernel rejects a start threshold above the end threshold, so the
+  // order of the writes matters
+  let start = new.start.filter(|_| current.start.is_some());
+  let lowering = new.end < current.start.unwrap_or(0);
+
+  if lowering {
+    if let Some(start) = start {
+      write_threshold(&battery.join(START_THRESHOLD), start)?;
+    }
+    write_threshold(&battery.join(END_THRESHOLD), new.end)?;
+  } else {
+    write_threshold(&battery.join(END_THRESHOLD), new.end)?;
+    if let Some(start) = start {
+      write_threshold(&battery.join(START_THRESHOLD), start)?;
+    }
+  }
+
+  Ok(())
+}
+
+fn read_thresholds(battery: &Path) -> io::Result<ChargeThresholds> {
+  Ok(ChargeThresholds {
+    start: read_threshold(&battery.join(START_THRESHOLD)).ok(),
+    end: read_threshold(&battery.join(END_THRESHOLD))?,
+  })
+}
+
+/// Charge thresholds of the batteries in `/sys/class/power_supply`.
+pub struct ChargeLimit {
+  batteries: Vec<PathBuf>,
+}
+
+impl ChargeLimit {
+  /// Returns `None` if no battery supports charge thresholds.
+  pub fn find() -> Option<ChargeLimit> {
+    let mut batteries = fs::read_dir("/sys/class/power_supply")
+      .ok()?
+      .filter_map(|entry| entry.ok())
+      .filter(|entry| entry.file_name().to_string_lossy().starts_with("BAT"))
+      .map(|entry| entry.path())
+      .filter(|path| path.join(END_THRESHOLD).exists())
+      .collect::<Vec<_>>();
+    batteries.sort();
+
+    if batteries.is_empty() {
+      None
+    } else {
+      Some(ChargeLimit { batteries })
+    }
+  }
+
+  pub fn thresholds(&self) -> io::Result<ChargeThresholds> {
+    read_thresholds(&self.batteries[0])
+  }
+
+  /// Applies the thresholds to every battery.
+  ///
+  /// Runs on a separate thread as it may wait for a polkit prompt.
+  pub async fn set_thresholds(&self, thresholds: ChargeThresholds) -> io::Result<()> {
+    let batteries = self.batteries.clone();
+    let (sender, receiver) = oneshot::channel();
+
+    thread::spawn(move || {
+      let result = batteries.iter().try_for_each(|battery| {
+        let current = read_thresholds(battery)?;
+        if current == thresholds {
+          return Ok(());
+        }
+        write_thresholds(battery, current, thresholds)
+      });
+      if sender.send(result).is_err() {
+        warn!("Charge limit result was dropped");
+      }
+    });
+
+    receiver
+      .await
+      .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::Other, "Charge limit thread died")))
+  }
+}
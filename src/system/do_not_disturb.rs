@@ -0,0 +1,208 @@
+use crate::config::NotificationsConfig;
+use crate::system::notifications::NotificationServer;
+use chrono::{DateTime, Duration, Local, NaiveTime};
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use futures::prelude::*;
+use log::error;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+const CHECK_INTERVAL_SECONDS: u32 = 30;
+/// When "until tomorrow" ends if there is no schedule.
+const DEFAULT_MORNING: (u32, u32) = (8, 0);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DoNotDisturbMode {
+  /// Only on during the schedule, if there is one.
+  Off,
+  On,
+  Until(DateTime<Local>),
+  /// Off even during the schedule, until the current scheduled time ends.
+  OffUntil(DateTime<Local>),
+}
+
+fn parse_time(time: &Option<String>) -> Option<NaiveTime> {
+  let time = time.as_ref()?;
+
+  match NaiveTime::parse_from_str(time, "%H:%M") {
+    Ok(time) => Some(time),
+    Err(error) => {
+      error!("Invalid do not disturb time \"{}\": {}", time, error);
+      None
+    }
+  }
+}
+
+/// Holds back notifications that are not critical, either when asked to or
+/// on a daily schedule.
+#[derive(Clone)]
+pub struct DoNotDisturb {
+  server: Rc<NotificationServer>,
+  /// Start and end of the daily schedule.
+  schedule: Option<(NaiveTime, NaiveTime)>,
+  schedule_enabled: Rc<Cell<bool>>,
+  mode: Rc<Cell<DoNotDisturbMode>>,
+  active: Rc<Cell<bool>>,
+  subscribers: Rc<RefCell<Vec<UnboundedSender<bool>>>>,
+}
+
+impl DoNotDisturb {
+  pub fn new(server: Rc<NotificationServer>, config: &NotificationsConfig) -> DoNotDisturb {
+    let schedule = match (
+      parse_time(&config.do_not_disturb_start),
+      parse_time(&config.do_not_disturb_end),
+    ) {
+      (Some(start), Some(end)) => Some((start, end)),
+      _ => None,
+    };
+
+    let do_not_disturb = DoNotDisturb {
+      server,
+      schedule,
+      schedule_enabled: Rc::new(Cell::new(true)),
+      mode: Rc::new(Cell::new(DoNotDisturbMode::Off)),
+      active: Rc::new(Cell::new(false)),
+      subscribers: Rc::new(RefCell::new(vec![])),
+    };
+    do_not_disturb.update();
+
+    let timer = do_not_disturb.clone();
+    gtk::timeout_add_seconds(CHECK_INTERVAL_SECONDS, move || {
+      if timer.update() {
+        timer.update_subscribers();
+      }
+      gtk::Continue(true)
+    });
+
+    do_not_disturb
+  }
+
+  pub fn schedule(&self) -> Option<(NaiveTime, NaiveTime)> {
+    self.schedule
+  }
+
+  pub fn is_schedule_enabled(&self) -> bool {
+    self.schedule_enabled.get()
+  }
+
+  pub fn set_schedule_enabled(&self, enabled: bool) {
+    self.schedule_enabled.set(enabled);
+    self.update();
+    self.update_subscribers();
+  }
+
+  fn is_scheduled(&self, now: DateTime<Local>) -> bool {
+    let (start, end) = match self.schedule {
+      Some(schedule) if self.schedule_enabled.get() => schedule,
+      _ => return false,
+    };
+    let time = now.time();
+
+    if start <= end {
+      start <= time && time < end
+    } else {
+      // The schedule spans midnight
+      time >= start || time < end
+    }
+  }
+
+  pub fn mode(&self) -> DoNotDisturbMode {
+    self.mode.get()
+  }
+
+  pub fn set_mode(&self, mode: DoNotDisturbMode) {
+    self.mode.set(mode);
+    self.update();
+    self.update_subscribers();
+  }
+
+  /// Turns do not disturb off, skipping the rest of the schedule when it is
+  /// on because of it.
+  pub fn turn_off(&self) {
+    let now = Local::now();
+    if !self.is_scheduled(now) {
+      return self.set_mode(DoNotDisturbMode::Off);
+    }
+
+    let end = match self.schedule {
+      Some((_, end)) => end,
+      None => return self.set_mode(DoNotDisturbMode::Off),
+    };
+    let day = if now.time() < end {
+      now.date()
+    } else {
+      now.date() + Duration::days(1)
+    };
+    // Skipped local times, like in the switch to summer time, end a day later
+    let until = day
+      .and_time(end)
+      .unwrap_or_else(|| now + Duration::days(1));
+
+    self.set_mode(DoNotDisturbMode::OffUntil(until));
+  }
+
+  /// Turns on do not disturb until the end of the schedule tomorrow, or
+  /// tomorrow morning without a schedule.
+  pub fn enable_until_tomorrow(&self) {
+    let morning = self
+      .schedule
+      .map(|(_, end)| end)
+      .unwrap_or_else(|| NaiveTime::from_hms(DEFAULT_MORNING.0, DEFAULT_MORNING.1, 0));
+    let tomorrow = Local::now().date() + Duration::days(1);
+
+    match tomorrow.and_time(morning) {
+      Some(until) => self.set_mode(DoNotDisturbMode::Until(until)),
+      None => self.set_mode(DoNotDisturbMode::On),
+    }
+  }
+
+  pub fn is_active(&self) -> bool {
+    self.active.get()
+  }
+
+  /// Reevaluates the mode and schedule, returning whether anything changed.
+  fn update(&self) -> bool {
+    let now = Local::now();
+    let mut changed = false;
+
+    match self.mode.get() {
+      DoNotDisturbMode::Until(until) | DoNotDisturbMode::OffUntil(until) if now >= until => {
+        self.mode.set(DoNotDisturbMode::Off);
+        changed = true;
+      }
+      _ => {}
+    }
+
+    let active = match self.mode.get() {
+      DoNotDisturbMode::On | DoNotDisturbMode::Until(_) => true,
+      DoNotDisturbMode::OffUntil(_) => false,
+      DoNotDisturbMode::Off => self.is_scheduled(now),
+    };
+
+    if active != self.active.replace(active) {
+      self.server.set_do_not_disturb(active);
+      changed = true;
+    }
+
+    changed
+  }
+
+  /// Also called when only the mode changed, so that menus stay in sync.
+  fn update_subscribers(&self) {
+    for subscriber in self.subscribers.borrow().iter() {
+      if !subscriber.is_closed() {
+        subscriber.unbounded_send(self.active.get()).unwrap();
+      }
+    }
+  }
+
+  /// Emits whether do not disturb is on immediately and then whenever it or
+  /// the mode may have changed.
+  pub fn subscribe(&self) -> impl Stream<Item = bool> {
+    let (sink, stream) = unbounded::<bool>();
+    sink.unbounded_send(self.active.get()).unwrap();
+    self.subscribers.borrow_mut().push(sink);
+
+    stream
+  }
+}
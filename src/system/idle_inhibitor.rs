@@ -0,0 +1,102 @@
+use crate::config::IdleInhibitorConfig;
+use crate::system::logind::Logind;
+use dbus::arg::OwnedFd;
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use futures::prelude::*;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+/// Keeps the system from idling (and optionally sleeping) while enabled.
+#[derive(Clone)]
+pub struct IdleInhibitor {
+  logind: Rc<Logind>,
+  what: &'static str,
+  expire_after_minutes: Option<u32>,
+  inhibitor: Rc<RefCell<Option<OwnedFd>>>,
+  /// Set while waiting for logind, so that enabling twice takes one lock.
+  enabling: Rc<Cell<bool>>,
+  expiry: Rc<RefCell<Option<glib::SourceId>>>,
+  subscribers: Rc<RefCell<Vec<UnboundedSender<bool>>>>,
+}
+
+impl IdleInhibitor {
+  pub fn new(logind: Rc<Logind>, config: &IdleInhibitorConfig) -> IdleInhibitor {
+    IdleInhibitor {
+      logind,
+      what: if config.sleep { "idle:sleep" } else { "idle" },
+      expire_after_minutes: config.expire_after_minutes,
+      inhibitor: Rc::new(RefCell::new(None)),
+      enabling: Rc::new(Cell::new(false)),
+      expiry: Rc::new(RefCell::new(None)),
+      subscribers: Rc::new(RefCell::new(vec![])),
+    }
+  }
+
+  pub fn is_active(&self) -> bool {
+    self.inhibitor.borrow().is_some()
+  }
+
+  pub fn expire_after_minutes(&self) -> Option<u32> {
+    self.expire_after_minutes
+  }
+
+  pub async fn enable(&self) -> Result<(), dbus::Error> {
+    if self.is_active() || self.enabling.replace(true) {
+      return Ok(());
+    }
+
+    let fd = self
+      .logind
+      .inhibit(self.what, "Keeping the system awake", "block")
+      .await;
+    // Disabling while waiting clears the flag, and dropping the fd unlocks
+    if !self.enabling.replace(false) {
+      return fd.map(|_| ());
+    }
+    *self.inhibitor.borrow_mut() = Some(fd?);
+
+    if let Some(minutes) = self.expire_after_minutes {
+      let idle_inhibitor = self.clone();
+      let source = gtk::timeout_add_seconds(minutes * 60, move || {
+        // The source is removed by returning false
+        idle_inhibitor.expiry.borrow_mut().take();
+        idle_inhibitor.disable();
+        gtk::Continue(false)
+      });
+      if let Some(previous) = self.expiry.borrow_mut().replace(source) {
+        glib::source_remove(previous);
+      }
+    }
+
+    self.update_subscribers();
+
+    Ok(())
+  }
+
+  pub fn disable(&self) {
+    self.enabling.set(false);
+    if let Some(source) = self.expiry.borrow_mut().take() {
+      glib::source_remove(source);
+    }
+
+    if self.inhibitor.borrow_mut().take().is_some() {
+      self.update_subscribers();
+    }
+  }
+
+  pub fn update_subscribers(&self) {
+    let active = self.is_active();
+    for subscriber in self.subscribers.borrow().iter() {
+      if !subscriber.is_closed() {
+        subscriber.unbounded_send(active).unwrap();
+      }
+    }
+  }
+
+  pub fn subscribe(&self) -> impl Stream<Item = bool> {
+    let (sink, stream) = unbounded::<bool>();
+    self.subscribers.borrow_mut().push(sink);
+
+    stream
+  }
+}
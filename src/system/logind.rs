@@ -0,0 +1,449 @@
+use crate::system::bus::Bus;
+use dbus::arg::OwnedFd;
+use dbus::strings::Path;
+use futures::prelude::*;
+use futures::stream;
+use log::{error, warn};
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::process;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const LOGIND: &str = "org.freedesktop.login1";
+const MANAGER_PATH: &str = "/org/freedesktop/login1";
+const MANAGER: &str = "org.freedesktop.login1.Manager";
+const SESSION: &str = "org.freedesktop.login1.Session";
+
+/// Answer of the logind `Can*` methods.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Capability {
+  Yes,
+  Challenge,
+  No,
+  NotApplicable,
+}
+
+impl Capability {
+  fn from_str(value: &str) -> Capability {
+    match value {
+      "yes" => Capability::Yes,
+      "challenge" => Capability::Challenge,
+      "no" => Capability::No,
+      _ => Capability::NotApplicable,
+    }
+  }
+
+  pub fn is_available(self) -> bool {
+    self == Capability::Yes || self == Capability::Challenge
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PowerAction {
+  LogOut,
+  Suspend,
+  Hibernate,
+  HybridSleep,
+  SuspendThenHibernate,
+  Reboot,
+  PowerOff,
+}
+
+impl PowerAction {
+  pub const ALL: [PowerAction; 7] = [
+    PowerAction::LogOut,
+    PowerAction::Suspend,
+    PowerAction::Hibernate,
+    PowerAction::HybridSleep,
+    PowerAction::SuspendThenHibernate,
+    PowerAction::Reboot,
+    PowerAction::PowerOff,
+  ];
+
+  fn method(self) -> &'static str {
+    match self {
+      PowerAction::LogOut => "Terminate",
+      PowerAction::Suspend => "Suspend",
+      PowerAction::Hibernate => "Hibernate",
+      PowerAction::HybridSleep => "HybridSleep",
+      PowerAction::SuspendThenHibernate => "SuspendThenHibernate",
+      PowerAction::Reboot => "Reboot",
+      PowerAction::PowerOff => "PowerOff",
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct Inhibitor {
+  pub what: String,
+  pub who: String,
+  pub why: String,
+  pub mode: String,
+  pub uid: u32,
+  pub pid: u32,
+}
+
+impl Inhibitor {
+  pub fn inhibits(&self, what: &str) -> bool {
+    self.what.split(':').any(|inhibited| inhibited == what)
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShutdownKind {
+  PowerOff,
+  Reboot,
+}
+
+impl ShutdownKind {
+  fn as_str(self) -> &'static str {
+    match self {
+      ShutdownKind::PowerOff => "poweroff",
+      ShutdownKind::Reboot => "reboot",
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScheduledShutdown {
+  pub kind: ShutdownKind,
+  pub time: SystemTime,
+}
+
+async fn read_scheduled_shutdown(bus: Bus) -> Result<Option<ScheduledShutdown>, dbus::Error> {
+  let (kind, usec): (String, u64) = bus
+    .get_property(LOGIND, MANAGER_PATH, MANAGER, "ScheduledShutdown")
+    .await?;
+
+  if kind.is_empty() || usec == 0 {
+    return Ok(None);
+  }
+
+  // Also covers the dry-run variants used for testing
+  let kind = if kind.ends_with("reboot") {
+    ShutdownKind::Reboot
+  } else {
+    ShutdownKind::PowerOff
+  };
+
+  Ok(Some(ScheduledShutdown {
+    kind,
+    time: UNIX_EPOCH + Duration::from_micros(usec),
+  }))
+}
+
+fn no_session_error() -> dbus::Error {
+  dbus::Error::new_custom(
+    "org.freedesktop.login1.NoSessionForPID",
+    "The panel is not part of a login session",
+  )
+}
+
+pub struct Logind {
+  bus: Bus,
+  session: Option<Path<'static>>,
+}
+
+impl Logind {
+  pub async fn new(bus: Bus) -> Logind {
+    let session = match bus
+      .call(
+        LOGIND,
+        MANAGER_PATH,
+        MANAGER,
+        "GetSessionByPID",
+        (process::id(),),
+      )
+      .await
+    {
+      Ok((session,)) => Some(session),
+      Err(error) => {
+        warn!("Could not find the login1 session of the panel: {}", error);
+        None
+      }
+    };
+
+    Logind { bus, session }
+  }
+
+  pub fn has_session(&self) -> bool {
+    self.session.is_some()
+  }
+
+  async fn call_manager(&self, method: &str) -> Result<(), dbus::Error> {
+    self
+      .bus
+      .call(LOGIND, MANAGER_PATH, MANAGER, method, (true,))
+      .await
+  }
+
+  async fn ask_manager(&self, method: &str) -> Result<Capability, dbus::Error> {
+    let (answer,): (String,) = self
+      .bus
+      .call(LOGIND, MANAGER_PATH, MANAGER, method, ())
+      .await?;
+
+    Ok(Capability::from_str(&answer))
+  }
+
+  async fn call_session(&self, method: &str) -> Result<(), dbus::Error> {
+    let session = self.session.as_ref().ok_or_else(no_session_error)?;
+
+    self.bus.call(LOGIND, session, SESSION, method, ()).await
+  }
+
+  pub async fn can(&self, action: PowerAction) -> Result<Capability, dbus::Error> {
+    match action {
+      PowerAction::LogOut if self.has_session() => Ok(Capability::Yes),
+      PowerAction::LogOut => Ok(Capability::NotApplicable),
+      _ => self.ask_manager(&format!("Can{}", action.method())).await,
+    }
+  }
+
+  pub async fn perform(&self, action: PowerAction) -> Result<(), dbus::Error> {
+    match action {
+      PowerAction::LogOut => self.call_session(action.method()).await,
+      _ => self.call_manager(action.method()).await,
+    }
+  }
+
+  pub async fn can_reboot_to_firmware_setup(&self) -> Result<Capability, dbus::Error> {
+    self.ask_manager("CanRebootToFirmwareSetup").await
+  }
+
+  pub async fn can_reboot_to_boot_loader_entry(&self) -> Result<Capability, dbus::Error> {
+    self.ask_manager("CanRebootToBootLoaderEntry").await
+  }
+
+  pub async fn boot_loader_entries(&self) -> Result<Vec<String>, dbus::Error> {
+    self
+      .bus
+      .get_property(LOGIND, MANAGER_PATH, MANAGER, "BootLoaderEntries")
+      .await
+  }
+
+  pub async fn set_reboot_to_firmware_setup(&self, enable: bool) -> Result<(), dbus::Error> {
+    self
+      .bus
+      .call(
+        LOGIND,
+        MANAGER_PATH,
+        MANAGER,
+        "SetRebootToFirmwareSetup",
+        (enable,),
+      )
+      .await
+  }
+
+  pub async fn set_reboot_to_boot_loader_entry(&self, entry: &str) -> Result<(), dbus::Error> {
+    self
+      .bus
+      .call(
+        LOGIND,
+        MANAGER_PATH,
+        MANAGER,
+        "SetRebootToBootLoaderEntry",
+        (entry,),
+      )
+      .await
+  }
+
+  pub async fn schedule_shutdown(
+    &self,
+    kind: ShutdownKind,
+    time: SystemTime,
+  ) -> Result<(), dbus::Error> {
+    let usec = time
+      .duration_since(UNIX_EPOCH)
+      .map(|since_epoch| since_epoch.as_micros() as u64)
+      .unwrap_or(0);
+
+    self
+      .bus
+      .call(
+        LOGIND,
+        MANAGER_PATH,
+        MANAGER,
+        "ScheduleShutdown",
+        (kind.as_str(), usec),
+      )
+      .await
+  }
+
+  pub async fn cancel_scheduled_shutdown(&self) -> Result<bool, dbus::Error> {
+    let (cancelled,): (bool,) = self
+      .bus
+      .call(LOGIND, MANAGER_PATH, MANAGER, "CancelScheduledShutdown", ())
+      .await?;
+
+    Ok(cancelled)
+  }
+
+  /// Emits the current scheduled shutdown and then every change to it,
+  /// including shutdowns scheduled by other tools.
+  pub fn subscribe_to_scheduled_shutdown(&self) -> impl Stream<Item = Option<ScheduledShutdown>> {
+    let bus = self.bus.clone();
+
+    stream::once(future::ready(()))
+      .chain(self.bus.subscribe_to_properties(LOGIND, Some(MANAGER_PATH)))
+      .then(move |_| read_scheduled_shutdown(bus.clone()))
+      .filter_map(|scheduled_shutdown| {
+        future::ready(match scheduled_shutdown {
+          Ok(scheduled_shutdown) => Some(scheduled_shutdown),
+          Err(error) => {
+            warn!("Could not read the scheduled shutdown: {}", error);
+            None
+          }
+        })
+      })
+  }
+
+  pub async fn list_inhibitors(&self) -> Result<Vec<Inhibitor>, dbus::Error> {
+    let (inhibitors,): (Vec<(String, String, String, String, u32, u32)>,) = self
+      .bus
+      .call(LOGIND, MANAGER_PATH, MANAGER, "ListInhibitors", ())
+      .await?;
+
+    Ok(
+      inhibitors
+        .into_iter()
+        .map(|(what, who, why, mode, uid, pid)| Inhibitor {
+          what,
+          who,
+          why,
+          mode,
+          uid,
+          pid,
+        })
+        .collect(),
+    )
+  }
+
+  pub async fn lock_session(&self) -> Result<(), dbus::Error> {
+    self.call_session("Lock").await
+  }
+
+  pub async fn set_brightness(
+    &self,
+    subsystem: &str,
+    name: &str,
+    brightness: u32,
+  ) -> Result<(), dbus::Error> {
+    let session = self.session.as_ref().ok_or_else(no_session_error)?;
+
+    self
+      .bus
+      .call(
+        LOGIND,
+        session,
+        SESSION,
+        "SetBrightness",
+        (subsystem, name, brightness),
+      )
+      .await
+  }
+
+  pub async fn is_locked(&self) -> Result<bool, dbus::Error> {
+    match self.session {
+      Some(ref session) => {
+        self
+          .bus
+          .get_property(LOGIND, session, SESSION, "LockedHint")
+          .await
+      }
+      None => Ok(false),
+    }
+  }
+
+  /// Takes an inhibitor lock that is held until the returned fd is dropped.
+  pub async fn inhibit(
+    &self,
+    what: &str,
+    why: &str,
+    mode: &str,
+  ) -> Result<OwnedFd, dbus::Error> {
+    let (fd,): (OwnedFd,) = self
+      .bus
+      .call(
+        LOGIND,
+        MANAGER_PATH,
+        MANAGER,
+        "Inhibit",
+        (what, "Panel", why, mode),
+      )
+      .await?;
+
+    Ok(fd)
+  }
+
+  /// Emits true just before the system goes to sleep and false after it
+  /// has resumed.
+  pub fn subscribe_to_prepare_for_sleep(&self) -> impl Stream<Item = bool> {
+    self
+      .bus
+      .subscribe_to_signal(LOGIND, Some(MANAGER_PATH), MANAGER, "PrepareForSleep")
+      .filter_map(|message| future::ready(message.get1::<bool>()))
+  }
+
+  pub fn subscribe_to_resume(&self) -> impl Stream<Item = ()> {
+    self
+      .subscribe_to_prepare_for_sleep()
+      .filter_map(|sleeping| future::ready(if sleeping { None } else { Some(()) }))
+  }
+}
+
+async fn take_sleep_inhibitor(logind: &Logind) -> Option<OwnedFd> {
+  match logind
+    .inhibit("sleep", "Lock the screen before suspending", "delay")
+    .await
+  {
+    Ok(fd) => Some(fd),
+    Err(error) => {
+      warn!("Could not take a sleep inhibitor: {}", error);
+      None
+    }
+  }
+}
+
+/// Waits a short while for the screen locker to report that the session is
+/// locked.
+async fn wait_for_lock(logind: &Logind) {
+  for _ in 0..20 {
+    if let Ok(true) = logind.is_locked().await {
+      return;
+    }
+    glib::timeout_future(100).await;
+  }
+
+  warn!("The session did not report being locked in time");
+}
+
+/// Holds a sleep delay inhibitor so that the session can be locked before
+/// the system suspends, which makes sure the screen is already locked on
+/// resume.
+pub async fn lock_before_sleep(logind: Rc<Logind>) {
+  let inhibitor = Rc::new(RefCell::new(take_sleep_inhibitor(&logind).await));
+
+  logind
+    .subscribe_to_prepare_for_sleep()
+    .for_each(move |sleeping| {
+      let logind = logind.clone();
+      let inhibitor = inhibitor.clone();
+
+      async move {
+        if sleeping {
+          match logind.lock_session().await {
+            Ok(()) => wait_for_lock(&logind).await,
+            Err(error) => error!("Failed to lock the session before sleeping: {}", error),
+          }
+          inhibitor.borrow_mut().take();
+        } else if inhibitor.borrow().is_none() {
+          let fd = take_sleep_inhibitor(&logind).await;
+          *inhibitor.borrow_mut() = fd;
+        }
+      }
+    })
+    .await;
+}
@@ -0,0 +1,324 @@
+use crate::system::bus::{prop_bool, prop_str, Bus};
+use dbus::arg::RefArg;
+use dbus::message::{MatchRule, MessageType};
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use futures::prelude::*;
+use futures::stream;
+use log::warn;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+const PLAYER_PREFIX: &str = "org.mpris.MediaPlayer2.";
+const MPRIS_PATH: &str = "/org/mpris/MediaPlayer2";
+const MEDIA_PLAYER: &str = "org.mpris.MediaPlayer2";
+const PLAYER: &str = "org.mpris.MediaPlayer2.Player";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlaybackStatus {
+  Playing,
+  Paused,
+  Stopped,
+}
+
+#[derive(Clone, Debug)]
+pub struct Player {
+  /// The bus name of the player.
+  pub name: String,
+  /// Name of the application, for the player selector.
+  pub identity: String,
+  pub status: PlaybackStatus,
+  pub track_id: Option<String>,
+  pub title: Option<String>,
+  pub artists: Vec<String>,
+  pub album: Option<String>,
+  /// Track length in microseconds.
+  pub length: Option<i64>,
+  /// Album art, only when it is a local file.
+  pub art: Option<PathBuf>,
+  pub can_play: bool,
+  pub can_pause: bool,
+  pub can_go_next: bool,
+  pub can_go_previous: bool,
+  pub can_seek: bool,
+}
+
+impl Player {
+  pub fn is_playing(&self) -> bool {
+    self.status == PlaybackStatus::Playing
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct MediaState {
+  pub players: Vec<Player>,
+  /// Bus name of the player the panel controls.
+  pub active: Option<String>,
+}
+
+impl MediaState {
+  pub fn active_player(&self) -> Option<&Player> {
+    let active = self.active.as_ref()?;
+    self.players.iter().find(|player| &player.name == active)
+  }
+}
+
+/// Unwraps values of `a{sv}` dictionaries that were read generically.
+fn variant_value(arg: &dyn RefArg) -> Option<&dyn RefArg> {
+  if &*arg.signature() == "v" {
+    arg.as_iter()?.next()
+  } else {
+    Some(arg)
+  }
+}
+
+fn read_metadata(metadata: &dyn RefArg) -> HashMap<String, &dyn RefArg> {
+  let mut values = HashMap::new();
+
+  if let Some(mut entries) = metadata.as_iter() {
+    while let (Some(key), Some(value)) = (entries.next(), entries.next()) {
+      if let (Some(key), Some(value)) = (key.as_str(), variant_value(value)) {
+        values.insert(key.to_string(), value);
+      }
+    }
+  }
+
+  values
+}
+
+fn art_path(url: &str) -> Option<PathBuf> {
+  if url.starts_with("file://") {
+    glib::filename_from_uri(url).ok().map(|(path, _)| path)
+  } else {
+    None
+  }
+}
+
+async fn read_player(bus: Bus, name: String) -> Result<Player, dbus::Error> {
+  let identity: Result<String, _> = bus
+    .get_property(&name, MPRIS_PATH, MEDIA_PLAYER, "Identity")
+    .await;
+  let properties = bus.get_all_properties(&name, MPRIS_PATH, PLAYER).await?;
+
+  let metadata = properties
+    .get("Metadata")
+    .map(|metadata| read_metadata(&metadata.0))
+    .unwrap_or_default();
+  let string = |key: &str| {
+    metadata
+      .get(key)
+      .and_then(|value| value.as_str())
+      .filter(|value| !value.is_empty())
+      .map(|value| value.to_string())
+  };
+
+  let status = match prop_str(&properties, "PlaybackStatus").as_ref().map(String::as_str) {
+    Some("Playing") => PlaybackStatus::Playing,
+    Some("Paused") => PlaybackStatus::Paused,
+    _ => PlaybackStatus::Stopped,
+  };
+  let can = |key: &str| prop_bool(&properties, key).unwrap_or(false);
+
+  Ok(Player {
+    identity: identity.unwrap_or_else(|_| name.trim_start_matches(PLAYER_PREFIX).to_string()),
+    status,
+    track_id: string("mpris:trackid"),
+    title: string("xesam:title"),
+    artists: metadata
+      .get("xesam:artist")
+      .and_then(|artists| artists.as_iter())
+      .map(|artists| {
+        artists
+          .filter_map(|artist| artist.as_str())
+          .map(|artist| artist.to_string())
+          .collect()
+      })
+      .unwrap_or_default(),
+    album: string("xesam:album"),
+    length: metadata
+      .get("mpris:length")
+      .and_then(|length| length.as_i64().or_else(|| length.as_u64().map(|length| length as i64)))
+      .filter(|length| *length > 0),
+    art: string("mpris:artUrl").and_then(|url| art_path(&url)),
+    can_play: can("CanPlay"),
+    can_pause: can("CanPause"),
+    can_go_next: can("CanGoNext"),
+    can_go_previous: can("CanGoPrevious"),
+    can_seek: can("CanSeek"),
+    name,
+  })
+}
+
+async fn read_players(bus: Bus) -> Result<Vec<Player>, dbus::Error> {
+  let (names,): (Vec<String>,) = bus
+    .call(
+      "org.freedesktop.DBus",
+      "/org/freedesktop/DBus",
+      "org.freedesktop.DBus",
+      "ListNames",
+      (),
+    )
+    .await?;
+
+  let mut players = vec![];
+  for name in names.into_iter().filter(|name| name.starts_with(PLAYER_PREFIX)) {
+    match read_player(bus.clone(), name).await {
+      Ok(player) => players.push(player),
+      Err(error) => warn!("Could not read a media player: {}", error),
+    }
+  }
+  players.sort_by(|a, b| a.identity.cmp(&b.identity));
+
+  Ok(players)
+}
+
+/// The selected player while it exists, or else the first one playing.
+fn pick_active(selected: &Option<String>, players: &[Player]) -> Option<String> {
+  players
+    .iter()
+    .find(|player| Some(&player.name) == selected.as_ref())
+    .or_else(|| players.iter().find(|player| player.is_playing()))
+    .or_else(|| players.first())
+    .map(|player| player.name.clone())
+}
+
+/// Media players on the session bus that implement MPRIS.
+pub struct Mpris {
+  bus: Bus,
+  /// The player picked by the user, if any.
+  selected: Rc<RefCell<Option<String>>>,
+  subscribers: Rc<RefCell<Vec<UnboundedSender<()>>>>,
+}
+
+impl Mpris {
+  pub fn new(bus: Bus) -> Mpris {
+    Mpris {
+      bus,
+      selected: Rc::new(RefCell::new(None)),
+      subscribers: Rc::new(RefCell::new(vec![])),
+    }
+  }
+
+  /// Controls `name` from now on, instead of the one that is playing.
+  pub fn select(&self, name: &str) {
+    self.selected.replace(Some(name.to_string()));
+
+    for subscriber in self.subscribers.borrow().iter() {
+      if !subscriber.is_closed() {
+        subscriber.unbounded_send(()).unwrap();
+      }
+    }
+  }
+
+  pub async fn play_pause(&self, player: &Player) -> Result<(), dbus::Error> {
+    self
+      .bus
+      .call(&player.name, MPRIS_PATH, PLAYER, "PlayPause", ())
+      .await
+  }
+
+  pub async fn next(&self, player: &Player) -> Result<(), dbus::Error> {
+    self
+      .bus
+      .call(&player.name, MPRIS_PATH, PLAYER, "Next", ())
+      .await
+  }
+
+  pub async fn previous(&self, player: &Player) -> Result<(), dbus::Error> {
+    self
+      .bus
+      .call(&player.name, MPRIS_PATH, PLAYER, "Previous", ())
+      .await
+  }
+
+  /// The position in the current track in microseconds, which players do
+  /// not signal changes of.
+  pub async fn get_position(&self, player: &Player) -> Result<i64, dbus::Error> {
+    self
+      .bus
+      .get_property(&player.name, MPRIS_PATH, PLAYER, "Position")
+      .await
+  }
+
+  /// Seeks to `position` microseconds into the current track.
+  pub async fn set_position(&self, player: &Player, position: i64) -> Result<(), dbus::Error> {
+    match player.track_id {
+      Some(ref track_id) => {
+        let track_id = dbus::Path::new(track_id.as_str())
+          .map_err(|error| dbus::Error::new_custom("org.freedesktop.DBus.Error.Failed", &error))?;
+
+        self
+          .bus
+          .call(
+            &player.name,
+            MPRIS_PATH,
+            PLAYER,
+            "SetPosition",
+            (track_id, position),
+          )
+          .await
+      }
+      None => {
+        let offset = position - self.get_position(player).await?;
+
+        self
+          .bus
+          .call(&player.name, MPRIS_PATH, PLAYER, "Seek", (offset,))
+          .await
+      }
+    }
+  }
+
+  /// Emits the players whenever one appears, disappears or changes.
+  pub fn subscribe(&self) -> impl Stream<Item = MediaState> {
+    let bus = self.bus.clone();
+    let selected = self.selected.clone();
+
+    let players_changed = self
+      .bus
+      .subscribe_to_signal(
+        "org.freedesktop.DBus",
+        Some("/org/freedesktop/DBus"),
+        "org.freedesktop.DBus",
+        "NameOwnerChanged",
+      )
+      .filter(|message| {
+        future::ready(
+          message
+            .read1::<&str>()
+            .map_or(false, |name| name.starts_with(PLAYER_PREFIX)),
+        )
+      })
+      .map(|_| ());
+
+    let mut rule = MatchRule::new();
+    rule.msg_type = Some(MessageType::Signal);
+    rule.path = Some(MPRIS_PATH.to_string().into());
+    rule.interface = Some("org.freedesktop.DBus.Properties".to_string().into());
+    rule.member = Some("PropertiesChanged".to_string().into());
+    let properties_changed = self.bus.subscribe(rule).map(|_| ());
+
+    let (sink, selection_changed) = unbounded::<()>();
+    self.subscribers.borrow_mut().push(sink);
+
+    stream::once(future::ready(()))
+      .chain(stream::select(
+        stream::select(players_changed, properties_changed),
+        selection_changed,
+      ))
+      .then(move |_| read_players(bus.clone()))
+      .filter_map(move |result| {
+        future::ready(match result {
+          Ok(players) => Some(MediaState {
+            active: pick_active(&selected.borrow(), &players),
+            players,
+          }),
+          Err(error) => {
+            warn!("Could not read the media players: {}", error);
+            None
+          }
+        })
+      })
+  }
+}
@@ -0,0 +1,915 @@
+use crate::system::bus::{prop_bool, prop_str, prop_u64, Bus, Properties};
+use dbus::arg::{RefArg, Variant};
+use dbus::strings::Path;
+use dbus::Message;
+use futures::future::join_all;
+use futures::prelude::*;
+use futures::stream;
+use glib::MainContext;
+use log::warn;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+const NM: &str = "org.freedesktop.NetworkManager";
+const NM_PATH: &str = "/org/freedesktop/NetworkManager";
+const ACTIVE_CONNECTION: &str = "org.freedesktop.NetworkManager.Connection.Active";
+const ACCESS_POINT: &str = "org.freedesktop.NetworkManager.AccessPoint";
+const DEVICE: &str = "org.freedesktop.NetworkManager.Device";
+const WIRELESS: &str = "org.freedesktop.NetworkManager.Device.Wireless";
+const SETTINGS_PATH: &str = "/org/freedesktop/NetworkManager/Settings";
+const SETTINGS: &str = "org.freedesktop.NetworkManager.Settings";
+const SETTINGS_CONNECTION: &str = "org.freedesktop.NetworkManager.Settings.Connection";
+const AGENT_MANAGER_PATH: &str = "/org/freedesktop/NetworkManager/AgentManager";
+const AGENT_MANAGER: &str = "org.freedesktop.NetworkManager.AgentManager";
+const SECRET_AGENT_PATH: &str = "/org/freedesktop/NetworkManager/SecretAgent";
+const SECRET_AGENT_ID: &str = "com.github.pajn.panel";
+const USER_CANCELED: &str = "org.freedesktop.NetworkManager.SecretAgent.UserCanceled";
+const NO_SECRETS: &str = "org.freedesktop.NetworkManager.SecretAgent.NoSecrets";
+
+const NM_STATE_ASLEEP: u32 = 10;
+const NM_STATE_CONNECTING: u32 = 40;
+const NM_DEVICE_TYPE_WIFI: u32 = 2;
+const NM_802_11_AP_FLAGS_PRIVACY: u32 = 0x1;
+const NM_802_11_AP_SEC_KEY_MGMT_PSK: u32 = 0x100;
+const NM_802_11_AP_SEC_KEY_MGMT_802_1X: u32 = 0x200;
+const NM_802_11_AP_SEC_KEY_MGMT_SAE: u32 = 0x400;
+const NM_SECRET_AGENT_GET_SECRETS_FLAG_ALLOW_INTERACTION: u32 = 0x1;
+
+const WIRELESS_SECURITY_SETTING: &str = "802-11-wireless-security";
+
+pub type ConnectionSettings = HashMap<String, HashMap<String, Variant<Box<dyn RefArg>>>>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Connectivity {
+  Unknown,
+  None,
+  Portal,
+  Limited,
+  Full,
+}
+
+impl Connectivity {
+  fn from_u32(value: u32) -> Connectivity {
+    match value {
+      1 => Connectivity::None,
+      2 => Connectivity::Portal,
+      3 => Connectivity::Limited,
+      4 => Connectivity::Full,
+      _ => Connectivity::Unknown,
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConnectionKind {
+  Wired,
+  Wifi { strength: u8 },
+  Cellular,
+  Vpn,
+  Other,
+}
+
+#[derive(Clone, Debug)]
+pub struct PrimaryConnection {
+  pub kind: ConnectionKind,
+  pub name: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct NetworkState {
+  pub asleep: bool,
+  pub connecting: bool,
+  pub connectivity: Connectivity,
+  pub primary_connection: Option<PrimaryConnection>,
+  /// Names of the VPN and WireGuard connections that are up.
+  pub active_vpns: Vec<String>,
+  /// URL NetworkManager checks connectivity with, which a captive portal
+  /// redirects to its sign in page.
+  pub connectivity_check_uri: Option<String>,
+}
+
+impl NetworkState {
+  pub fn icon_name(&self) -> &'static str {
+    let limited = self.connectivity == Connectivity::Limited
+      || self.connectivity == Connectivity::Portal
+      || self.connectivity == Connectivity::None;
+
+    match self.primary_connection {
+      None if self.connecting => "network-wireless-acquiring-symbolic",
+      None => "network-offline-symbolic",
+      Some(ref connection) => match connection.kind {
+        ConnectionKind::Wired if limited => "network-wired-no-route-symbolic",
+        ConnectionKind::Wired | ConnectionKind::Other => "network-wired-symbolic",
+        ConnectionKind::Wifi { .. } if limited => "network-wireless-no-route-symbolic",
+        ConnectionKind::Wifi { strength } => match strength {
+          0..=19 => "network-wireless-signal-none-symbolic",
+          20..=39 => "network-wireless-signal-weak-symbolic",
+          40..=59 => "network-wireless-signal-ok-symbolic",
+          60..=79 => "network-wireless-signal-good-symbolic",
+          _ => "network-wireless-signal-excellent-symbolic",
+        },
+        ConnectionKind::Cellular if limited => "network-cellular-no-route-symbolic",
+        ConnectionKind::Cellular => "network-cellular-connected-symbolic",
+        ConnectionKind::Vpn => "network-vpn-symbolic",
+      },
+    }
+  }
+
+  pub fn description(&self) -> String {
+    match self.primary_connection {
+      None if self.asleep => "Networking disabled".to_string(),
+      None if self.connecting => "Connecting".to_string(),
+      None => "Disconnected".to_string(),
+      Some(ref connection) => match self.connectivity {
+        Connectivity::Portal => format!("{}: sign in required", connection.name),
+        Connectivity::Limited | Connectivity::None => {
+          format!("{}: limited connectivity", connection.name)
+        }
+        _ => format!("Connected to {}", connection.name),
+      },
+    }
+  }
+
+  pub fn is_vpn_active(&self) -> bool {
+    !self.active_vpns.is_empty()
+  }
+}
+
+async fn read_primary_connection(
+  bus: &Bus,
+  path: &str,
+) -> Result<PrimaryConnection, dbus::Error> {
+  let properties = bus.get_all_properties(NM, path, ACTIVE_CONNECTION).await?;
+  let connection_type = prop_str(&properties, "Type").unwrap_or_default();
+
+  let kind = match connection_type.as_str() {
+    "802-3-ethernet" => ConnectionKind::Wired,
+    "802-11-wireless" => {
+      let access_point = prop_str(&properties, "SpecificObject").unwrap_or_default();
+      let strength: u8 = if access_point.len() > 1 {
+        bus
+          .get_property(NM, &access_point, ACCESS_POINT, "Strength")
+          .await
+          .unwrap_or(0)
+      } else {
+        0
+      };
+      ConnectionKind::Wifi { strength }
+    }
+    "gsm" | "cdma" => ConnectionKind::Cellular,
+    "vpn" | "wireguard" => ConnectionKind::Vpn,
+    _ if prop_bool(&properties, "Vpn").unwrap_or(false) => ConnectionKind::Vpn,
+    _ => ConnectionKind::Other,
+  };
+
+  Ok(PrimaryConnection {
+    kind,
+    name: prop_str(&properties, "Id").unwrap_or_default(),
+  })
+}
+
+fn prop_paths(properties: &Properties, key: &str) -> Vec<String> {
+  properties
+    .get(key)
+    .and_then(|value| value.0.as_iter())
+    .map(|paths| {
+      paths
+        .filter_map(|path| path.as_str().map(|path| path.to_string()))
+        .collect()
+    })
+    .unwrap_or_default()
+}
+
+fn setting_str(settings: &ConnectionSettings, setting: &str, key: &str) -> Option<String> {
+  settings
+    .get(setting)
+    .and_then(|values| values.get(key))
+    .and_then(|value| value.0.as_str())
+    .map(|value| value.to_string())
+}
+
+fn is_vpn_type(connection_type: &str) -> bool {
+  connection_type == "vpn" || connection_type == "wireguard"
+}
+
+struct ActiveConnection {
+  path: String,
+  /// Path of the settings the connection was activated from.
+  connection: String,
+  name: String,
+  is_vpn: bool,
+}
+
+async fn read_active_connection(bus: Bus, path: String) -> Result<ActiveConnection, dbus::Error> {
+  let properties = bus.get_all_properties(NM, &path, ACTIVE_CONNECTION).await?;
+  let connection_type = prop_str(&properties, "Type").unwrap_or_default();
+
+  Ok(ActiveConnection {
+    path,
+    connection: prop_str(&properties, "Connection").unwrap_or_default(),
+    name: prop_str(&properties, "Id").unwrap_or_default(),
+    is_vpn: prop_bool(&properties, "Vpn").unwrap_or(false) || is_vpn_type(&connection_type),
+  })
+}
+
+/// Reads the active connections, skipping any that deactivate while read.
+async fn read_active_connections(bus: &Bus, properties: &Properties) -> Vec<ActiveConnection> {
+  join_all(
+    prop_paths(properties, "ActiveConnections")
+      .into_iter()
+      .map(|path| read_active_connection(bus.clone(), path)),
+  )
+  .await
+  .into_iter()
+  .filter_map(|result| match result {
+    Ok(connection) => Some(connection),
+    Err(error) => {
+      warn!("Could not read an active connection: {}", error);
+      None
+    }
+  })
+  .collect()
+}
+
+/// The parts of the settings of a saved connection that the panel uses.
+#[derive(Clone, Debug)]
+struct SavedConnection {
+  path: String,
+  id: String,
+  connection_type: String,
+  /// Only set for Wi-Fi connections.
+  ssid: Option<Vec<u8>>,
+}
+
+/// Reads the settings of every saved connection, skipping any that fail.
+async fn read_connections(bus: &Bus) -> Result<Vec<SavedConnection>, dbus::Error> {
+  let (paths,): (Vec<Path<'static>>,) = bus
+    .call(NM, SETTINGS_PATH, SETTINGS, "ListConnections", ())
+    .await?;
+  let mut connections = vec![];
+
+  for path in paths {
+    let result: Result<(ConnectionSettings,), _> = bus
+      .call(NM, &path, SETTINGS_CONNECTION, "GetSettings", ())
+      .await;
+
+    match result {
+      Ok((settings,)) => connections.push(SavedConnection {
+        id: setting_str(&settings, "connection", "id").unwrap_or_default(),
+        connection_type: setting_str(&settings, "connection", "type").unwrap_or_default(),
+        ssid: settings
+          .get("802-11-wireless")
+          .and_then(|wireless| wireless.get("ssid"))
+          .map(variant_bytes),
+        path: path.to_string(),
+      }),
+      Err(error) => warn!("Could not read the settings of {}: {}", path, error),
+    }
+  }
+
+  Ok(connections)
+}
+
+/// The saved connections, read again only after NetworkManager signals that
+/// they changed.
+#[derive(Clone)]
+struct ConnectionCache {
+  bus: Bus,
+  connections: Rc<RefCell<Option<Rc<Vec<SavedConnection>>>>>,
+  /// Counts the changes, so that a read that overlaps one is not kept.
+  generation: Rc<Cell<u64>>,
+}
+
+impl ConnectionCache {
+  fn new(bus: Bus) -> ConnectionCache {
+    let cache = ConnectionCache {
+      bus: bus.clone(),
+      connections: Rc::new(RefCell::new(None)),
+      generation: Rc::new(Cell::new(0)),
+    };
+
+    let changed = stream::select(
+      stream::select(
+        bus.subscribe_to_signal(NM, Some(SETTINGS_PATH), SETTINGS, "NewConnection"),
+        bus.subscribe_to_signal(NM, Some(SETTINGS_PATH), SETTINGS, "ConnectionRemoved"),
+      ),
+      bus.subscribe_to_signal(NM, None, SETTINGS_CONNECTION, "Updated"),
+    );
+    let invalidated = cache.clone();
+    MainContext::default().spawn_local(changed.for_each(move |_| {
+      invalidated.generation.set(invalidated.generation.get() + 1);
+      invalidated.connections.replace(None);
+
+      future::ready(())
+    }));
+
+    cache
+  }
+
+  async fn get(&self) -> Result<Rc<Vec<SavedConnection>>, dbus::Error> {
+    let cached = self.connections.borrow().clone();
+    if let Some(connections) = cached {
+      return Ok(connections);
+    }
+
+    let generation = self.generation.get();
+    let connections = Rc::new(read_connections(&self.bus).await?);
+    if self.generation.get() == generation {
+      self.connections.replace(Some(connections.clone()));
+    }
+
+    Ok(connections)
+  }
+}
+
+async fn read_state(bus: Bus) -> Result<NetworkState, dbus::Error> {
+  let properties = bus.get_all_properties(NM, NM_PATH, NM).await?;
+  let state = prop_u64(&properties, "State").unwrap_or(0) as u32;
+  let connectivity = prop_u64(&properties, "Connectivity").unwrap_or(0) as u32;
+
+  let primary_connection = match prop_str(&properties, "PrimaryConnection") {
+    // NetworkManager uses "/" when there is no primary connection
+    Some(ref path) if path.len() > 1 => Some(read_primary_connection(&bus, path).await?),
+    _ => None,
+  };
+
+  let active_vpns = read_active_connections(&bus, &properties)
+    .await
+    .into_iter()
+    .filter(|connection| connection.is_vpn)
+    .map(|connection| connection.name)
+    .collect();
+
+  Ok(NetworkState {
+    asleep: state == NM_STATE_ASLEEP,
+    connecting: state == NM_STATE_CONNECTING,
+    connectivity: Connectivity::from_u32(connectivity),
+    primary_connection,
+    active_vpns,
+    connectivity_check_uri: prop_str(&properties, "ConnectivityCheckUri")
+      .filter(|uri| !uri.is_empty()),
+  })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Security {
+  Open,
+  Wep,
+  Psk,
+  Sae,
+  Enterprise,
+}
+
+impl Security {
+  fn from_flags(flags: u32, wpa_flags: u32, rsn_flags: u32) -> Security {
+    let key_mgmt = wpa_flags | rsn_flags;
+
+    if key_mgmt & NM_802_11_AP_SEC_KEY_MGMT_802_1X != 0 {
+      Security::Enterprise
+    } else if key_mgmt & NM_802_11_AP_SEC_KEY_MGMT_PSK != 0 {
+      Security::Psk
+    } else if key_mgmt & NM_802_11_AP_SEC_KEY_MGMT_SAE != 0 {
+      Security::Sae
+    } else if flags & NM_802_11_AP_FLAGS_PRIVACY != 0 {
+      Security::Wep
+    } else {
+      Security::Open
+    }
+  }
+
+  pub fn is_secured(self) -> bool {
+    self != Security::Open
+  }
+
+  /// Whether connecting for the first time only needs a password, which
+  /// can be asked for before the connection is added.
+  pub fn needs_password(self) -> bool {
+    self == Security::Psk || self == Security::Sae
+  }
+
+  fn key_mgmt(self) -> Option<&'static str> {
+    match self {
+      Security::Psk => Some("wpa-psk"),
+      Security::Sae => Some("sae"),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct AccessPoint {
+  pub path: String,
+  pub ssid: String,
+  pub strength: u8,
+  pub security: Security,
+  pub active: bool,
+  /// Path of a saved connection for this network, if there is one.
+  pub known_connection: Option<String>,
+}
+
+fn variant_bytes(value: &Variant<Box<dyn RefArg>>) -> Vec<u8> {
+  value
+    .0
+    .as_iter()
+    .map(|bytes| bytes.filter_map(|byte| byte.as_u64()).map(|byte| byte as u8).collect())
+    .unwrap_or_default()
+}
+
+fn not_found_error(message: &str) -> dbus::Error {
+  dbus::Error::new_custom("org.freedesktop.NetworkManager.UnknownDevice", message)
+}
+
+async fn find_wifi_device(bus: &Bus) -> Result<Option<String>, dbus::Error> {
+  let (paths,): (Vec<Path<'static>>,) = bus.call(NM, NM_PATH, NM, "GetDevices", ()).await?;
+
+  for path in paths {
+    let device_type: u32 = bus.get_property(NM, &path, DEVICE, "DeviceType").await?;
+    if device_type == NM_DEVICE_TYPE_WIFI {
+      return Ok(Some(path.to_string()));
+    }
+  }
+
+  Ok(None)
+}
+
+/// Maps the SSID of every saved Wi-Fi connection to the connection's path.
+async fn read_known_connections(
+  connections: &ConnectionCache,
+) -> Result<HashMap<Vec<u8>, String>, dbus::Error> {
+  let mut known_connections = HashMap::new();
+
+  for connection in connections.get().await?.iter() {
+    if let Some(ref ssid) = connection.ssid {
+      known_connections.insert(ssid.clone(), connection.path.clone());
+    }
+  }
+
+  Ok(known_connections)
+}
+
+async fn read_access_point(
+  bus: Bus,
+  path: String,
+) -> Result<(String, Vec<u8>, Properties), dbus::Error> {
+  let properties = bus.get_all_properties(NM, &path, ACCESS_POINT).await?;
+  let ssid = properties.get("Ssid").map(variant_bytes).unwrap_or_default();
+
+  Ok((path, ssid, properties))
+}
+
+/// Reads the access points seen by the first Wi-Fi device, one per network,
+/// with the active network first and the rest by signal strength.
+async fn read_access_points(
+  bus: Bus,
+  connections: ConnectionCache,
+) -> Result<Vec<AccessPoint>, dbus::Error> {
+  let device = match find_wifi_device(&bus).await? {
+    Some(device) => device,
+    None => return Ok(vec![]),
+  };
+
+  let device_properties = bus.get_all_properties(NM, &device, WIRELESS).await?;
+  let active_path = prop_str(&device_properties, "ActiveAccessPoint").unwrap_or_default();
+  let (paths,): (Vec<Path<'static>>,) = bus
+    .call(NM, &device, WIRELESS, "GetAllAccessPoints", ())
+    .await?;
+  let known_connections = read_known_connections(&connections).await?;
+
+  let results = join_all(
+    paths
+      .into_iter()
+      .map(|path| read_access_point(bus.clone(), path.to_string())),
+  )
+  .await;
+
+  let mut networks: HashMap<Vec<u8>, AccessPoint> = HashMap::new();
+  for (path, ssid, properties) in results.into_iter().filter_map(Result::ok) {
+    // Hidden networks have no SSID and can not be picked from a list
+    if ssid.is_empty() {
+      continue;
+    }
+
+    let access_point = AccessPoint {
+      active: path == active_path,
+      ssid: String::from_utf8_lossy(&ssid).into_owned(),
+      strength: prop_u64(&properties, "Strength").unwrap_or(0) as u8,
+      security: Security::from_flags(
+        prop_u64(&properties, "Flags").unwrap_or(0) as u32,
+        prop_u64(&properties, "WpaFlags").unwrap_or(0) as u32,
+        prop_u64(&properties, "RsnFlags").unwrap_or(0) as u32,
+      ),
+      known_connection: known_connections.get(&ssid).cloned(),
+      path,
+    };
+
+    let is_better = match networks.get(&ssid) {
+      Some(existing) => {
+        !existing.active && (access_point.active || access_point.strength > existing.strength)
+      }
+      None => true,
+    };
+    if is_better {
+      networks.insert(ssid, access_point);
+    }
+  }
+
+  let mut access_points: Vec<AccessPoint> = networks.into_iter().map(|(_, ap)| ap).collect();
+  access_points.sort_by(|a, b| {
+    b.active
+      .cmp(&a.active)
+      .then(b.strength.cmp(&a.strength))
+  });
+
+  Ok(access_points)
+}
+
+#[derive(Clone, Debug)]
+pub struct VpnConnection {
+  pub path: String,
+  pub name: String,
+  /// Path of the active connection while the VPN is up or connecting.
+  pub active_connection: Option<String>,
+}
+
+impl VpnConnection {
+  pub fn is_active(&self) -> bool {
+    self.active_connection.is_some()
+  }
+}
+
+async fn read_vpn_connections(
+  bus: Bus,
+  connections: ConnectionCache,
+) -> Result<Vec<VpnConnection>, dbus::Error> {
+  let properties = bus.get_all_properties(NM, NM_PATH, NM).await?;
+  let active_connections = read_active_connections(&bus, &properties).await;
+
+  let mut vpn_connections: Vec<VpnConnection> = connections
+    .get()
+    .await?
+    .iter()
+    .filter(|connection| is_vpn_type(&connection.connection_type))
+    .map(|connection| VpnConnection {
+      active_connection: active_connections
+        .iter()
+        .find(|active| active.connection == connection.path)
+        .map(|active| active.path.clone()),
+      name: connection.id.clone(),
+      path: connection.path.clone(),
+    })
+    .collect();
+  vpn_connections.sort_by(|a, b| a.name.cmp(&b.name));
+
+  Ok(vpn_connections)
+}
+
+/// Cancels the prompts that are waiting for the user, by connection path and
+/// setting name.
+type PendingPrompts = Rc<RefCell<HashMap<(String, String), Box<dyn Fn()>>>>;
+
+/// A request from NetworkManager for a secret it needs to connect.
+pub struct SecretRequest {
+  /// User visible name of the connection, usually the SSID.
+  pub connection_name: String,
+  bus: Bus,
+  message: Message,
+  setting_name: String,
+  key: &'static str,
+  connection_path: String,
+  pending: PendingPrompts,
+}
+
+impl SecretRequest {
+  /// Replies to NetworkManager, `None` meaning that the user canceled.
+  pub fn reply(self, secret: Option<String>) {
+    self
+      .pending
+      .borrow_mut()
+      .remove(&(self.connection_path.clone(), self.setting_name.clone()));
+
+    let reply = match secret {
+      Some(secret) => {
+        let mut setting: HashMap<&str, Variant<Box<dyn RefArg>>> = HashMap::new();
+        setting.insert(self.key, Variant(Box::new(secret)));
+        let mut secrets = HashMap::new();
+        secrets.insert(self.setting_name.as_str(), setting);
+
+        self.message.method_return().append1(secrets)
+      }
+      None => error_reply(&self.message, USER_CANCELED, "The user canceled"),
+    };
+
+    if let Err(error) = self.bus.send(reply) {
+      warn!("Could not reply to NetworkManager: {}", error);
+    }
+  }
+}
+
+fn error_reply(message: &Message, name: &str, text: &str) -> Message {
+  Message::new_error(message, name, text).expect("Could not create D-Bus error reply")
+}
+
+/// Reads a GetSecrets call, returning the error reply for requests the panel
+/// can not prompt for, like enterprise or VPN secrets.
+fn read_secret_request(
+  bus: &Bus,
+  pending: &PendingPrompts,
+  message: Message,
+) -> Result<SecretRequest, Message> {
+  let no_secrets = |message: &Message| error_reply(message, NO_SECRETS, "No secrets available");
+  let (settings, connection_path, setting_name, _, flags) = match message
+    .read5::<ConnectionSettings, Path, String, Vec<String>, u32>()
+  {
+    Ok(args) => args,
+    Err(error) => {
+      warn!("Invalid GetSecrets call from NetworkManager: {}", error);
+      return Err(no_secrets(&message));
+    }
+  };
+
+  if flags & NM_SECRET_AGENT_GET_SECRETS_FLAG_ALLOW_INTERACTION == 0
+    || setting_name != WIRELESS_SECURITY_SETTING
+  {
+    return Err(no_secrets(&message));
+  }
+
+  let key_mgmt = setting_str(&settings, WIRELESS_SECURITY_SETTING, "key-mgmt");
+  let key = match key_mgmt.as_ref().map(String::as_str) {
+    Some("wpa-psk") | Some("sae") => "psk",
+    Some("none") => "wep-key0",
+    _ => return Err(no_secrets(&message)),
+  };
+
+  let connection_name = setting_str(&settings, "connection", "id").unwrap_or_default();
+
+  Ok(SecretRequest {
+    connection_name,
+    bus: bus.clone(),
+    connection_path: connection_path.to_string(),
+    message,
+    setting_name,
+    key,
+    pending: pending.clone(),
+  })
+}
+
+pub struct NetworkManager {
+  bus: Bus,
+  connections: ConnectionCache,
+}
+
+impl NetworkManager {
+  pub fn new(bus: Bus) -> NetworkManager {
+    NetworkManager {
+      connections: ConnectionCache::new(bus.clone()),
+      bus,
+    }
+  }
+
+  pub async fn request_scan(&self) -> Result<(), dbus::Error> {
+    let device = find_wifi_device(&self.bus)
+      .await?
+      .ok_or_else(|| not_found_error("There is no Wi-Fi device"))?;
+    let options: HashMap<String, Variant<Box<dyn RefArg>>> = HashMap::new();
+
+    self
+      .bus
+      .call(NM, &device, WIRELESS, "RequestScan", (options,))
+      .await
+  }
+
+  /// Connects to the network of `access_point`, reusing its saved connection
+  /// if there is one.
+  ///
+  /// `password` is only used when adding a new connection.
+  pub async fn connect(
+    &self,
+    access_point: &AccessPoint,
+    password: Option<String>,
+  ) -> Result<(), dbus::Error> {
+    let device = find_wifi_device(&self.bus)
+      .await?
+      .ok_or_else(|| not_found_error("There is no Wi-Fi device"))?;
+    let device = Path::from(device);
+    let specific_object = Path::from(access_point.path.clone());
+
+    if let Some(ref connection) = access_point.known_connection {
+      let (_,): (Path<'static>,) = self
+        .bus
+        .call(
+          NM,
+          NM_PATH,
+          NM,
+          "ActivateConnection",
+          (Path::from(connection.clone()), device, specific_object),
+        )
+        .await?;
+    } else {
+      // NetworkManager fills in everything else from the access point
+      let mut settings: HashMap<&str, HashMap<&str, Variant<Box<dyn RefArg>>>> = HashMap::new();
+      if let (Some(key_mgmt), Some(password)) = (access_point.security.key_mgmt(), password) {
+        let mut security: HashMap<&str, Variant<Box<dyn RefArg>>> = HashMap::new();
+        security.insert("key-mgmt", Variant(Box::new(key_mgmt.to_string())));
+        security.insert("psk", Variant(Box::new(password)));
+        settings.insert(WIRELESS_SECURITY_SETTING, security);
+      }
+
+      let (_, _): (Path<'static>, Path<'static>) = self
+        .bus
+        .call(
+          NM,
+          NM_PATH,
+          NM,
+          "AddAndActivateConnection",
+          (settings, device, specific_object),
+        )
+        .await?;
+    }
+
+    Ok(())
+  }
+
+  pub async fn set_wireless_enabled(&self, enabled: bool) -> Result<(), dbus::Error> {
+    self
+      .bus
+      .set_property(NM, NM_PATH, NM, "WirelessEnabled", enabled)
+      .await
+  }
+
+  pub async fn set_wwan_enabled(&self, enabled: bool) -> Result<(), dbus::Error> {
+    self
+      .bus
+      .set_property(NM, NM_PATH, NM, "WwanEnabled", enabled)
+      .await
+  }
+
+  /// Brings `connection` up, or down if it is active.
+  pub async fn toggle_vpn(&self, connection: &VpnConnection) -> Result<(), dbus::Error> {
+    match connection.active_connection {
+      Some(ref active_connection) => {
+        self
+          .bus
+          .call(
+            NM,
+            NM_PATH,
+            NM,
+            "DeactivateConnection",
+            (Path::from(active_connection.clone()),),
+          )
+          .await
+      }
+      None => {
+        // VPNs pick their own device, so none is given
+        let (_,): (Path<'static>,) = self
+          .bus
+          .call(
+            NM,
+            NM_PATH,
+            NM,
+            "ActivateConnection",
+            (
+              Path::from(connection.path.clone()),
+              Path::from("/"),
+              Path::from("/"),
+            ),
+          )
+          .await?;
+
+        Ok(())
+      }
+    }
+  }
+
+  /// Emits the saved VPN and WireGuard connections immediately and then
+  /// whenever anything in NetworkManager changes.
+  pub fn subscribe_to_vpn_connections(&self) -> impl Stream<Item = Vec<VpnConnection>> {
+    let bus = self.bus.clone();
+    let connections = self.connections.clone();
+
+    stream::once(future::ready(()))
+      .chain(self.subscribe_to_changes())
+      .then(move |_| read_vpn_connections(bus.clone(), connections.clone()))
+      .filter_map(|vpn_connections| {
+        future::ready(match vpn_connections {
+          Ok(vpn_connections) => Some(vpn_connections),
+          Err(error) => {
+            warn!("Could not read VPN connections: {}", error);
+            None
+          }
+        })
+      })
+  }
+
+  /// Emits the visible Wi-Fi networks immediately and then whenever
+  /// anything in NetworkManager changes.
+  pub fn subscribe_to_access_points(&self) -> impl Stream<Item = Vec<AccessPoint>> {
+    let bus = self.bus.clone();
+    let connections = self.connections.clone();
+
+    stream::once(future::ready(()))
+      .chain(self.subscribe_to_changes())
+      .then(move |_| read_access_points(bus.clone(), connections.clone()))
+      .filter_map(|access_points| {
+        future::ready(match access_points {
+          Ok(access_points) => Some(access_points),
+          Err(error) => {
+            warn!("Could not read Wi-Fi networks: {}", error);
+            None
+          }
+        })
+      })
+  }
+
+  /// Registers the panel as a NetworkManager secret agent so that passwords
+  /// are asked for by the panel.
+  ///
+  /// `prompt` is called for every request and returns a function that
+  /// cancels the prompt if NetworkManager no longer needs the secret.
+  pub async fn register_secret_agent<F>(&self, prompt: F) -> Result<(), dbus::Error>
+  where
+    F: Fn(SecretRequest) -> Box<dyn Fn()> + 'static,
+  {
+    let bus = self.bus.clone();
+    let pending: PendingPrompts = Rc::default();
+
+    self.bus.register_object(SECRET_AGENT_PATH, move |message| {
+      let member = message.member().map(|member| member.to_string());
+      let reply = match member.as_ref().map(String::as_str) {
+        Some("GetSecrets") => {
+          match read_secret_request(&bus, &pending, message) {
+            Ok(request) => {
+              // Replying removes the prompt again
+              let key = (request.connection_path.clone(), request.setting_name.clone());
+              let cancel = prompt(request);
+              pending.borrow_mut().insert(key, cancel);
+              None
+            }
+            Err(reply) => Some(reply),
+          }
+        }
+        Some("CancelGetSecrets") => {
+          if let Ok((path, setting_name)) = message.read2::<Path, String>() {
+            let cancel = pending
+              .borrow_mut()
+              .remove(&(path.to_string(), setting_name));
+            // Canceling the prompt replies to the original GetSecrets call
+            if let Some(cancel) = cancel {
+              cancel();
+            }
+          }
+          Some(message.method_return())
+        }
+        // Secrets are stored by NetworkManager, so there is nothing to save
+        // or delete
+        _ => Some(message.method_return()),
+      };
+
+      if let Some(reply) = reply {
+        if let Err(error) = bus.send(reply) {
+          warn!("Could not reply to NetworkManager: {}", error);
+        }
+      }
+    });
+
+    self
+      .bus
+      .call(
+        NM,
+        AGENT_MANAGER_PATH,
+        AGENT_MANAGER,
+        "Register",
+        (SECRET_AGENT_ID,),
+      )
+      .await
+  }
+
+  fn subscribe_to_changes(&self) -> impl Stream<Item = ()> {
+    stream::select(
+      self.bus.subscribe_to_properties(NM, None),
+      self
+        .bus
+        .subscribe_to_signal(NM, Some(NM_PATH), NM, "StateChanged")
+        .map(|_| ()),
+    )
+  }
+
+  /// Emits the state of the primary connection immediately and then
+  /// whenever anything in NetworkManager changes.
+  pub fn subscribe(&self) -> impl Stream<Item = NetworkState> {
+    let bus = self.bus.clone();
+
+    stream::once(future::ready(()))
+      .chain(self.subscribe_to_changes())
+      .then(move |_| read_state(bus.clone()))
+      .filter_map(|state| {
+        future::ready(match state {
+          Ok(state) => Some(state),
+          Err(error) => {
+            warn!("Could not read the network state: {}", error);
+            None
+          }
+        })
+      })
+  }
+}
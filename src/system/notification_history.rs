@@ -0,0 +1,212 @@
+use crate::config::data_dir;
+use crate::system::notifications::{
+  Notification, NotificationEvent, NotificationImage, NotificationServer, Urgency,
+};
+use chrono::Utc;
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use futures::prelude::*;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HistoryEntry {
+  /// Unique within the history, unlike notification ids which restart with
+  /// the panel.
+  pub key: u64,
+  pub app_name: String,
+  /// Icon name or image path, raw image data is not kept.
+  pub icon: Option<String>,
+  pub summary: String,
+  pub body: String,
+  pub critical: bool,
+  /// Unix timestamp of when the notification was received.
+  pub time: i64,
+  pub read: bool,
+}
+
+fn history_path() -> PathBuf {
+  data_dir().join("notifications.json")
+}
+
+fn load_entries() -> Vec<HistoryEntry> {
+  let content = match fs::read_to_string(history_path()) {
+    Ok(content) => content,
+    Err(ref error) if error.kind() == io::ErrorKind::NotFound => return vec![],
+    Err(error) => {
+      warn!("Could not read the notification history: {}", error);
+      return vec![];
+    }
+  };
+
+  serde_json::from_str(&content).unwrap_or_else(|error| {
+    warn!("Invalid notification history: {}", error);
+    vec![]
+  })
+}
+
+fn save_entries(entries: &[HistoryEntry]) -> io::Result<()> {
+  let path = history_path();
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent)?;
+  }
+
+  fs::write(path, serde_json::to_string(entries)?)
+}
+
+/// Every notification received, newest first, kept across restarts.
+pub struct NotificationHistory {
+  limit: usize,
+  entries: RefCell<Vec<HistoryEntry>>,
+  /// Never reused, so that a key is not confused with a dismissed entry.
+  next_key: Cell<u64>,
+  /// History keys of the notifications received since the panel started,
+  /// so that replacements update their entry.
+  keys: RefCell<HashMap<u32, u64>>,
+  subscribers: RefCell<Vec<UnboundedSender<Vec<HistoryEntry>>>>,
+}
+
+impl NotificationHistory {
+  pub fn load(limit: usize) -> NotificationHistory {
+    let entries = load_entries();
+    let next_key = entries.iter().map(|entry| entry.key + 1).max().unwrap_or(0);
+
+    NotificationHistory {
+      limit,
+      entries: RefCell::new(entries),
+      next_key: Cell::new(next_key),
+      keys: RefCell::new(HashMap::new()),
+      subscribers: RefCell::new(vec![]),
+    }
+  }
+
+  fn changed(&self) {
+    let entries = self.entries.borrow();
+
+    // Replacements of removed entries are added as new ones
+    self
+      .keys
+      .borrow_mut()
+      .retain(|_, key| entries.iter().any(|entry| entry.key == *key));
+
+    if let Err(error) = save_entries(&entries) {
+      warn!("Could not save the notification history: {}", error);
+    }
+
+    for subscriber in self.subscribers.borrow().iter() {
+      if !subscriber.is_closed() {
+        subscriber.unbounded_send(entries.clone()).unwrap();
+      }
+    }
+  }
+
+  fn add(&self, notification: &Notification) {
+    if notification.transient {
+      return;
+    }
+
+    let icon = match notification.image {
+      Some(NotificationImage::IconName(ref icon)) | Some(NotificationImage::Path(ref icon)) => {
+        Some(icon.clone())
+      }
+      _ => None,
+    };
+
+    {
+      let mut entries = self.entries.borrow_mut();
+      let replaced_key = self.keys.borrow().get(&notification.id).cloned();
+      if let Some(key) = replaced_key {
+        entries.retain(|entry| entry.key != key);
+      }
+
+      let key = self.next_key.get();
+      self.next_key.set(key + 1);
+      self.keys.borrow_mut().insert(notification.id, key);
+      entries.insert(
+        0,
+        HistoryEntry {
+          key,
+          app_name: notification.app_name.clone(),
+          icon,
+          summary: notification.summary.clone(),
+          body: notification.body.clone(),
+          critical: notification.urgency == Urgency::Critical,
+          time: Utc::now().timestamp(),
+          read: false,
+        },
+      );
+      entries.truncate(self.limit);
+    }
+
+    self.changed();
+  }
+
+  pub fn dismiss(&self, key: u64) {
+    self.entries.borrow_mut().retain(|entry| entry.key != key);
+    self.changed();
+  }
+
+  pub fn dismiss_app(&self, app_name: &str) {
+    self
+      .entries
+      .borrow_mut()
+      .retain(|entry| entry.app_name != app_name);
+    self.changed();
+  }
+
+  pub fn clear(&self) {
+    self.entries.borrow_mut().clear();
+    self.changed();
+  }
+
+  pub fn mark_all_read(&self) {
+    if self.unread_count() == 0 {
+      return;
+    }
+
+    for entry in self.entries.borrow_mut().iter_mut() {
+      entry.read = true;
+    }
+    self.changed();
+  }
+
+  pub fn unread_count(&self) -> usize {
+    self
+      .entries
+      .borrow()
+      .iter()
+      .filter(|entry| !entry.read)
+      .count()
+  }
+
+  /// Emits the entries immediately and then whenever they change.
+  pub fn subscribe(&self) -> impl Stream<Item = Vec<HistoryEntry>> {
+    let (sink, stream) = unbounded::<Vec<HistoryEntry>>();
+    sink.unbounded_send(self.entries.borrow().clone()).unwrap();
+    self.subscribers.borrow_mut().push(sink);
+
+    stream
+  }
+}
+
+/// Adds every notification the server receives to `history`.
+pub async fn record_notifications(
+  history: Rc<NotificationHistory>,
+  server: Rc<NotificationServer>,
+) {
+  server
+    .subscribe()
+    .for_each(move |event| {
+      if let NotificationEvent::Received(ref notification) = event {
+        history.add(notification);
+      }
+
+      future::ready(())
+    })
+    .await;
+}
@@ -0,0 +1,441 @@
+use crate::config::NotificationsConfig;
+use crate::system::bus::{prop_bool, prop_str, prop_u64, Bus, Properties};
+use dbus::arg::{RefArg, TypeMismatchError, Variant};
+use dbus::Message;
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use futures::prelude::*;
+use log::warn;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+const NOTIFICATIONS: &str = "org.freedesktop.Notifications";
+const NOTIFICATIONS_PATH: &str = "/org/freedesktop/Notifications";
+const SPEC_VERSION: &str = "1.2";
+const CAPABILITIES: [&str; 5] = ["actions", "body", "body-markup", "icon-static", "persistence"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Urgency {
+  Low,
+  Normal,
+  Critical,
+}
+
+impl Urgency {
+  fn from_u64(value: u64) -> Urgency {
+    match value {
+      0 => Urgency::Low,
+      2 => Urgency::Critical,
+      _ => Urgency::Normal,
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CloseReason {
+  Expired = 1,
+  Dismissed = 2,
+  /// Closed by a call to CloseNotification.
+  Closed = 3,
+}
+
+/// Raw pixels from the `image-data` hint.
+#[derive(Clone, Debug)]
+pub struct ImageData {
+  pub width: i32,
+  pub height: i32,
+  pub rowstride: i32,
+  pub has_alpha: bool,
+  pub bits_per_sample: i32,
+  pub channels: i32,
+  pub data: Rc<Vec<u8>>,
+}
+
+impl ImageData {
+  /// The number of bytes the dimensions claim, if they describe 8 bit RGB
+  /// or RGBA rows that fit in the rowstride.
+  pub fn byte_size(&self) -> Option<usize> {
+    let expected_channels = if self.has_alpha { 4 } else { 3 };
+    if self.bits_per_sample != 8 || self.channels != expected_channels {
+      return None;
+    }
+    if self.width <= 0 || self.height <= 0 || self.rowstride <= 0 {
+      return None;
+    }
+
+    let row = (self.width as usize).checked_mul(self.channels as usize)?;
+    let rowstride = self.rowstride as usize;
+    if rowstride < row {
+      return None;
+    }
+
+    rowstride
+      .checked_mul(self.height as usize - 1)?
+      .checked_add(row)
+  }
+
+  /// Whether `data` holds as many bytes as the dimensions claim.
+  pub fn is_valid(&self) -> bool {
+    self
+      .byte_size()
+      .map_or(false, |size| self.data.len() >= size)
+  }
+}
+
+#[derive(Clone, Debug)]
+pub enum NotificationImage {
+  Data(ImageData),
+  Path(String),
+  IconName(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Notification {
+  pub id: u32,
+  pub app_name: String,
+  pub summary: String,
+  /// Body text, which may contain the markup allowed by the spec.
+  pub body: String,
+  /// Pairs of action key and label, in the order they should be shown.
+  pub actions: Vec<(String, String)>,
+  pub urgency: Urgency,
+  pub image: Option<NotificationImage>,
+  pub desktop_entry: Option<String>,
+  pub category: Option<String>,
+  /// Keep the notification when an action is invoked.
+  pub resident: bool,
+  /// Do not keep the notification after it is closed.
+  pub transient: bool,
+  /// Milliseconds until the notification expires, `None` for never.
+  pub timeout: Option<u32>,
+}
+
+impl Notification {
+  pub fn has_default_action(&self) -> bool {
+    self.actions.iter().any(|(key, _)| key == "default")
+  }
+}
+
+#[derive(Clone, Debug)]
+pub enum NotificationEvent {
+  /// A notification was sent, even if it is not shown yet.
+  Received(Notification),
+  /// A new notification, or a replacement for one with the same id, should
+  /// be shown.
+  Show(Notification),
+  Close(u32, CloseReason),
+}
+
+fn as_bool(arg: &dyn RefArg) -> Option<bool> {
+  arg
+    .as_any()
+    .downcast_ref::<bool>()
+    .cloned()
+    .or_else(|| arg.as_u64().map(|value| value != 0))
+}
+
+fn read_image_data(value: &Variant<Box<dyn RefArg>>) -> Option<ImageData> {
+  let mut fields = value.0.as_iter()?;
+  let width = fields.next()?.as_i64()? as i32;
+  let height = fields.next()?.as_i64()? as i32;
+  let rowstride = fields.next()?.as_i64()? as i32;
+  let has_alpha = as_bool(fields.next()?)?;
+  let bits_per_sample = fields.next()?.as_i64()? as i32;
+  let channels = fields.next()?.as_i64()? as i32;
+  let data = fields.next()?;
+  let data = match data.as_any().downcast_ref::<Vec<u8>>() {
+    Some(data) => data.clone(),
+    None => data
+      .as_iter()?
+      .filter_map(|byte| byte.as_u64())
+      .map(|byte| byte as u8)
+      .collect(),
+  };
+
+  let image_data = ImageData {
+    width,
+    height,
+    rowstride,
+    has_alpha,
+    bits_per_sample,
+    channels,
+    data: Rc::new(data),
+  };
+
+  if image_data.is_valid() {
+    Some(image_data)
+  } else {
+    None
+  }
+}
+
+fn icon_to_image(icon: &str) -> Option<NotificationImage> {
+  if icon.is_empty() {
+    None
+  } else if icon.starts_with('/') || icon.starts_with("file://") {
+    Some(NotificationImage::Path(icon.trim_start_matches("file://").to_string()))
+  } else {
+    Some(NotificationImage::IconName(icon.to_string()))
+  }
+}
+
+/// Picks the image in the order of precedence given by the spec.
+fn read_image(hints: &Properties, app_icon: &str) -> Option<NotificationImage> {
+  let image_data = hints.get("image-data").or_else(|| hints.get("image_data"));
+  let image_path = prop_str(hints, "image-path").or_else(|| prop_str(hints, "image_path"));
+
+  image_data
+    .and_then(read_image_data)
+    .map(NotificationImage::Data)
+    .or_else(|| image_path.as_ref().and_then(|path| icon_to_image(path)))
+    .or_else(|| icon_to_image(app_icon))
+    .or_else(|| {
+      hints
+        .get("icon_data")
+        .and_then(read_image_data)
+        .map(NotificationImage::Data)
+    })
+}
+
+/// The notification server, owning `org.freedesktop.Notifications` on the
+/// session bus.
+#[derive(Clone)]
+pub struct NotificationServer {
+  bus: Bus,
+  default_timeout: u32,
+  next_id: Rc<Cell<u32>>,
+  notifications: Rc<RefCell<BTreeMap<u32, Notification>>>,
+  timeouts: Rc<RefCell<HashMap<u32, glib::SourceId>>>,
+  do_not_disturb: Rc<Cell<bool>>,
+  /// Notifications held back while do not disturb is on.
+  queue: Rc<RefCell<Vec<Notification>>>,
+  subscribers: Rc<RefCell<Vec<UnboundedSender<NotificationEvent>>>>,
+}
+
+impl NotificationServer {
+  pub async fn start(
+    bus: Bus,
+    config: &NotificationsConfig,
+  ) -> Result<NotificationServer, dbus::Error> {
+    let server = NotificationServer {
+      bus: bus.clone(),
+      default_timeout: config.default_timeout_seconds * 1000,
+      next_id: Rc::new(Cell::new(1)),
+      notifications: Rc::new(RefCell::new(BTreeMap::new())),
+      timeouts: Rc::new(RefCell::new(HashMap::new())),
+      do_not_disturb: Rc::new(Cell::new(false)),
+      queue: Rc::new(RefCell::new(vec![])),
+      subscribers: Rc::new(RefCell::new(vec![])),
+    };
+
+    let handler = server.clone();
+    bus.register_object(NOTIFICATIONS_PATH, move |message| {
+      handler.handle_call(message);
+    });
+
+    if bus.request_name(NOTIFICATIONS).await? {
+      Ok(server)
+    } else {
+      Err(dbus::Error::new_custom(
+        "org.freedesktop.DBus.Error.AddressInUse",
+        "Another notification daemon is running",
+      ))
+    }
+  }
+
+  fn handle_call(&self, message: Message) {
+    let member = message.member().map(|member| member.to_string());
+    let reply = match member.as_ref().map(String::as_str) {
+      Some("Notify") => match self.read_notification(&message) {
+        Ok(notification) => {
+          let id = notification.id;
+          self.receive(notification);
+          message.method_return().append1(id)
+        }
+        Err(error) => Message::new_error(
+          &message,
+          "org.freedesktop.DBus.Error.InvalidArgs",
+          &error.to_string(),
+        )
+        .unwrap(),
+      },
+      Some("CloseNotification") => {
+        if let Ok(id) = message.read1::<u32>() {
+          self.close(id, CloseReason::Closed);
+        }
+        message.method_return()
+      }
+      Some("GetCapabilities") => message.method_return().append1(CAPABILITIES.to_vec()),
+      Some("GetServerInformation") => message
+        .method_return()
+        .append3("panel", "panel", env!("CARGO_PKG_VERSION"))
+        .append1(SPEC_VERSION),
+      _ => Message::new_error(
+        &message,
+        "org.freedesktop.DBus.Error.UnknownMethod",
+        "Unknown method",
+      )
+      .unwrap(),
+    };
+
+    if let Err(error) = self.bus.send(reply) {
+      warn!("Could not reply to a notification call: {}", error);
+    }
+  }
+
+  fn read_notification(&self, message: &Message) -> Result<Notification, TypeMismatchError> {
+    let mut args = message.iter_init();
+    let app_name: String = args.read()?;
+    let replaces_id: u32 = args.read()?;
+    let app_icon: String = args.read()?;
+    let summary: String = args.read()?;
+    let body: String = args.read()?;
+    let actions: Vec<String> = args.read()?;
+    let hints: Properties = args.read()?;
+    let expire_timeout: i32 = args.read()?;
+
+    let urgency = Urgency::from_u64(prop_u64(&hints, "urgency").unwrap_or(1));
+    let timeout = match expire_timeout {
+      -1 if urgency == Urgency::Critical => None,
+      -1 => Some(self.default_timeout),
+      0 => None,
+      timeout => Some(timeout as u32),
+    };
+
+    let id = if replaces_id != 0 {
+      replaces_id
+    } else {
+      let id = self.next_id.get();
+      self.next_id.set(id.wrapping_add(1).max(1));
+      id
+    };
+
+    Ok(Notification {
+      id,
+      app_name,
+      summary,
+      body,
+      actions: actions
+        .chunks(2)
+        .filter(|pair| pair.len() == 2)
+        .map(|pair| (pair[0].clone(), pair[1].clone()))
+        .collect(),
+      urgency,
+      image: read_image(&hints, &app_icon),
+      desktop_entry: prop_str(&hints, "desktop-entry"),
+      category: prop_str(&hints, "category"),
+      resident: prop_bool(&hints, "resident").unwrap_or(false),
+      transient: prop_bool(&hints, "transient").unwrap_or(false),
+      timeout,
+    })
+  }
+
+  fn update_subscribers(&self, event: NotificationEvent) {
+    for subscriber in self.subscribers.borrow().iter() {
+      if !subscriber.is_closed() {
+        subscriber.unbounded_send(event.clone()).unwrap();
+      }
+    }
+  }
+
+  fn receive(&self, notification: Notification) {
+    self.update_subscribers(NotificationEvent::Received(notification.clone()));
+
+    if self.do_not_disturb.get() && notification.urgency != Urgency::Critical {
+      let id = notification.id;
+      let mut queue = self.queue.borrow_mut();
+      queue.retain(|queued| queued.id != id);
+      queue.push(notification.clone());
+      // Known, so that it can be closed, but without a timeout until shown
+      self.notifications.borrow_mut().insert(id, notification);
+    } else {
+      self.show(notification);
+    }
+  }
+
+  /// While on, notifications that are not critical are queued and shown
+  /// when it is turned off again.
+  pub fn set_do_not_disturb(&self, active: bool) {
+    self.do_not_disturb.set(active);
+
+    if !active {
+      let queue = self.queue.replace(vec![]);
+      for notification in queue {
+        self.show(notification);
+      }
+    }
+  }
+
+  fn show(&self, notification: Notification) {
+    let id = notification.id;
+
+    if let Some(timeout) = self.timeouts.borrow_mut().remove(&id) {
+      glib::source_remove(timeout);
+    }
+    if let Some(timeout) = notification.timeout {
+      let server = self.clone();
+      let source = gtk::timeout_add(timeout, move || {
+        server.timeouts.borrow_mut().remove(&id);
+        server.close(id, CloseReason::Expired);
+        gtk::Continue(false)
+      });
+      self.timeouts.borrow_mut().insert(id, source);
+    }
+
+    self
+      .notifications
+      .borrow_mut()
+      .insert(id, notification.clone());
+    self.update_subscribers(NotificationEvent::Show(notification));
+  }
+
+  fn emit_signal(&self, signal: Message) {
+    if let Err(error) = self.bus.send(signal) {
+      warn!("Could not emit a notification signal: {}", error);
+    }
+  }
+
+  /// Closes the notification `id` if it is still open.
+  pub fn close(&self, id: u32, reason: CloseReason) {
+    if self.notifications.borrow_mut().remove(&id).is_none() {
+      return;
+    }
+    self.queue.borrow_mut().retain(|queued| queued.id != id);
+    if let Some(timeout) = self.timeouts.borrow_mut().remove(&id) {
+      glib::source_remove(timeout);
+    }
+
+    self.emit_signal(
+      Message::new_signal(NOTIFICATIONS_PATH, NOTIFICATIONS, "NotificationClosed")
+        .unwrap()
+        .append2(id, reason as u32),
+    );
+    self.update_subscribers(NotificationEvent::Close(id, reason));
+  }
+
+  /// Tells the sender of `id` that the user picked the action `key`.
+  pub fn invoke_action(&self, id: u32, key: &str) {
+    let resident = match self.notifications.borrow().get(&id) {
+      Some(notification) => notification.resident,
+      None => return,
+    };
+
+    self.emit_signal(
+      Message::new_signal(NOTIFICATIONS_PATH, NOTIFICATIONS, "ActionInvoked")
+        .unwrap()
+        .append2(id, key),
+    );
+
+    if !resident {
+      self.close(id, CloseReason::Dismissed);
+    }
+  }
+
+  /// Emits every notification shown or closed after subscribing.
+  pub fn subscribe(&self) -> impl Stream<Item = NotificationEvent> {
+    let (sink, stream) = unbounded::<NotificationEvent>();
+    self.subscribers.borrow_mut().push(sink);
+
+    stream
+  }
+}
@@ -0,0 +1,87 @@
+use crate::system::bus::{prop_str, Bus};
+use dbus::arg::{RefArg, Variant};
+use futures::prelude::*;
+use futures::stream;
+use log::warn;
+use std::collections::HashMap;
+
+const POWER_PROFILES: &str = "net.hadess.PowerProfiles";
+const POWER_PROFILES_PATH: &str = "/net/hadess/PowerProfiles";
+
+#[derive(Clone, Debug)]
+pub struct PowerProfilesState {
+  pub active_profile: String,
+  pub profiles: Vec<String>,
+  /// Why the performance profile is degraded, empty if it is not.
+  pub performance_degraded: String,
+}
+
+async fn read_state(bus: Bus) -> Result<PowerProfilesState, dbus::Error> {
+  let properties = bus
+    .get_all_properties(POWER_PROFILES, POWER_PROFILES_PATH, POWER_PROFILES)
+    .await?;
+  let profiles: Vec<HashMap<String, Variant<Box<dyn RefArg>>>> = bus
+    .get_property(
+      POWER_PROFILES,
+      POWER_PROFILES_PATH,
+      POWER_PROFILES,
+      "Profiles",
+    )
+    .await?;
+
+  Ok(PowerProfilesState {
+    active_profile: prop_str(&properties, "ActiveProfile").unwrap_or_default(),
+    profiles: profiles
+      .iter()
+      .filter_map(|profile| prop_str(profile, "Profile"))
+      .collect(),
+    performance_degraded: prop_str(&properties, "PerformanceDegraded").unwrap_or_default(),
+  })
+}
+
+pub struct PowerProfiles {
+  bus: Bus,
+}
+
+impl PowerProfiles {
+  pub fn new(bus: Bus) -> PowerProfiles {
+    PowerProfiles { bus }
+  }
+
+  /// Emits the current profiles immediately and then whenever they change,
+  /// also when changed by another program. Emits nothing if
+  /// power-profiles-daemon is not running.
+  pub fn subscribe(&self) -> impl Stream<Item = PowerProfilesState> {
+    let bus = self.bus.clone();
+
+    stream::once(future::ready(()))
+      .chain(
+        self
+          .bus
+          .subscribe_to_properties(POWER_PROFILES, Some(POWER_PROFILES_PATH)),
+      )
+      .then(move |_| read_state(bus.clone()))
+      .filter_map(|state| {
+        future::ready(match state {
+          Ok(state) => Some(state),
+          Err(error) => {
+            warn!("Could not read power profiles: {}", error);
+            None
+          }
+        })
+      })
+  }
+
+  pub async fn set_active_profile(&self, profile: &str) -> Result<(), dbus::Error> {
+    self
+      .bus
+      .set_property(
+        POWER_PROFILES,
+        POWER_PROFILES_PATH,
+        POWER_PROFILES,
+        "ActiveProfile",
+        profile.to_string(),
+      )
+      .await
+  }
+}
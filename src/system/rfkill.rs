@@ -0,0 +1,255 @@
+use crate::system::network::NetworkManager;
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use futures::prelude::*;
+use glib::IOCondition;
+use log::warn;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::rc::Rc;
+
+const RFKILL_PATH: &str = "/dev/rfkill";
+/// Size of the original `struct rfkill_event`, newer kernels may append
+/// fields which are ignored.
+const RFKILL_EVENT_SIZE: usize = 8;
+
+const RFKILL_TYPE_ALL: u8 = 0;
+const RFKILL_TYPE_WLAN: u8 = 1;
+const RFKILL_TYPE_BLUETOOTH: u8 = 2;
+const RFKILL_TYPE_WWAN: u8 = 5;
+
+const RFKILL_OP_ADD: u8 = 0;
+const RFKILL_OP_DEL: u8 = 1;
+const RFKILL_OP_CHANGE: u8 = 2;
+const RFKILL_OP_CHANGE_ALL: u8 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RadioKind {
+  Wifi,
+  Bluetooth,
+  Wwan,
+  Other,
+}
+
+impl RadioKind {
+  pub const ALL: [RadioKind; 3] = [RadioKind::Wifi, RadioKind::Bluetooth, RadioKind::Wwan];
+
+  fn from_u8(value: u8) -> RadioKind {
+    match value {
+      RFKILL_TYPE_WLAN => RadioKind::Wifi,
+      RFKILL_TYPE_BLUETOOTH => RadioKind::Bluetooth,
+      RFKILL_TYPE_WWAN => RadioKind::Wwan,
+      _ => RadioKind::Other,
+    }
+  }
+
+  fn to_u8(self) -> u8 {
+    match self {
+      RadioKind::Wifi => RFKILL_TYPE_WLAN,
+      RadioKind::Bluetooth => RFKILL_TYPE_BLUETOOTH,
+      RadioKind::Wwan => RFKILL_TYPE_WWAN,
+      RadioKind::Other => RFKILL_TYPE_ALL,
+    }
+  }
+
+  pub fn label(self) -> &'static str {
+    match self {
+      RadioKind::Wifi => "Wi-Fi",
+      RadioKind::Bluetooth => "Bluetooth",
+      RadioKind::Wwan => "Mobile Broadband",
+      RadioKind::Other => "Other",
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Radio {
+  pub kind: RadioKind,
+  pub soft_blocked: bool,
+  /// Blocked by a hardware switch, which software can not undo.
+  pub hard_blocked: bool,
+}
+
+impl Radio {
+  pub fn is_blocked(&self) -> bool {
+    self.soft_blocked || self.hard_blocked
+  }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RadioState {
+  pub radios: Vec<Radio>,
+}
+
+impl RadioState {
+  /// Airplane mode is on when every radio is blocked.
+  pub fn is_airplane_mode(&self) -> bool {
+    !self.radios.is_empty() && self.radios.iter().all(Radio::is_blocked)
+  }
+
+  /// The state of all radios of `kind`, `None` if there are none.
+  pub fn radio(&self, kind: RadioKind) -> Option<Radio> {
+    let radios = self.radios.iter().filter(|radio| radio.kind == kind);
+
+    radios.fold(None, |state: Option<Radio>, radio| {
+      Some(match state {
+        Some(state) => Radio {
+          kind,
+          soft_blocked: state.soft_blocked && radio.soft_blocked,
+          hard_blocked: state.hard_blocked && radio.hard_blocked,
+        },
+        None => *radio,
+      })
+    })
+  }
+}
+
+fn write_event(kind: u8, blocked: bool) -> io::Result<()> {
+  let mut event = [0; RFKILL_EVENT_SIZE];
+  event[4] = kind;
+  event[5] = RFKILL_OP_CHANGE_ALL;
+  event[6] = blocked as u8;
+
+  OpenOptions::new()
+    .write(true)
+    .open(RFKILL_PATH)?
+    .write_all(&event)
+}
+
+fn io_to_dbus_error(error: io::Error) -> dbus::Error {
+  let name = match error.kind() {
+    io::ErrorKind::PermissionDenied => "org.freedesktop.DBus.Error.AccessDenied",
+    _ => "org.freedesktop.DBus.Error.Failed",
+  };
+
+  dbus::Error::new_custom(name, &error.to_string())
+}
+
+/// Radio kill switches, read from and written to `/dev/rfkill`.
+///
+/// Writing needs access to `/dev/rfkill`, which logind normally grants to
+/// the active session. Without it Wi-Fi and mobile broadband are switched
+/// through NetworkManager instead.
+pub struct Rfkill {
+  network: Rc<NetworkManager>,
+  radios: Rc<RefCell<BTreeMap<u32, Radio>>>,
+  subscribers: Rc<RefCell<Vec<UnboundedSender<RadioState>>>>,
+}
+
+impl Rfkill {
+  pub fn open(network: Rc<NetworkManager>) -> Option<Rfkill> {
+    let file = match OpenOptions::new()
+      .read(true)
+      .custom_flags(libc::O_NONBLOCK)
+      .open(RFKILL_PATH)
+    {
+      Ok(file) => file,
+      Err(error) => {
+        warn!("Could not open {}: {}", RFKILL_PATH, error);
+        return None;
+      }
+    };
+
+    let rfkill = Rfkill {
+      network,
+      radios: Rc::new(RefCell::new(BTreeMap::new())),
+      subscribers: Rc::new(RefCell::new(vec![])),
+    };
+    // The kernel starts by sending an add event for every existing radio
+    rfkill.watch(file);
+
+    Some(rfkill)
+  }
+
+  fn state(&self) -> RadioState {
+    RadioState {
+      radios: self.radios.borrow().values().cloned().collect(),
+    }
+  }
+
+  fn watch(&self, mut file: File) {
+    let radios = self.radios.clone();
+    let subscribers = self.subscribers.clone();
+
+    glib::unix_fd_add_local(file.as_raw_fd(), IOCondition::IN, move |_, _| {
+      let mut event = [0; 32];
+
+      // Every read returns a single event
+      while let Ok(size) = file.read(&mut event) {
+        if size < RFKILL_EVENT_SIZE {
+          break;
+        }
+
+        let index = u32::from_ne_bytes([event[0], event[1], event[2], event[3]]);
+        let radio = Radio {
+          kind: RadioKind::from_u8(event[4]),
+          soft_blocked: event[6] != 0,
+          hard_blocked: event[7] != 0,
+        };
+        match event[5] {
+          RFKILL_OP_ADD | RFKILL_OP_CHANGE => {
+            radios.borrow_mut().insert(index, radio);
+          }
+          RFKILL_OP_DEL => {
+            radios.borrow_mut().remove(&index);
+          }
+          _ => {}
+        }
+      }
+
+      let state = RadioState {
+        radios: radios.borrow().values().cloned().collect(),
+      };
+      for subscriber in subscribers.borrow().iter() {
+        if !subscriber.is_closed() {
+          subscriber.unbounded_send(state.clone()).unwrap();
+        }
+      }
+
+      glib::Continue(true)
+    });
+  }
+
+  /// Blocks or unblocks all radios of `kind`, or every radio when `None`.
+  pub async fn set_blocked(
+    &self,
+    kind: Option<RadioKind>,
+    blocked: bool,
+  ) -> Result<(), dbus::Error> {
+    let error = match write_event(kind.map_or(RFKILL_TYPE_ALL, RadioKind::to_u8), blocked) {
+      Ok(()) => return Ok(()),
+      Err(error) => error,
+    };
+
+    if error.kind() != io::ErrorKind::PermissionDenied {
+      return Err(io_to_dbus_error(error));
+    }
+
+    match kind {
+      Some(RadioKind::Wifi) => self.network.set_wireless_enabled(!blocked).await,
+      Some(RadioKind::Wwan) => self.network.set_wwan_enabled(!blocked).await,
+      None => {
+        self.network.set_wireless_enabled(!blocked).await?;
+        self.network.set_wwan_enabled(!blocked).await
+      }
+      _ => Err(io_to_dbus_error(error)),
+    }
+  }
+
+  pub async fn set_airplane_mode(&self, enabled: bool) -> Result<(), dbus::Error> {
+    self.set_blocked(None, enabled).await
+  }
+
+  /// Emits the state of all radios immediately and then whenever it
+  /// changes.
+  pub fn subscribe(&self) -> impl Stream<Item = RadioState> {
+    let (sink, stream) = unbounded::<RadioState>();
+    sink.unbounded_send(self.state()).unwrap();
+    self.subscribers.borrow_mut().push(sink);
+
+    stream
+  }
+}
@@ -0,0 +1,653 @@
+use crate::system::bus::{prop_bool, prop_str, Bus, Properties};
+use dbus::arg::{RefArg, Variant};
+use dbus::message::{MatchRule, MessageType};
+use dbus::Message;
+use futures::prelude::*;
+use futures::stream;
+use log::warn;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+const WATCHER: &str = "org.kde.StatusNotifierWatcher";
+const WATCHER_PATH: &str = "/StatusNotifierWatcher";
+const HOST: &str = "org.kde.StatusNotifierHost";
+const ITEM: &str = "org.kde.StatusNotifierItem";
+const DEFAULT_ITEM_PATH: &str = "/StatusNotifierItem";
+const MENU: &str = "com.canonical.dbusmenu";
+const PROTOCOL_VERSION: i32 = 0;
+
+/// Where a tray item lives, as registered with the watcher.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ItemAddress {
+  pub destination: String,
+  pub path: String,
+}
+
+impl ItemAddress {
+  /// Parses the `name/path` form used by `RegisteredStatusNotifierItems`.
+  fn parse(address: &str) -> ItemAddress {
+    match address.find('/') {
+      Some(index) => ItemAddress {
+        destination: address[..index].to_string(),
+        path: address[index..].to_string(),
+      },
+      None => ItemAddress {
+        destination: address.to_string(),
+        path: DEFAULT_ITEM_PATH.to_string(),
+      },
+    }
+  }
+}
+
+/// The watcher, owning `org.kde.StatusNotifierWatcher` on the session bus
+/// and keeping track of which applications have tray items.
+#[derive(Clone)]
+pub struct StatusNotifierWatcher {
+  bus: Bus,
+  items: Rc<RefCell<Vec<String>>>,
+  hosts: Rc<RefCell<Vec<String>>>,
+}
+
+impl StatusNotifierWatcher {
+  pub async fn start(bus: Bus) -> Result<StatusNotifierWatcher, dbus::Error> {
+    let watcher = StatusNotifierWatcher {
+      bus: bus.clone(),
+      items: Rc::new(RefCell::new(vec![])),
+      hosts: Rc::new(RefCell::new(vec![])),
+    };
+
+    let handler = watcher.clone();
+    bus.register_object(WATCHER_PATH, move |message| {
+      handler.handle_call(message);
+    });
+
+    if !bus.request_name(WATCHER).await? {
+      return Err(dbus::Error::new_custom(
+        "org.freedesktop.DBus.Error.AddressInUse",
+        "Another status notifier watcher is running",
+      ));
+    }
+
+    // Forget items and hosts of applications that leave the bus
+    let name_watcher = watcher.clone();
+    glib::MainContext::default().spawn_local(
+      bus
+        .subscribe_to_signal(
+          "org.freedesktop.DBus",
+          Some("/org/freedesktop/DBus"),
+          "org.freedesktop.DBus",
+          "NameOwnerChanged",
+        )
+        .for_each(move |message| {
+          if let Ok((name, _, new_owner)) = message.read3::<&str, &str, &str>() {
+            if new_owner.is_empty() {
+              name_watcher.remove_name(name);
+            }
+          }
+
+          future::ready(())
+        }),
+    );
+
+    Ok(watcher)
+  }
+
+  fn handle_call(&self, message: Message) {
+    let member = message.member().map(|member| member.to_string());
+    let reply = match member.as_ref().map(String::as_str) {
+      Some("RegisterStatusNotifierItem") => match message.read1::<&str>() {
+        Ok(service) => {
+          self.register_item(&message, service);
+          message.method_return()
+        }
+        Err(error) => invalid_args(&message, &error.to_string()),
+      },
+      Some("RegisterStatusNotifierHost") => match message.read1::<&str>() {
+        Ok(service) => {
+          self.register_host(service);
+          message.method_return()
+        }
+        Err(error) => invalid_args(&message, &error.to_string()),
+      },
+      Some("Get") => match message.read2::<&str, &str>() {
+        Ok((_, name)) => match self.properties().remove(name) {
+          Some(value) => message.method_return().append1(value),
+          None => Message::new_error(
+            &message,
+            "org.freedesktop.DBus.Error.UnknownProperty",
+            "Unknown property",
+          )
+          .unwrap(),
+        },
+        Err(error) => invalid_args(&message, &error.to_string()),
+      },
+      Some("GetAll") => message.method_return().append1(self.properties()),
+      _ => Message::new_error(
+        &message,
+        "org.freedesktop.DBus.Error.UnknownMethod",
+        "Unknown method",
+      )
+      .unwrap(),
+    };
+
+    if let Err(error) = self.bus.send(reply) {
+      warn!("Could not reply to a status notifier call: {}", error);
+    }
+  }
+
+  fn properties(&self) -> Properties {
+    let mut properties: Properties = HashMap::new();
+    properties.insert(
+      "RegisteredStatusNotifierItems".to_string(),
+      Variant(Box::new(self.items.borrow().clone())),
+    );
+    properties.insert(
+      "IsStatusNotifierHostRegistered".to_string(),
+      Variant(Box::new(!self.hosts.borrow().is_empty())),
+    );
+    properties.insert(
+      "ProtocolVersion".to_string(),
+      Variant(Box::new(PROTOCOL_VERSION)),
+    );
+
+    properties
+  }
+
+  fn emit_signal(&self, member: &str, service: Option<&str>) {
+    let signal = Message::new_signal(WATCHER_PATH, WATCHER, member).unwrap();
+    let signal = match service {
+      Some(service) => signal.append1(service),
+      None => signal,
+    };
+
+    if let Err(error) = self.bus.send(signal) {
+      warn!("Could not emit a status notifier signal: {}", error);
+    }
+  }
+
+  /// Items register either with a bus name, using the default path, or
+  /// with an object path on the connection that made the call.
+  fn register_item(&self, message: &Message, service: &str) {
+    let address = if service.starts_with('/') {
+      let sender = message.sender().map(|sender| sender.to_string());
+      format!("{}{}", sender.unwrap_or_default(), service)
+    } else {
+      format!("{}{}", service, DEFAULT_ITEM_PATH)
+    };
+
+    if self.items.borrow().contains(&address) {
+      return;
+    }
+    self.items.borrow_mut().push(address.clone());
+    self.emit_signal("StatusNotifierItemRegistered", Some(&address));
+  }
+
+  fn register_host(&self, service: &str) {
+    if self.hosts.borrow().iter().any(|host| host == service) {
+      return;
+    }
+    self.hosts.borrow_mut().push(service.to_string());
+    self.emit_signal("StatusNotifierHostRegistered", None);
+  }
+
+  fn remove_name(&self, name: &str) {
+    let removed = self
+      .items
+      .borrow()
+      .iter()
+      .filter(|address| ItemAddress::parse(address).destination == name)
+      .cloned()
+      .collect::<Vec<_>>();
+    self
+      .items
+      .borrow_mut()
+      .retain(|address| !removed.contains(address));
+    for address in removed {
+      self.emit_signal("StatusNotifierItemUnregistered", Some(&address));
+    }
+
+    self.hosts.borrow_mut().retain(|host| host != name);
+  }
+}
+
+fn invalid_args(message: &Message, error: &str) -> Message {
+  Message::new_error(message, "org.freedesktop.DBus.Error.InvalidArgs", error).unwrap()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ItemStatus {
+  Passive,
+  Active,
+  NeedsAttention,
+}
+
+/// An icon image in RGBA, converted from the ARGB sent by items.
+#[derive(Clone, Debug)]
+pub struct IconPixmap {
+  pub width: i32,
+  pub height: i32,
+  pub data: Rc<Vec<u8>>,
+}
+
+/// Items may give an icon name, pixmaps in several sizes, or both.
+#[derive(Clone, Debug, Default)]
+pub struct Icon {
+  pub name: Option<String>,
+  pub pixmaps: Vec<IconPixmap>,
+}
+
+impl Icon {
+  pub fn is_empty(&self) -> bool {
+    self.name.is_none() && self.pixmaps.is_empty()
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct TrayItem {
+  pub address: ItemAddress,
+  pub id: String,
+  pub title: String,
+  pub status: ItemStatus,
+  pub icon: Icon,
+  pub overlay_icon: Icon,
+  pub attention_icon: Icon,
+  /// Extra directory to look up the icon names in.
+  pub icon_theme_path: Option<String>,
+  pub tooltip_title: String,
+  /// Tooltip text, which may contain the same markup as notifications.
+  pub tooltip_description: String,
+  /// The item only supports showing its menu, not being activated.
+  pub item_is_menu: bool,
+  /// Object path of the dbusmenu of the item.
+  pub menu: Option<String>,
+}
+
+fn read_bytes(arg: &dyn RefArg) -> Option<Vec<u8>> {
+  match arg.as_any().downcast_ref::<Vec<u8>>() {
+    Some(bytes) => Some(bytes.clone()),
+    None => Some(
+      arg
+        .as_iter()?
+        .filter_map(|byte| byte.as_u64())
+        .map(|byte| byte as u8)
+        .collect(),
+    ),
+  }
+}
+
+fn read_pixmap(arg: &dyn RefArg) -> Option<IconPixmap> {
+  let mut fields = arg.as_iter()?;
+  let width = i32::try_from(fields.next()?.as_i64()?).ok().filter(|width| *width > 0)?;
+  let height = i32::try_from(fields.next()?.as_i64()?).ok().filter(|height| *height > 0)?;
+  let argb = read_bytes(fields.next()?)?;
+
+  let size = (width as usize)
+    .checked_mul(height as usize)?
+    .checked_mul(4)?;
+  let argb = argb.get(..size)?;
+
+  let data = argb
+    .chunks_exact(4)
+    .flat_map(|pixel| vec![pixel[1], pixel[2], pixel[3], pixel[0]])
+    .collect();
+
+  Some(IconPixmap {
+    width,
+    height,
+    data: Rc::new(data),
+  })
+}
+
+fn read_pixmaps(arg: Option<&dyn RefArg>) -> Vec<IconPixmap> {
+  arg
+    .and_then(|arg| arg.as_iter())
+    .map(|pixmaps| pixmaps.filter_map(read_pixmap).collect())
+    .unwrap_or_default()
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+  value.filter(|value| !value.is_empty())
+}
+
+fn read_icon(properties: &Properties, name: &str, pixmap: &str) -> Icon {
+  Icon {
+    name: non_empty(prop_str(properties, name)),
+    pixmaps: read_pixmaps(properties.get(pixmap).map(|value| &value.0 as &dyn RefArg)),
+  }
+}
+
+async fn read_item(bus: Bus, address: ItemAddress) -> Result<TrayItem, dbus::Error> {
+  let properties = bus
+    .get_all_properties(&address.destination, &address.path, ITEM)
+    .await?;
+
+  let (tooltip_title, tooltip_description) = properties
+    .get("ToolTip")
+    .and_then(|tooltip| {
+      let mut fields = tooltip.0.as_iter()?;
+      let _icon_name = fields.next()?;
+      let _icon_pixmaps = fields.next()?;
+      let title = fields.next()?.as_str()?.to_string();
+      let description = fields.next()?.as_str()?.to_string();
+      Some((title, description))
+    })
+    .unwrap_or_default();
+
+  let status = match prop_str(&properties, "Status").as_ref().map(String::as_str) {
+    Some("Passive") => ItemStatus::Passive,
+    Some("NeedsAttention") => ItemStatus::NeedsAttention,
+    _ => ItemStatus::Active,
+  };
+
+  Ok(TrayItem {
+    id: prop_str(&properties, "Id").unwrap_or_default(),
+    title: prop_str(&properties, "Title").unwrap_or_default(),
+    status,
+    icon: read_icon(&properties, "IconName", "IconPixmap"),
+    overlay_icon: read_icon(&properties, "OverlayIconName", "OverlayIconPixmap"),
+    attention_icon: read_icon(&properties, "AttentionIconName", "AttentionIconPixmap"),
+    icon_theme_path: non_empty(prop_str(&properties, "IconThemePath")),
+    tooltip_title,
+    tooltip_description,
+    item_is_menu: prop_bool(&properties, "ItemIsMenu").unwrap_or(false),
+    menu: non_empty(prop_str(&properties, "Menu")).filter(|menu| menu != "/"),
+    address,
+  })
+}
+
+async fn read_item_addresses(bus: Bus) -> Result<Vec<ItemAddress>, dbus::Error> {
+  let items: Vec<String> = bus
+    .get_property(WATCHER, WATCHER_PATH, WATCHER, "RegisteredStatusNotifierItems")
+    .await?;
+
+  Ok(items.iter().map(|item| ItemAddress::parse(item)).collect())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToggleType {
+  None,
+  Checkmark,
+  Radio,
+}
+
+/// An entry of a dbusmenu, with its submenu in `children`.
+#[derive(Clone, Debug)]
+pub struct MenuItem {
+  pub id: i32,
+  /// Label with `_` marking the mnemonic.
+  pub label: String,
+  pub enabled: bool,
+  pub visible: bool,
+  pub separator: bool,
+  pub toggle_type: ToggleType,
+  pub toggled: bool,
+  pub children: Vec<MenuItem>,
+}
+
+impl MenuItem {
+  pub fn has_submenu(&self) -> bool {
+    !self.children.is_empty()
+  }
+}
+
+fn as_bool(arg: &dyn RefArg) -> Option<bool> {
+  arg
+    .as_any()
+    .downcast_ref::<bool>()
+    .cloned()
+    .or_else(|| arg.as_u64().map(|value| value != 0))
+}
+
+fn create_menu_item(
+  id: i32,
+  properties: &HashMap<String, &dyn RefArg>,
+  children: Vec<MenuItem>,
+) -> MenuItem {
+  let string = |key: &str| properties.get(key).and_then(|value| value.as_str());
+  let boolean = |key: &str| properties.get(key).and_then(|value| as_bool(*value));
+
+  MenuItem {
+    id,
+    label: string("label").unwrap_or_default().to_string(),
+    enabled: boolean("enabled").unwrap_or(true),
+    visible: boolean("visible").unwrap_or(true),
+    separator: string("type") == Some("separator"),
+    toggle_type: match string("toggle-type") {
+      Some("checkmark") => ToggleType::Checkmark,
+      Some("radio") => ToggleType::Radio,
+      _ => ToggleType::None,
+    },
+    toggled: properties
+      .get("toggle-state")
+      .and_then(|value| value.as_i64())
+      == Some(1),
+    children,
+  }
+}
+
+/// Reads a `(ia{sv}av)` layout, as found in the children of another.
+fn read_menu_item(arg: &dyn RefArg) -> Option<MenuItem> {
+  let mut fields = arg.as_iter()?;
+  let id = fields.next()?.as_i64()? as i32;
+
+  let mut properties = HashMap::new();
+  let mut entries = fields.next()?.as_iter()?;
+  while let (Some(key), Some(value)) = (entries.next(), entries.next()) {
+    if let Some(key) = key.as_str() {
+      properties.insert(key.to_string(), value);
+    }
+  }
+
+  let children = fields
+    .next()?
+    .as_iter()?
+    .filter_map(|child| child.as_iter()?.next().and_then(read_menu_item))
+    .collect();
+
+  Some(create_menu_item(id, &properties, children))
+}
+
+/// Talks to the watcher and the items on behalf of the panel tray.
+pub struct StatusNotifierHost {
+  bus: Bus,
+}
+
+impl StatusNotifierHost {
+  pub fn new(bus: Bus) -> StatusNotifierHost {
+    StatusNotifierHost { bus }
+  }
+
+  /// Tells the watcher that a tray is shown, which some applications wait
+  /// for before creating their items.
+  pub async fn register(&self) -> Result<(), dbus::Error> {
+    let name = format!("{}-{}", HOST, std::process::id());
+    self.bus.request_name(&name).await?;
+
+    self
+      .bus
+      .call(
+        WATCHER,
+        WATCHER_PATH,
+        WATCHER,
+        "RegisterStatusNotifierHost",
+        (name,),
+      )
+      .await
+  }
+
+  /// Emits the registered items whenever one is added or removed.
+  pub fn subscribe_to_items(&self) -> impl Stream<Item = Vec<ItemAddress>> {
+    let bus = self.bus.clone();
+    let registered = self.bus.subscribe_to_signal(
+      WATCHER,
+      Some(WATCHER_PATH),
+      WATCHER,
+      "StatusNotifierItemRegistered",
+    );
+    let unregistered = self.bus.subscribe_to_signal(
+      WATCHER,
+      Some(WATCHER_PATH),
+      WATCHER,
+      "StatusNotifierItemUnregistered",
+    );
+
+    stream::once(future::ready(()))
+      .chain(stream::select(registered, unregistered).map(|_| ()))
+      .then(move |_| read_item_addresses(bus.clone()))
+      .filter_map(|result| {
+        future::ready(match result {
+          Ok(addresses) => Some(addresses),
+          Err(error) => {
+            warn!("Could not read the tray items: {}", error);
+            None
+          }
+        })
+      })
+  }
+
+  /// Emits the item now and whenever its icons, tooltip or status change.
+  pub fn subscribe_to_item(&self, address: ItemAddress) -> impl Stream<Item = TrayItem> {
+    let bus = self.bus.clone();
+    let mut rule = MatchRule::new();
+    rule.msg_type = Some(MessageType::Signal);
+    rule.path = Some(address.path.clone().into());
+    rule.interface = Some(ITEM.to_string().into());
+    let changed = self.bus.subscribe_from(&address.destination, rule);
+
+    stream::once(future::ready(()))
+      .chain(changed.map(|_| ()))
+      .then(move |_| read_item(bus.clone(), address.clone()))
+      .filter_map(|result| {
+        future::ready(match result {
+          Ok(item) => Some(item),
+          Err(error) => {
+            warn!("Could not read a tray item: {}", error);
+            None
+          }
+        })
+      })
+  }
+
+  pub async fn activate(&self, address: &ItemAddress, x: i32, y: i32) -> Result<(), dbus::Error> {
+    self
+      .bus
+      .call(&address.destination, &address.path, ITEM, "Activate", (x, y))
+      .await
+  }
+
+  pub async fn secondary_activate(
+    &self,
+    address: &ItemAddress,
+    x: i32,
+    y: i32,
+  ) -> Result<(), dbus::Error> {
+    self
+      .bus
+      .call(
+        &address.destination,
+        &address.path,
+        ITEM,
+        "SecondaryActivate",
+        (x, y),
+      )
+      .await
+  }
+
+  /// Asks the item to show its own context menu, for items without a
+  /// dbusmenu.
+  pub async fn context_menu(
+    &self,
+    address: &ItemAddress,
+    x: i32,
+    y: i32,
+  ) -> Result<(), dbus::Error> {
+    self
+      .bus
+      .call(&address.destination, &address.path, ITEM, "ContextMenu", (x, y))
+      .await
+  }
+
+  /// `orientation` is either "vertical" or "horizontal".
+  pub async fn scroll(
+    &self,
+    address: &ItemAddress,
+    delta: i32,
+    orientation: &str,
+  ) -> Result<(), dbus::Error> {
+    self
+      .bus
+      .call(
+        &address.destination,
+        &address.path,
+        ITEM,
+        "Scroll",
+        (delta, orientation),
+      )
+      .await
+  }
+
+  /// Reads the whole menu of `item`, whose children are the top level
+  /// entries.
+  pub async fn read_menu(&self, item: &TrayItem) -> Result<MenuItem, dbus::Error> {
+    let destination = &item.address.destination;
+    let path = match item.menu {
+      Some(ref path) => path,
+      None => {
+        return Err(dbus::Error::new_custom(
+          "org.freedesktop.DBus.Error.Failed",
+          "The item has no menu",
+        ))
+      }
+    };
+
+    // Lets applications populate the menu lazily, not all of them support
+    // it so errors are ignored
+    let _: Result<(bool,), _> = self
+      .bus
+      .call(destination, path, MENU, "AboutToShow", (0,))
+      .await;
+
+    let (_revision, (id, properties, children)): (
+      u32,
+      (i32, Properties, Vec<Variant<Box<dyn RefArg>>>),
+    ) = self
+      .bus
+      .call(
+        destination,
+        path,
+        MENU,
+        "GetLayout",
+        (0, -1, Vec::<String>::new()),
+      )
+      .await?;
+
+    let properties = properties
+      .iter()
+      .map(|(key, value)| (key.clone(), &value.0 as &dyn RefArg))
+      .collect();
+    let children = children
+      .iter()
+      .filter_map(|child| read_menu_item(&child.0))
+      .collect();
+
+    Ok(create_menu_item(id, &properties, children))
+  }
+
+  pub async fn send_menu_event(&self, item: &TrayItem, id: i32) -> Result<(), dbus::Error> {
+    let path = item.menu.as_ref().map(String::as_str).unwrap_or("/");
+    let timestamp = 0u32;
+
+    self
+      .bus
+      .call(
+        &item.address.destination,
+        path,
+        MENU,
+        "Event",
+        (id, "clicked", Variant(0), timestamp),
+      )
+      .await
+  }
+}
@@ -0,0 +1,562 @@
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use futures::channel::oneshot;
+use futures::prelude::*;
+use glib::IOCondition;
+use log::warn;
+use serde::Deserialize;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::env;
+use std::io::{self, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::rc::Rc;
+
+const IPC_MAGIC: &[u8] = b"i3-ipc";
+/// The magic followed by the payload length and message type.
+const HEADER_SIZE: usize = 14;
+
+const RUN_COMMAND: u32 = 0;
+const GET_WORKSPACES: u32 = 1;
+const SUBSCRIBE: u32 = 2;
+/// Events have the highest bit set in their type.
+const EVENT_MASK: u32 = 0x8000_0000;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Workspace {
+  /// The number at the start of the name, -1 if there is none.
+  pub num: i32,
+  pub name: String,
+  pub output: String,
+  pub focused: bool,
+  pub visible: bool,
+  pub urgent: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommandResult {
+  success: bool,
+  #[serde(default)]
+  error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubscribeResult {
+  success: bool,
+}
+
+fn invalid_data<E: ToString>(error: E) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+fn encode_message(message_type: u32, payload: &str) -> Vec<u8> {
+  let mut message = Vec::with_capacity(HEADER_SIZE + payload.len());
+  message.extend_from_slice(IPC_MAGIC);
+  message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+  message.extend_from_slice(&message_type.to_ne_bytes());
+  message.extend_from_slice(payload.as_bytes());
+
+  message
+}
+
+/// Returns the payload length and message type of a message header.
+fn read_header(header: &[u8]) -> io::Result<(usize, u32)> {
+  if &header[..IPC_MAGIC.len()] != IPC_MAGIC {
+    return Err(invalid_data("Invalid IPC message"));
+  }
+
+  let length = u32::from_ne_bytes([header[6], header[7], header[8], header[9]]);
+  let message_type = u32::from_ne_bytes([header[10], header[11], header[12], header[13]]);
+
+  Ok((length as usize, message_type))
+}
+
+/// Splits the first complete message off `buffer`, if it has one.
+fn take_message(buffer: &mut Vec<u8>) -> io::Result<Option<(u32, Vec<u8>)>> {
+  if buffer.len() < HEADER_SIZE {
+    return Ok(None);
+  }
+  let (length, message_type) = read_header(&buffer[..HEADER_SIZE])?;
+  if buffer.len() < HEADER_SIZE + length {
+    return Ok(None);
+  }
+
+  let payload = buffer[HEADER_SIZE..HEADER_SIZE + length].to_vec();
+  buffer.drain(..HEADER_SIZE + length);
+
+  Ok(Some((message_type, payload)))
+}
+
+fn closed_error() -> io::Error {
+  io::Error::new(io::ErrorKind::BrokenPipe, "The IPC connection is closed")
+}
+
+/// A non-blocking connection to the IPC socket, driven by the glib main
+/// loop so that an unresponsive window manager never blocks the panel.
+#[derive(Clone)]
+struct Connection {
+  stream: Rc<UnixStream>,
+  output: Rc<RefCell<Vec<u8>>>,
+  /// Whether the rest of `output` waits for the socket to be writable.
+  flushing: Rc<Cell<bool>>,
+}
+
+impl Connection {
+  fn open<P: AsRef<Path>>(socket_path: P) -> io::Result<Connection> {
+    let stream = UnixStream::connect(socket_path)?;
+    stream.set_nonblocking(true)?;
+
+    Ok(Connection {
+      stream: Rc::new(stream),
+      output: Rc::new(RefCell::new(vec![])),
+      flushing: Rc::new(Cell::new(false)),
+    })
+  }
+
+  /// Writes as much of `output` as the socket takes, returning whether
+  /// anything is left.
+  fn write_output(&self) -> io::Result<bool> {
+    let mut output = self.output.borrow_mut();
+
+    while !output.is_empty() {
+      match (&*self.stream).write(&output) {
+        Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+        Ok(size) => {
+          output.drain(..size);
+        }
+        Err(ref error) if error.kind() == io::ErrorKind::WouldBlock => return Ok(true),
+        Err(ref error) if error.kind() == io::ErrorKind::Interrupted => {}
+        Err(error) => return Err(error),
+      }
+    }
+
+    Ok(false)
+  }
+
+  fn send(&self, message_type: u32, payload: &str) -> io::Result<()> {
+    self
+      .output
+      .borrow_mut()
+      .extend_from_slice(&encode_message(message_type, payload));
+
+    if self.flushing.get() || !self.write_output()? {
+      return Ok(());
+    }
+
+    self.flushing.set(true);
+    let connection = self.clone();
+    glib::unix_fd_add_local(self.stream.as_raw_fd(), IOCondition::OUT, move |_, _| {
+      match connection.write_output() {
+        Ok(true) => glib::Continue(true),
+        Ok(false) => {
+          connection.flushing.set(false);
+          glib::Continue(false)
+        }
+        Err(error) => {
+          // Reading notices the closed connection and cleans up
+          warn!("Could not write to the IPC socket: {}", error);
+          connection.flushing.set(false);
+          glib::Continue(false)
+        }
+      }
+    });
+
+    Ok(())
+  }
+
+  /// Calls `on_message` with every message received and `on_close` once
+  /// the connection is lost.
+  fn watch<F, G>(&self, mut on_message: F, on_close: G)
+  where
+    F: FnMut(u32, Vec<u8>) + 'static,
+    G: Fn() + 'static,
+  {
+    let stream = self.stream.clone();
+    let mut buffer = vec![];
+
+    glib::unix_fd_add_local(
+      stream.as_raw_fd(),
+      IOCondition::IN | IOCondition::HUP | IOCondition::ERR,
+      move |_, _| {
+        let mut chunk = [0; 4096];
+        loop {
+          match (&*stream).read(&mut chunk) {
+            Ok(0) => {
+              warn!("The window manager closed the IPC socket");
+              on_close();
+              return glib::Continue(false);
+            }
+            Ok(size) => buffer.extend_from_slice(&chunk[..size]),
+            Err(ref error) if error.kind() == io::ErrorKind::WouldBlock => break,
+            Err(ref error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => {
+              warn!("Could not read from the IPC socket: {}", error);
+              on_close();
+              return glib::Continue(false);
+            }
+          }
+        }
+
+        loop {
+          match take_message(&mut buffer) {
+            Ok(Some((message_type, payload))) => on_message(message_type, payload),
+            Ok(None) => break,
+            Err(error) => {
+              warn!("Could not read from the IPC socket: {}", error);
+              on_close();
+              return glib::Continue(false);
+            }
+          }
+        }
+
+        glib::Continue(true)
+      },
+    );
+  }
+}
+
+type Reply = oneshot::Sender<io::Result<Vec<u8>>>;
+
+/// Workspaces of sway, or i3, read over its IPC socket.
+///
+/// Requests are made on one connection and events are received on
+/// another, as replies and events could otherwise interleave.
+#[derive(Clone)]
+pub struct Sway {
+  commands: Connection,
+  /// Requests waiting for a reply, which come in the order they were sent.
+  replies: Rc<RefCell<VecDeque<(u32, Reply)>>>,
+  connected: Rc<Cell<bool>>,
+  workspaces: Rc<RefCell<Vec<Workspace>>>,
+  subscribers: Rc<RefCell<Vec<UnboundedSender<Vec<Workspace>>>>>,
+}
+
+impl Sway {
+  /// Connects to the socket in `$SWAYSOCK`, or `$I3SOCK` when running i3.
+  pub fn connect_from_env() -> Option<Sway> {
+    let socket_path = env::var_os("SWAYSOCK").or_else(|| env::var_os("I3SOCK"))?;
+
+    match Sway::connect(&socket_path) {
+      Ok(sway) => Some(sway),
+      Err(error) => {
+        warn!("Could not connect to {}: {}", Path::new(&socket_path).display(), error);
+        None
+      }
+    }
+  }
+
+  /// Connects to any socket that speaks the i3 IPC protocol at
+  /// `socket_path`. The workspaces are read in the background.
+  pub fn connect<P: AsRef<Path>>(socket_path: P) -> io::Result<Sway> {
+    let commands = Connection::open(&socket_path)?;
+    let events = Connection::open(&socket_path)?;
+
+    let sway = Sway {
+      commands,
+      replies: Rc::new(RefCell::new(VecDeque::new())),
+      connected: Rc::new(Cell::new(true)),
+      workspaces: Rc::new(RefCell::new(vec![])),
+      subscribers: Rc::new(RefCell::new(vec![])),
+    };
+
+    let replies = sway.replies.clone();
+    let disconnected = sway.clone();
+    sway.commands.watch(
+      move |message_type, payload| {
+        let reply = replies.borrow_mut().pop_front();
+        if let Some((request_type, reply)) = reply {
+          let _ = reply.send(if message_type == request_type {
+            Ok(payload)
+          } else {
+            Err(invalid_data("Unexpected IPC reply"))
+          });
+        }
+      },
+      move || disconnected.disconnect(),
+    );
+
+    let updated = sway.clone();
+    let disconnected = sway.clone();
+    events.watch(
+      move |message_type, payload| {
+        if message_type == SUBSCRIBE {
+          let subscribed = serde_json::from_slice::<SubscribeResult>(&payload)
+            .map_or(false, |result| result.success);
+          if !subscribed {
+            warn!("Could not subscribe to workspace events");
+          }
+        } else if message_type & EVENT_MASK != 0 {
+          // Workspace and output events describe the change, but reading
+          // all workspaces again is simpler than applying it
+          glib::MainContext::default().spawn_local(updated.clone().update_workspaces());
+        }
+      },
+      move || disconnected.disconnect(),
+    );
+    events.send(SUBSCRIBE, r#"["workspace","output"]"#)?;
+
+    glib::MainContext::default().spawn_local(sway.clone().update_workspaces());
+
+    Ok(sway)
+  }
+
+  /// Fails pending requests and clears the workspaces, which hides them.
+  fn disconnect(&self) {
+    if !self.connected.replace(false) {
+      return;
+    }
+
+    for (_, reply) in self.replies.replace(VecDeque::new()) {
+      let _ = reply.send(Err(closed_error()));
+    }
+    self.workspaces.replace(vec![]);
+    self.update_subscribers();
+  }
+
+  async fn request(&self, message_type: u32, payload: &str) -> io::Result<Vec<u8>> {
+    if !self.connected.get() {
+      return Err(closed_error());
+    }
+
+    let (sender, receiver) = oneshot::channel();
+    self.replies.borrow_mut().push_back((message_type, sender));
+    if let Err(error) = self.commands.send(message_type, payload) {
+      self.disconnect();
+      return Err(error);
+    }
+
+    receiver.await.unwrap_or_else(|_| Err(closed_error()))
+  }
+
+  pub async fn get_workspaces(&self) -> io::Result<Vec<Workspace>> {
+    let reply = self.request(GET_WORKSPACES, "").await?;
+
+    serde_json::from_slice(&reply).map_err(invalid_data)
+  }
+
+  pub async fn run_command(&self, command: &str) -> io::Result<()> {
+    let reply = self.request(RUN_COMMAND, command).await?;
+    let results: Vec<CommandResult> = serde_json::from_slice(&reply).map_err(invalid_data)?;
+
+    match results.into_iter().find(|result| !result.success) {
+      Some(result) => Err(io::Error::new(
+        io::ErrorKind::Other,
+        result
+          .error
+          .unwrap_or_else(|| format!("Command failed: {}", command)),
+      )),
+      None => Ok(()),
+    }
+  }
+
+  pub async fn focus_workspace(&self, workspace: &Workspace) -> io::Result<()> {
+    let command = format!(
+      "workspace \"{}\"",
+      workspace.name.replace('\\', "\\\\").replace('"', "\\\"")
+    );
+
+    self.run_command(&command).await
+  }
+
+  /// Focuses the workspace after, or before, the one visible on `output`.
+  pub async fn focus_adjacent_workspace(&self, output: &str, forward: bool) -> io::Result<()> {
+    let workspaces = self
+      .workspaces
+      .borrow()
+      .iter()
+      .filter(|workspace| workspace.output == output)
+      .cloned()
+      .collect::<Vec<_>>();
+    let current = match workspaces.iter().position(|workspace| workspace.visible) {
+      Some(current) => current,
+      None => return Ok(()),
+    };
+
+    let adjacent = if forward {
+      workspaces.get(current + 1)
+    } else if current > 0 {
+      workspaces.get(current - 1)
+    } else {
+      None
+    };
+
+    match adjacent {
+      Some(workspace) => self.focus_workspace(workspace).await,
+      None => Ok(()),
+    }
+  }
+
+  fn update_subscribers(&self) {
+    let workspaces = self.workspaces.borrow().clone();
+
+    for subscriber in self.subscribers.borrow().iter() {
+      if !subscriber.is_closed() {
+        subscriber.unbounded_send(workspaces.clone()).unwrap();
+      }
+    }
+  }
+
+  async fn update_workspaces(self) {
+    match self.get_workspaces().await {
+      Ok(workspaces) => {
+        self.workspaces.replace(workspaces);
+        self.update_subscribers();
+      }
+      Err(error) => warn!("Could not read the workspaces: {}", error),
+    }
+  }
+
+  /// Emits the workspaces now and whenever they change, and an empty list
+  /// if the connection is lost.
+  pub fn subscribe(&self) -> impl Stream<Item = Vec<Workspace>> {
+    let (sink, stream) = unbounded::<Vec<Workspace>>();
+    sink.unbounded_send(self.workspaces.borrow().clone()).unwrap();
+    self.subscribers.borrow_mut().push(sink);
+
+    stream
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::fs;
+  use std::os::unix::net::UnixListener;
+  use std::sync::atomic::{AtomicBool, Ordering};
+  use std::sync::{mpsc, Arc};
+  use std::thread;
+
+  fn frame(message_type: u32, payload: &str) -> Vec<u8> {
+    encode_message(message_type, payload)
+  }
+
+  #[test]
+  fn take_message_waits_for_partial_frames() {
+    let message = frame(GET_WORKSPACES, "[]");
+    let mut buffer = message[..HEADER_SIZE - 1].to_vec();
+    assert_eq!(take_message(&mut buffer).unwrap(), None);
+
+    buffer.extend_from_slice(&message[HEADER_SIZE - 1..message.len() - 1]);
+    assert_eq!(take_message(&mut buffer).unwrap(), None);
+
+    buffer.push(message[message.len() - 1]);
+    assert_eq!(
+      take_message(&mut buffer).unwrap(),
+      Some((GET_WORKSPACES, b"[]".to_vec()))
+    );
+    assert!(buffer.is_empty());
+  }
+
+  #[test]
+  fn take_message_splits_concatenated_frames() {
+    let mut buffer = frame(SUBSCRIBE, r#"{"success":true}"#);
+    buffer.extend_from_slice(&frame(EVENT_MASK, "{}"));
+    buffer.extend_from_slice(&frame(RUN_COMMAND, "")[..4]);
+
+    assert_eq!(
+      take_message(&mut buffer).unwrap(),
+      Some((SUBSCRIBE, br#"{"success":true}"#.to_vec()))
+    );
+    assert_eq!(
+      take_message(&mut buffer).unwrap(),
+      Some((EVENT_MASK, b"{}".to_vec()))
+    );
+    assert_eq!(take_message(&mut buffer).unwrap(), None);
+    assert_eq!(buffer.len(), 4);
+  }
+
+  #[test]
+  fn take_message_rejects_invalid_magic() {
+    let mut buffer = frame(RUN_COMMAND, "");
+    buffer[0] = b'x';
+
+    assert!(take_message(&mut buffer).is_err());
+  }
+
+  fn read_frame(stream: &mut UnixStream) -> Option<(u32, String)> {
+    let mut header = [0; HEADER_SIZE];
+    stream.read_exact(&mut header).ok()?;
+    let (length, message_type) = read_header(&header).ok()?;
+    let mut payload = vec![0; length];
+    stream.read_exact(&mut payload).ok()?;
+
+    Some((message_type, String::from_utf8(payload).ok()?))
+  }
+
+  const FIRST_WORKSPACES: &str =
+    r#"[{"num":1,"name":"1","output":"eDP-1","focused":true,"visible":true,"urgent":false}]"#;
+  const SECOND_WORKSPACES: &str = r#"[
+    {"num":1,"name":"1","output":"eDP-1","focused":false,"visible":false,"urgent":false},
+    {"num":2,"name":"2: \"web\"","output":"eDP-1","focused":true,"visible":true,"urgent":false}
+  ]"#;
+
+  /// Answers like sway on one connection, sending a workspace event after
+  /// subscribing and the commands it runs to `commands`.
+  fn serve(mut stream: UnixStream, subscribed: Arc<AtomicBool>, commands: mpsc::Sender<String>) {
+    while let Some((message_type, payload)) = read_frame(&mut stream) {
+      let reply = match message_type {
+        SUBSCRIBE => {
+          subscribed.store(true, Ordering::SeqCst);
+          r#"{"success":true}"#
+        }
+        GET_WORKSPACES if subscribed.load(Ordering::SeqCst) => SECOND_WORKSPACES,
+        GET_WORKSPACES => FIRST_WORKSPACES,
+        RUN_COMMAND => {
+          commands.send(payload).unwrap();
+          r#"[{"success":true}]"#
+        }
+        _ => return,
+      };
+      stream.write_all(&frame(message_type, reply)).unwrap();
+
+      if message_type == SUBSCRIBE {
+        stream
+          .write_all(&frame(EVENT_MASK, r#"{"change":"focus"}"#))
+          .unwrap();
+      }
+    }
+  }
+
+  #[test]
+  fn follows_workspaces_of_a_stand_in_socket() {
+    let directory = env::temp_dir().join(format!("panel-sway-test-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let socket_path = directory.join("sway.sock");
+    let _ = fs::remove_file(&socket_path);
+    let listener = UnixListener::bind(&socket_path).unwrap();
+
+    let (commands, received_commands) = mpsc::channel();
+    let subscribed = Arc::new(AtomicBool::new(false));
+    thread::spawn(move || {
+      for stream in listener.incoming() {
+        let (subscribed, commands) = (subscribed.clone(), commands.clone());
+        let stream = stream.unwrap();
+        thread::spawn(move || serve(stream, subscribed, commands));
+      }
+    });
+
+    let c = glib::MainContext::default();
+    c.block_on(async {
+      let sway = Sway::connect(&socket_path).unwrap();
+
+      // The event makes the panel read the workspaces again
+      let workspaces = sway
+        .subscribe()
+        .filter(|workspaces| future::ready(workspaces.len() == 2))
+        .next()
+        .await
+        .unwrap();
+      assert_eq!(workspaces[1].name, "2: \"web\"");
+      assert!(workspaces[1].focused);
+
+      sway.focus_workspace(&workspaces[1]).await.unwrap();
+    });
+
+    assert_eq!(
+      received_commands.recv().unwrap(),
+      r#"workspace "2: \"web\"""#
+    );
+    fs::remove_dir_all(&directory).unwrap();
+  }
+}
@@ -0,0 +1,159 @@
+use crate::config::NetworkMonitorConfig;
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use futures::prelude::*;
+use log::warn;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs;
+use std::io;
+use std::rc::Rc;
+
+const NET_DEV_PATH: &str = "/proc/net/dev";
+
+/// Transfer rates in bytes per second.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Sample {
+  pub down: f64,
+  pub up: f64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Throughput {
+  /// Recent samples per interface, oldest first.
+  pub interfaces: BTreeMap<String, VecDeque<Sample>>,
+}
+
+impl Throughput {
+  /// The latest rates summed over all interfaces.
+  pub fn total(&self) -> Sample {
+    self
+      .interfaces
+      .values()
+      .filter_map(|history| history.back())
+      .fold(Sample::default(), |total, sample| Sample {
+        down: total.down + sample.down,
+        up: total.up + sample.up,
+      })
+  }
+}
+
+/// Reads the received and transmitted byte counters of every interface.
+fn read_counters() -> io::Result<HashMap<String, (u64, u64)>> {
+  let content = fs::read_to_string(NET_DEV_PATH)?;
+
+  // The first two lines are headers
+  Ok(
+    content
+      .lines()
+      .skip(2)
+      .filter_map(|line| {
+        let mut parts = line.splitn(2, ':');
+        let interface = parts.next()?.trim().to_string();
+        let fields = parts
+          .next()?
+          .split_whitespace()
+          .map(|field| field.parse::<u64>().unwrap_or(0))
+          .collect::<Vec<_>>();
+
+        Some((interface, (*fields.get(0)?, *fields.get(8)?)))
+      })
+      .collect(),
+  )
+}
+
+/// Samples `/proc/net/dev` at a fixed interval and keeps a short history of
+/// the transfer rates.
+pub struct ThroughputMonitor {
+  throughput: Rc<RefCell<Throughput>>,
+  subscribers: Rc<RefCell<Vec<UnboundedSender<Throughput>>>>,
+}
+
+impl ThroughputMonitor {
+  pub fn new(config: &NetworkMonitorConfig) -> ThroughputMonitor {
+    let monitor = ThroughputMonitor {
+      throughput: Rc::new(RefCell::new(Throughput::default())),
+      subscribers: Rc::new(RefCell::new(vec![])),
+    };
+    monitor.watch(config);
+
+    monitor
+  }
+
+  fn watch(&self, config: &NetworkMonitorConfig) {
+    let interval_seconds = config.interval_seconds.max(1);
+    let history_length = config.history_length.max(2);
+    let selected_interfaces = config.interfaces.clone();
+    let throughput = self.throughput.clone();
+    let subscribers = self.subscribers.clone();
+    let mut previous_counters: Option<HashMap<String, (u64, u64)>> = None;
+
+    gtk::timeout_add_seconds(interval_seconds, move || {
+      let counters = match read_counters() {
+        Ok(counters) => counters,
+        Err(error) => {
+          // Try again later, rates over the gap would be wrong
+          warn!("Could not read {}: {}", NET_DEV_PATH, error);
+          previous_counters = None;
+          return gtk::Continue(true);
+        }
+      };
+
+      if let Some(ref previous_counters) = previous_counters {
+        let mut throughput = throughput.borrow_mut();
+
+        for (interface, &(received, transmitted)) in counters.iter() {
+          let is_selected = if selected_interfaces.is_empty() {
+            interface != "lo"
+          } else {
+            selected_interfaces.contains(interface)
+          };
+          let previous = match previous_counters.get(interface) {
+            Some(previous) if is_selected => previous,
+            _ => continue,
+          };
+
+          let history = throughput
+            .interfaces
+            .entry(interface.clone())
+            .or_insert_with(VecDeque::new);
+          // Counters restart from zero when an interface is recreated
+          history.push_back(Sample {
+            down: received.saturating_sub(previous.0) as f64 / f64::from(interval_seconds),
+            up: transmitted.saturating_sub(previous.1) as f64 / f64::from(interval_seconds),
+          });
+          while history.len() > history_length {
+            history.pop_front();
+          }
+        }
+
+        let removed_interfaces = throughput
+          .interfaces
+          .keys()
+          .filter(|interface| !counters.contains_key(*interface))
+          .cloned()
+          .collect::<Vec<_>>();
+        for interface in removed_interfaces {
+          throughput.interfaces.remove(&interface);
+        }
+
+        for subscriber in subscribers.borrow().iter() {
+          if !subscriber.is_closed() {
+            subscriber.unbounded_send(throughput.clone()).unwrap();
+          }
+        }
+      }
+      previous_counters = Some(counters);
+
+      gtk::Continue(true)
+    });
+  }
+
+  /// Emits the rates immediately and then after every sample.
+  pub fn subscribe(&self) -> impl Stream<Item = Throughput> {
+    let (sink, stream) = unbounded::<Throughput>();
+    sink.unbounded_send(self.throughput.borrow().clone()).unwrap();
+    self.subscribers.borrow_mut().push(sink);
+
+    stream
+  }
+}
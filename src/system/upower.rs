@@ -0,0 +1,196 @@
+use crate::system::bus::{prop_bool, prop_f64, prop_i64, prop_str, prop_u64, Bus, Properties};
+use dbus::strings::Path;
+use futures::future::join_all;
+use futures::prelude::*;
+use futures::stream;
+use log::warn;
+use std::time::Duration;
+
+const UPOWER: &str = "org.freedesktop.UPower";
+const UPOWER_PATH: &str = "/org/freedesktop/UPower";
+const DISPLAY_DEVICE_PATH: &str = "/org/freedesktop/UPower/devices/DisplayDevice";
+const DEVICE: &str = "org.freedesktop.UPower.Device";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeviceKind {
+  LinePower,
+  Battery,
+  Ups,
+  Mouse,
+  Keyboard,
+  Phone,
+  Tablet,
+  GamingInput,
+  Headset,
+  Headphones,
+  Other,
+}
+
+impl DeviceKind {
+  fn from_u32(value: u32) -> DeviceKind {
+    match value {
+      1 => DeviceKind::LinePower,
+      2 => DeviceKind::Battery,
+      3 => DeviceKind::Ups,
+      5 => DeviceKind::Mouse,
+      6 => DeviceKind::Keyboard,
+      8 => DeviceKind::Phone,
+      10 => DeviceKind::Tablet,
+      12 => DeviceKind::GamingInput,
+      17 => DeviceKind::Headset,
+      19 => DeviceKind::Headphones,
+      _ => DeviceKind::Other,
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeviceState {
+  Unknown,
+  Charging,
+  Discharging,
+  Empty,
+  FullyCharged,
+  PendingCharge,
+  PendingDischarge,
+}
+
+impl DeviceState {
+  fn from_u32(value: u32) -> DeviceState {
+    match value {
+      1 => DeviceState::Charging,
+      2 => DeviceState::Discharging,
+      3 => DeviceState::Empty,
+      4 => DeviceState::FullyCharged,
+      5 => DeviceState::PendingCharge,
+      6 => DeviceState::PendingDischarge,
+      _ => DeviceState::Unknown,
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct Device {
+  pub kind: DeviceKind,
+  pub model: String,
+  pub percentage: f64,
+  pub state: DeviceState,
+  pub time_to_empty: Option<Duration>,
+  pub time_to_full: Option<Duration>,
+  pub icon_name: String,
+  pub is_present: bool,
+  pub power_supply: bool,
+}
+
+fn duration(seconds: Option<i64>) -> Option<Duration> {
+  seconds
+    .filter(|seconds| *seconds > 0)
+    .map(|seconds| Duration::from_secs(seconds as u64))
+}
+
+impl Device {
+  fn from_properties(properties: &Properties) -> Device {
+    Device {
+      kind: DeviceKind::from_u32(prop_u64(properties, "Type").unwrap_or(0) as u32),
+      model: prop_str(properties, "Model").unwrap_or_default(),
+      percentage: prop_f64(properties, "Percentage").unwrap_or(0.0),
+      state: DeviceState::from_u32(prop_u64(properties, "State").unwrap_or(0) as u32),
+      time_to_empty: duration(prop_i64(properties, "TimeToEmpty")),
+      time_to_full: duration(prop_i64(properties, "TimeToFull")),
+      icon_name: prop_str(properties, "IconName").unwrap_or_default(),
+      is_present: prop_bool(properties, "IsPresent").unwrap_or(false),
+      power_supply: prop_bool(properties, "PowerSupply").unwrap_or(false),
+    }
+  }
+
+  pub fn is_charging(&self) -> bool {
+    match self.state {
+      DeviceState::Charging | DeviceState::FullyCharged | DeviceState::PendingCharge => true,
+      _ => false,
+    }
+  }
+}
+
+async fn read_device(bus: Bus, path: String) -> Result<Device, dbus::Error> {
+  let properties = bus.get_all_properties(UPOWER, &path, DEVICE).await?;
+
+  Ok(Device::from_properties(&properties))
+}
+
+async fn read_devices(bus: Bus) -> Result<Vec<Device>, dbus::Error> {
+  let (paths,): (Vec<Path<'static>>,) = bus
+    .call(UPOWER, UPOWER_PATH, UPOWER, "EnumerateDevices", ())
+    .await?;
+
+  join_all(
+    paths
+      .into_iter()
+      .map(|path| read_device(bus.clone(), path.to_string())),
+  )
+  .await
+  .into_iter()
+  .collect()
+}
+
+fn log_errors<T>(result: Result<T, dbus::Error>) -> impl Future<Output = Option<T>> {
+  future::ready(match result {
+    Ok(value) => Some(value),
+    Err(error) => {
+      warn!("Could not read power devices from UPower: {}", error);
+      None
+    }
+  })
+}
+
+pub struct UPower {
+  bus: Bus,
+}
+
+impl UPower {
+  pub fn new(bus: Bus) -> UPower {
+    UPower { bus }
+  }
+
+  /// Emits the composite device UPower uses to represent the system's
+  /// batteries, first immediately and then on every change.
+  ///
+  /// Anything emitted on `refresh` forces a re-read, e.g. after resuming
+  /// from sleep when the values may be stale.
+  pub fn subscribe_to_display_device<S>(&self, refresh: S) -> impl Stream<Item = Device>
+  where
+    S: Stream<Item = ()>,
+  {
+    let bus = self.bus.clone();
+    let changes = self
+      .bus
+      .subscribe_to_properties(UPOWER, Some(DISPLAY_DEVICE_PATH));
+
+    stream::once(future::ready(()))
+      .chain(stream::select(changes, refresh))
+      .then(move |_| read_device(bus.clone(), DISPLAY_DEVICE_PATH.to_string()))
+      .filter_map(log_errors)
+  }
+
+  /// Emits every power device, including peripherals such as mice and
+  /// headsets, whenever any of them change.
+  pub fn subscribe_to_devices(&self) -> impl Stream<Item = Vec<Device>> {
+    let bus = self.bus.clone();
+    let changes = stream::select(
+      self.bus.subscribe_to_properties(UPOWER, None),
+      stream::select(
+        self
+          .bus
+          .subscribe_to_signal(UPOWER, Some(UPOWER_PATH), UPOWER, "DeviceAdded"),
+        self
+          .bus
+          .subscribe_to_signal(UPOWER, Some(UPOWER_PATH), UPOWER, "DeviceRemoved"),
+      )
+      .map(|_| ()),
+    );
+
+    stream::once(future::ready(()))
+      .chain(changes)
+      .then(move |_| read_devices(bus.clone()))
+      .filter_map(log_errors)
+  }
+}
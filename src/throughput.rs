@@ -0,0 +1,239 @@
+use crate::clone;
+use crate::popup::create_popup;
+use crate::system::network::{Connectivity, NetworkManager, NetworkState};
+use crate::system::throughput::{Sample, Throughput, ThroughputMonitor};
+use crate::utils::format_panel_text;
+use futures::prelude::*;
+use glib::MainContext;
+use glib::PRIORITY_DEFAULT_IDLE;
+use gtk::prelude::*;
+use log::error;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+const GRAPH_WIDTH: i32 = 250;
+const GRAPH_HEIGHT: i32 = 60;
+
+fn format_rate(bytes_per_second: f64) -> String {
+  match bytes_per_second {
+    rate if rate >= 1e9 => format!("{:.1} GB/s", rate / 1e9),
+    rate if rate >= 1e6 => format!("{:.1} MB/s", rate / 1e6),
+    rate if rate >= 1e3 => format!("{:.0} kB/s", rate / 1e3),
+    rate => format!("{:.0} B/s", rate),
+  }
+}
+
+fn format_sample(sample: Sample) -> String {
+  format!("↓ {} ↑ {}", format_rate(sample.down), format_rate(sample.up))
+}
+
+fn draw_line(cr: &cairo::Context, history: &VecDeque<Sample>, value: fn(&Sample) -> f64) {
+  let width = f64::from(GRAPH_WIDTH);
+  let height = f64::from(GRAPH_HEIGHT);
+  let max = history
+    .iter()
+    .map(|sample| sample.down.max(sample.up))
+    .fold(1.0, f64::max);
+  let step = width / (history.len().max(2) - 1) as f64;
+
+  cr.move_to(0.0, height);
+  for (index, sample) in history.iter().enumerate() {
+    cr.line_to(index as f64 * step, height - value(sample) / max * height);
+  }
+}
+
+/// Draws the download rate as a filled area with the upload rate on top.
+fn draw_graph(cr: &cairo::Context, history: &VecDeque<Sample>) {
+  cr.set_source_rgba(1.0, 1.0, 1.0, 0.1);
+  cr.rectangle(0.0, 0.0, f64::from(GRAPH_WIDTH), f64::from(GRAPH_HEIGHT));
+  cr.fill();
+
+  if history.is_empty() {
+    return;
+  }
+
+  draw_line(cr, history, |sample| sample.down);
+  cr.line_to(
+    (history.len() - 1) as f64 * f64::from(GRAPH_WIDTH) / (history.len().max(2) - 1) as f64,
+    f64::from(GRAPH_HEIGHT),
+  );
+  cr.close_path();
+  cr.set_source_rgba(0.2, 0.6, 1.0, 0.6);
+  cr.fill();
+
+  draw_line(cr, history, |sample| sample.up);
+  cr.set_source_rgba(1.0, 0.6, 0.2, 1.0);
+  cr.set_line_width(1.5);
+  cr.stroke();
+}
+
+struct InterfaceGraph {
+  row: gtk::Box,
+  label: gtk::Label,
+  graph: gtk::DrawingArea,
+}
+
+fn create_interface_graph(interface: &str, throughput: &Rc<RefCell<Throughput>>) -> InterfaceGraph {
+  let row = gtk::Box::new(gtk::Orientation::Vertical, 2);
+
+  let label = gtk::Label::new(None);
+  label.set_xalign(0.0);
+  row.add(&label);
+
+  let graph = gtk::DrawingArea::new();
+  graph.set_size_request(GRAPH_WIDTH, GRAPH_HEIGHT);
+  let interface = interface.to_string();
+  graph.connect_draw(clone!(throughput => move |_, cr| {
+    if let Some(history) = throughput.borrow().interfaces.get(&interface) {
+      draw_graph(cr, history);
+    }
+
+    Inhibit(false)
+  }));
+  row.add(&graph);
+
+  InterfaceGraph { row, label, graph }
+}
+
+fn update_connectivity(label: &gtk::Label, sign_in_button: &gtk::Button, state: &NetworkState) {
+  let text = match state.connectivity {
+    Connectivity::Full => "Connected to the internet",
+    Connectivity::Portal => "Sign in to the network to reach the internet",
+    Connectivity::Limited => "The network is connected but can not reach the internet",
+    Connectivity::None => "Not connected",
+    Connectivity::Unknown => "Internet connectivity is not being checked",
+  };
+  label.set_text(text);
+  sign_in_button.set_visible(
+    state.connectivity == Connectivity::Portal && state.connectivity_check_uri.is_some(),
+  );
+}
+
+fn create_monitor_popup_content(
+  c: &MainContext,
+  monitor: &Rc<ThroughputMonitor>,
+  network: &Rc<NetworkManager>,
+) -> gtk::Box {
+  let content = gtk::Box::new(gtk::Orientation::Vertical, 8);
+  content.set_margin_top(8);
+  content.set_margin_bottom(8);
+  content.set_margin_start(8);
+  content.set_margin_end(8);
+
+  let connectivity_row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+  let connectivity_label = gtk::Label::new(None);
+  connectivity_label.set_line_wrap(true);
+  connectivity_label.set_xalign(0.0);
+  connectivity_row.pack_start(&connectivity_label, true, true, 0);
+  let sign_in_button = gtk::Button::new_with_label("Sign In");
+  sign_in_button.set_no_show_all(true);
+  connectivity_row.pack_end(&sign_in_button, false, false, 0);
+  content.add(&connectivity_row);
+
+  let graphs_box = gtk::Box::new(gtk::Orientation::Vertical, 8);
+  content.add(&graphs_box);
+
+  let destroyed = Rc::new(Cell::new(false));
+  content.connect_destroy(clone!(destroyed => move |_| {
+    destroyed.set(true);
+  }));
+
+  let portal_uri: Rc<RefCell<Option<String>>> = Rc::default();
+  sign_in_button.connect_clicked(clone!(portal_uri => move |_| {
+    if let Some(ref uri) = *portal_uri.borrow() {
+      let result = gio::AppInfo::launch_default_for_uri(uri, None::<&gio::AppLaunchContext>);
+      if let Err(error) = result {
+        error!("Could not open the captive portal {}: {}", uri, error);
+      }
+    }
+  }));
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    network
+      .subscribe()
+      .take_while(clone!(destroyed => move |_| future::ready(!destroyed.get())))
+      .for_each(move |state| {
+        update_connectivity(&connectivity_label, &sign_in_button, &state);
+        *portal_uri.borrow_mut() = state.connectivity_check_uri.clone();
+
+        future::ready(())
+      }),
+  );
+
+  let throughput = Rc::new(RefCell::new(Throughput::default()));
+  let mut graphs: HashMap<String, InterfaceGraph> = HashMap::new();
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    monitor
+      .subscribe()
+      .take_while(move |_| future::ready(!destroyed.get()))
+      .for_each(move |new_throughput| {
+        graphs.retain(|interface, graph| {
+          let keep = new_throughput.interfaces.contains_key(interface);
+          if !keep {
+            graphs_box.remove(&graph.row);
+          }
+          keep
+        });
+
+        for (interface, history) in new_throughput.interfaces.iter() {
+          let graph = graphs.entry(interface.clone()).or_insert_with(|| {
+            let graph = create_interface_graph(interface, &throughput);
+            graphs_box.add(&graph.row);
+            graph.row.show_all();
+            graph
+          });
+          let latest = history.back().cloned().unwrap_or_default();
+          graph
+            .label
+            .set_markup(&format!(
+              "<b>{}</b>  {}",
+              glib::markup_escape_text(interface),
+              format_sample(latest)
+            ));
+          graph.graph.queue_draw();
+        }
+
+        *throughput.borrow_mut() = new_throughput;
+
+        future::ready(())
+      }),
+  );
+
+  content
+}
+
+/// Panel label with the current transfer rates, opening graphs of the
+/// recent rates and the internet connectivity when clicked.
+pub fn create_throughput_indicator(
+  c: MainContext,
+  monitor: Rc<ThroughputMonitor>,
+  network: Rc<NetworkManager>,
+) -> gtk::EventBox {
+  let label = gtk::Label::new(None);
+  label.set_margin_top(6);
+  label.set_margin_bottom(6);
+
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    monitor.subscribe().for_each(clone!(label => move |throughput| {
+      label.set_markup(&format_panel_text(format_sample(throughput.total())));
+
+      future::ready(())
+    })),
+  );
+
+  let button = gtk::EventBox::new();
+  button.add(&label);
+
+  button.connect_button_press_event(move |button, _| {
+    let content = create_monitor_popup_content(&c, &monitor, &network);
+    let show_popup = create_popup(button, &content);
+
+    show_popup();
+    Inhibit(false)
+  });
+
+  button
+}
@@ -0,0 +1,57 @@
+use crate::clone;
+use crate::utils::{format_panel_text, set_window_background};
+use gtk::prelude::*;
+use gtk_layer_shell_rs as gtk_layer_shell;
+use log::error;
+
+const TOAST_TIMEOUT_SECONDS: u32 = 5;
+
+pub fn show_toast(message: &str) {
+  let window = gtk::Window::new(gtk::WindowType::Toplevel);
+
+  set_window_background(&window, 0.0, 0.0, 0.0, 0.0);
+
+  gtk_layer_shell::init_for_window(&window);
+  gtk_layer_shell::set_layer(&window, gtk_layer_shell::Layer::Overlay);
+  gtk_layer_shell::set_anchor(&window, gtk_layer_shell::Edge::Top, true);
+  gtk_layer_shell::set_margin(&window, gtk_layer_shell::Edge::Top, 8);
+
+  let label = gtk::Label::new(None);
+  label.set_markup(&format_panel_text(glib::markup_escape_text(message)));
+
+  let toast = gtk::EventBox::new();
+  toast.get_style_context().add_class("toast");
+  toast.add(&label);
+  window.add(&toast);
+
+  toast.connect_button_press_event(clone!(window => move |_, _| {
+    window.close();
+    Inhibit(false)
+  }));
+
+  window.show_all();
+
+  gtk::timeout_add_seconds(TOAST_TIMEOUT_SECONDS, move || {
+    window.close();
+    gtk::Continue(false)
+  });
+}
+
+/// Logs a failed D-Bus call and tells the user about it.
+pub fn show_error_toast(action: &str, error: &dbus::Error) {
+  error!("Failed to {}: {}", action, error);
+
+  let message = match error.name() {
+    Some("org.freedesktop.DBus.Error.AccessDenied")
+    | Some("org.freedesktop.DBus.Error.InteractiveAuthorizationRequired") => {
+      format!("Not authorized to {}", action)
+    }
+    _ => format!(
+      "Failed to {}: {}",
+      action,
+      error.message().unwrap_or("Unknown error")
+    ),
+  };
+
+  show_toast(&message);
+}
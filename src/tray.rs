@@ -0,0 +1,383 @@
+use crate::clone;
+use crate::notifications::sanitize_markup;
+use crate::popup::create_popup_with_submenus;
+use crate::system::status_notifier::{
+  Icon, ItemAddress, ItemStatus, MenuItem, StatusNotifierHost, ToggleType, TrayItem,
+};
+use futures::prelude::*;
+use gdk_pixbuf::{Colorspace, InterpType, Pixbuf};
+use glib::MainContext;
+use glib::PRIORITY_DEFAULT_IDLE;
+use gtk::prelude::*;
+use log::warn;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+
+const ICON_SIZE: i32 = 16;
+const OVERLAY_ICON_SIZE: i32 = 8;
+
+/// Picks the smallest pixmap that is at least `size`, or else the largest.
+fn create_pixbuf(icon: &Icon, size: i32) -> Option<Pixbuf> {
+  let pixmap = icon
+    .pixmaps
+    .iter()
+    .filter(|pixmap| pixmap.width >= size)
+    .min_by_key(|pixmap| pixmap.width)
+    .or_else(|| icon.pixmaps.iter().max_by_key(|pixmap| pixmap.width))?;
+
+  let pixbuf = Pixbuf::new_from_bytes(
+    &glib::Bytes::from(&pixmap.data[..]),
+    Colorspace::Rgb,
+    true,
+    8,
+    pixmap.width,
+    pixmap.height,
+    pixmap.width * 4,
+  );
+
+  if pixmap.width == size && pixmap.height == size {
+    Some(pixbuf)
+  } else {
+    pixbuf.scale_simple(size, size, InterpType::Bilinear)
+  }
+}
+
+/// Sets `image` to `icon`, preferring the icon name when the theme has it.
+fn set_icon(image: &gtk::Image, icon: &Icon, size: i32) -> bool {
+  let has_icon_name = icon.name.as_ref().map_or(false, |name| {
+    name.starts_with('/')
+      || gtk::IconTheme::get_default().map_or(false, |theme| theme.has_icon(name))
+  });
+
+  match icon.name {
+    Some(ref name) if has_icon_name && name.starts_with('/') => {
+      match Pixbuf::new_from_file_at_size(name, size, size) {
+        Ok(pixbuf) => image.set_from_pixbuf(Some(&pixbuf)),
+        Err(_) => return false,
+      }
+    }
+    Some(ref name) if has_icon_name => {
+      image.set_from_icon_name(Some(name.as_str()), gtk::IconSize::SmallToolbar);
+      image.set_pixel_size(size);
+    }
+    _ => match create_pixbuf(icon, size) {
+      Some(pixbuf) => image.set_from_pixbuf(Some(&pixbuf)),
+      None => return false,
+    },
+  }
+
+  true
+}
+
+/// Lets the icon theme find icons installed with the application.
+fn add_icon_theme_path(path: &str) {
+  if let Some(theme) = gtk::IconTheme::get_default() {
+    if !theme.get_search_path().iter().any(|search_path| search_path == Path::new(path)) {
+      theme.append_search_path(path);
+    }
+  }
+}
+
+fn format_tooltip(item: &TrayItem) -> String {
+  let title = if item.tooltip_title.is_empty() {
+    &item.title
+  } else {
+    &item.tooltip_title
+  };
+  let title = glib::markup_escape_text(title).to_string();
+
+  if item.tooltip_description.is_empty() {
+    title
+  } else {
+    format!(
+      "<b>{}</b>\n{}",
+      title,
+      sanitize_markup(&item.tooltip_description)
+    )
+  }
+}
+
+fn update_tray_item(
+  button: &gtk::EventBox,
+  image: &gtk::Image,
+  overlay_image: &gtk::Image,
+  item: &TrayItem,
+) {
+  if let Some(ref path) = item.icon_theme_path {
+    add_icon_theme_path(path);
+  }
+
+  let icon = if item.status == ItemStatus::NeedsAttention && !item.attention_icon.is_empty() {
+    &item.attention_icon
+  } else {
+    &item.icon
+  };
+  if !set_icon(image, icon, ICON_SIZE) {
+    image.set_from_icon_name(Some("image-missing"), gtk::IconSize::SmallToolbar);
+  }
+  overlay_image.set_visible(set_icon(overlay_image, &item.overlay_icon, OVERLAY_ICON_SIZE));
+
+  let tooltip = format_tooltip(item);
+  button.set_tooltip_markup(if tooltip.is_empty() {
+    None
+  } else {
+    Some(tooltip.as_str())
+  });
+  button.set_visible(item.status != ItemStatus::Passive);
+}
+
+/// Removes the `_` marking mnemonics, keeping escaped `__` as `_`.
+fn strip_mnemonic(label: &str) -> String {
+  let mut stripped = String::new();
+  let mut chars = label.chars();
+
+  while let Some(c) = chars.next() {
+    if c == '_' {
+      if let Some(next) = chars.next() {
+        stripped.push(next);
+      }
+    } else {
+      stripped.push(c);
+    }
+  }
+
+  stripped
+}
+
+fn add_menu_items(
+  container: &gtk::Box,
+  items: &[MenuItem],
+  parent: &str,
+  submenus: &mut Vec<(String, gtk::Widget)>,
+  on_click: &Rc<dyn Fn(i32)>,
+) {
+  for item in items.iter().filter(|item| item.visible) {
+    if item.separator {
+      let separator = gtk::Separator::new(gtk::Orientation::Horizontal);
+      container.add(&separator);
+      continue;
+    }
+
+    let label = strip_mnemonic(&item.label);
+    let button = gtk::ModelButton::new();
+    button.set_label(&label);
+    button.set_sensitive(item.enabled);
+    container.add(&button);
+
+    if item.has_submenu() {
+      let name = format!("tray-menu-{}", item.id);
+      button.set_property_menu_name(Some(name.as_str()));
+
+      let submenu = gtk::Box::new(gtk::Orientation::Vertical, 0);
+      let back_button = gtk::ModelButton::new();
+      back_button.set_label(&label);
+      back_button.set_property_menu_name(Some(parent));
+      back_button.set_property_inverted(true);
+      back_button.set_property_centered(true);
+      submenu.add(&back_button);
+
+      add_menu_items(&submenu, &item.children, &name, submenus, on_click);
+      submenus.push((name, submenu.upcast()));
+      continue;
+    }
+
+    match item.toggle_type {
+      ToggleType::Checkmark => button.set_property_role(gtk::ButtonRole::Check),
+      ToggleType::Radio => button.set_property_role(gtk::ButtonRole::Radio),
+      ToggleType::None => {}
+    }
+    if item.toggle_type != ToggleType::None {
+      button.set_property_active(item.toggled);
+    }
+
+    let id = item.id;
+    button.connect_clicked(clone!(on_click => move |_| {
+      on_click(id);
+    }));
+  }
+}
+
+/// Reads the dbusmenu of `item` and shows it in a popup.
+async fn show_tray_menu(host: Rc<StatusNotifierHost>, button: gtk::EventBox, item: TrayItem) {
+  let root = match host.read_menu(&item).await {
+    Ok(root) => root,
+    Err(error) => {
+      warn!("Could not read the menu of {}: {}", item.id, error);
+      return;
+    }
+  };
+
+  let c = MainContext::default();
+  let on_click: Rc<dyn Fn(i32)> = Rc::new(clone!(host, item => move |id| {
+    c.spawn_local(clone!(host, item => async move {
+      if let Err(error) = host.send_menu_event(&item, id).await {
+        warn!("Could not activate a menu entry of {}: {}", item.id, error);
+      }
+    }));
+  }));
+
+  let menu = gtk::Box::new(gtk::Orientation::Vertical, 0);
+  let mut submenus = vec![];
+  add_menu_items(&menu, &root.children, "main", &mut submenus, &on_click);
+
+  let show_popup = create_popup_with_submenus(&button, &menu, submenus);
+  show_popup();
+}
+
+fn create_tray_item(
+  c: MainContext,
+  host: Rc<StatusNotifierHost>,
+  address: ItemAddress,
+) -> gtk::EventBox {
+  let button = gtk::EventBox::new();
+  // Shown once the item has been read, unless it is passive
+  button.set_no_show_all(true);
+  button.add_events(gdk::EventMask::SCROLL_MASK | gdk::EventMask::SMOOTH_SCROLL_MASK);
+
+  let overlay = gtk::Overlay::new();
+  let image = gtk::Image::new();
+  overlay.add(&image);
+  let overlay_image = gtk::Image::new();
+  overlay_image.set_halign(gtk::Align::End);
+  overlay_image.set_valign(gtk::Align::End);
+  overlay_image.set_no_show_all(true);
+  overlay.add_overlay(&overlay_image);
+  overlay.show_all();
+  button.add(&overlay);
+
+  let current_item: Rc<RefCell<Option<TrayItem>>> = Rc::new(RefCell::new(None));
+
+  button.connect_button_press_event(clone!(c, host, current_item => move |button, event| {
+    let item = match current_item.borrow().clone() {
+      Some(item) => item,
+      None => return Inhibit(false),
+    };
+    let (x, y) = event.get_root();
+    let (x, y) = (x as i32, y as i32);
+    let button = button.clone();
+
+    match event.get_button() {
+      1 if item.item_is_menu && item.menu.is_some() => {
+        c.spawn_local(show_tray_menu(host.clone(), button, item));
+      }
+      1 => c.spawn_local(clone!(host => async move {
+        if let Err(error) = host.activate(&item.address, x, y).await {
+          // Some items only have a menu without saying so
+          if item.menu.is_some() {
+            show_tray_menu(host, button, item).await;
+          } else {
+            warn!("Could not activate {}: {}", item.id, error);
+          }
+        }
+      })),
+      2 => c.spawn_local(clone!(host => async move {
+        if let Err(error) = host.secondary_activate(&item.address, x, y).await {
+          warn!("Could not activate {}: {}", item.id, error);
+        }
+      })),
+      3 if item.menu.is_some() => {
+        c.spawn_local(show_tray_menu(host.clone(), button, item));
+      }
+      3 => c.spawn_local(clone!(host => async move {
+        if let Err(error) = host.context_menu(&item.address, x, y).await {
+          warn!("Could not open the context menu of {}: {}", item.id, error);
+        }
+      })),
+      _ => {}
+    }
+
+    Inhibit(true)
+  }));
+
+  button.connect_scroll_event(clone!(c, host, current_item => move |_, event| {
+    let item = match current_item.borrow().clone() {
+      Some(item) => item,
+      None => return Inhibit(false),
+    };
+    let (delta, orientation) = match event.get_direction() {
+      gdk::ScrollDirection::Up => (1, "vertical"),
+      gdk::ScrollDirection::Down => (-1, "vertical"),
+      gdk::ScrollDirection::Left => (-1, "horizontal"),
+      gdk::ScrollDirection::Right => (1, "horizontal"),
+      gdk::ScrollDirection::Smooth => {
+        let (_, delta_y) = event.get_delta();
+        ((-delta_y).round() as i32, "vertical")
+      }
+      _ => (0, "vertical"),
+    };
+
+    if delta != 0 {
+      c.spawn_local(clone!(host => async move {
+        if let Err(error) = host.scroll(&item.address, delta, orientation).await {
+          warn!("Could not scroll {}: {}", item.id, error);
+        }
+      }));
+    }
+
+    Inhibit(true)
+  }));
+
+  let destroyed = Rc::new(Cell::new(false));
+  button.connect_destroy(clone!(destroyed => move |_| {
+    destroyed.set(true);
+  }));
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    host
+      .subscribe_to_item(address)
+      .take_while(move |_| future::ready(!destroyed.get()))
+      .for_each(clone!(button => move |item| {
+        update_tray_item(&button, &image, &overlay_image, &item);
+        current_item.replace(Some(item));
+
+        future::ready(())
+      })),
+  );
+
+  button
+}
+
+/// Panel area showing the tray items of applications.
+pub fn create_tray(c: MainContext, host: Rc<StatusNotifierHost>) -> gtk::Box {
+  let tray = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+  let mut items: HashMap<ItemAddress, gtk::EventBox> = HashMap::new();
+
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    host
+      .subscribe_to_items()
+      .for_each(clone!(c, host, tray => move |addresses| {
+        let removed = items
+          .keys()
+          .filter(|address| !addresses.contains(address))
+          .cloned()
+          .collect::<Vec<_>>();
+        for address in removed {
+          if let Some(button) = items.remove(&address) {
+            button.destroy();
+          }
+        }
+
+        for address in addresses {
+          if !items.contains_key(&address) {
+            let button = create_tray_item(c.clone(), host.clone(), address.clone());
+            tray.add(&button);
+            items.insert(address, button);
+          }
+        }
+
+        future::ready(())
+      })),
+  );
+
+  tray
+}
+
+/// Lets applications know that their tray items are shown.
+pub async fn register_tray_host(host: Rc<StatusNotifierHost>) {
+  if let Err(error) = host.register().await {
+    warn!("Could not register the status notifier host: {}", error);
+  }
+}
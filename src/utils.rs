@@ -1,4 +1,7 @@
 use gtk::prelude::*;
+use std::io;
+use std::process::Command;
+use std::thread;
 
 // make moving clones into closures more convenient
 #[macro_export]
@@ -11,6 +14,12 @@ macro_rules! clone {
             move || $body
         }
     );
+    ($($n:ident),+ => async move $body:block) => (
+        {
+            $( let $n = $n.clone(); )+
+            async move $body
+        }
+    );
     ($($n:ident),+ => move |$($p:tt),+| $body:expr) => (
         {
             $( let $n = $n.clone(); )+
@@ -54,3 +63,13 @@ pub fn set_window_background<T: WidgetExt>(
 
   window.set_app_paintable(true);
 }
+
+/// Runs a user configured shell command without waiting for it to finish.
+pub fn spawn_command(command: &str) -> io::Result<()> {
+  let mut child = Command::new("sh").arg("-c").arg(command).spawn()?;
+
+  // Reap the child so it does not linger as a zombie
+  thread::spawn(move || child.wait());
+
+  Ok(())
+}
@@ -0,0 +1,137 @@
+use crate::clone;
+use crate::system::sway::{Sway, Workspace};
+use futures::prelude::*;
+use glib::MainContext;
+use glib::PRIORITY_DEFAULT_IDLE;
+use gtk::prelude::*;
+use log::error;
+use std::cell::Cell;
+use std::rc::Rc;
+
+/// Groups workspaces by output, keeping the order sway lists them in.
+fn group_by_output(workspaces: &[Workspace]) -> Vec<(&str, Vec<&Workspace>)> {
+  let mut outputs: Vec<(&str, Vec<&Workspace>)> = vec![];
+
+  for workspace in workspaces {
+    match outputs.iter_mut().find(|(output, _)| *output == workspace.output) {
+      Some((_, group)) => group.push(workspace),
+      None => outputs.push((workspace.output.as_str(), vec![workspace])),
+    }
+  }
+
+  outputs
+}
+
+fn create_workspace_button(c: &MainContext, sway: &Rc<Sway>, workspace: &Workspace) -> gtk::Button {
+  let button = gtk::Button::new_with_label(&workspace.name);
+  button.set_relief(gtk::ReliefStyle::None);
+
+  let style_context = button.get_style_context();
+  style_context.add_class("workspace");
+  if workspace.focused {
+    style_context.add_class("focused");
+  }
+  if workspace.visible {
+    style_context.add_class("visible");
+  }
+  if workspace.urgent {
+    style_context.add_class("urgent");
+  }
+
+  let workspace = workspace.clone();
+  button.connect_clicked(clone!(c, sway => move |_| {
+    c.spawn_local(clone!(sway, workspace => async move {
+      if let Err(error) = sway.focus_workspace(&workspace).await {
+        error!("Failed to switch to workspace {}: {}", workspace.name, error);
+      }
+    }));
+  }));
+
+  button
+}
+
+/// The workspaces of one output, switched between when scrolled over.
+fn create_output_workspaces(
+  c: &MainContext,
+  sway: &Rc<Sway>,
+  output: &str,
+  workspaces: &[&Workspace],
+  show_output: bool,
+) -> gtk::EventBox {
+  let group = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+  if show_output {
+    let label = gtk::Label::new(None);
+    label.set_markup(&format!("<small>{}</small>", glib::markup_escape_text(output)));
+    label.set_margin_end(4);
+    group.add(&label);
+  }
+  for workspace in workspaces {
+    group.add(&create_workspace_button(c, sway, workspace));
+  }
+
+  let event_box = gtk::EventBox::new();
+  event_box.add(&group);
+  event_box.add_events(gdk::EventMask::SCROLL_MASK | gdk::EventMask::SMOOTH_SCROLL_MASK);
+
+  // Touchpads scroll in small steps that are summed up to whole ones
+  let scrolled = Cell::new(0.0);
+  let output = output.to_string();
+  event_box.connect_scroll_event(clone!(c, sway => move |_, event| {
+    let delta = match event.get_direction() {
+      gdk::ScrollDirection::Up | gdk::ScrollDirection::Left => -1.0,
+      gdk::ScrollDirection::Down | gdk::ScrollDirection::Right => 1.0,
+      gdk::ScrollDirection::Smooth => {
+        let (_, delta_y) = event.get_delta();
+        delta_y
+      }
+      _ => 0.0,
+    };
+    scrolled.set(scrolled.get() + delta);
+
+    if scrolled.get().abs() >= 1.0 {
+      let forward = scrolled.get() > 0.0;
+      scrolled.set(0.0);
+
+      c.spawn_local(clone!(sway, output => async move {
+        if let Err(error) = sway.focus_adjacent_workspace(&output, forward).await {
+          error!("Failed to switch workspace: {}", error);
+        }
+      }));
+    }
+
+    Inhibit(true)
+  }));
+
+  event_box
+}
+
+/// Panel area with the workspaces of every output, hidden while there are
+/// none, such as after the window manager closed the connection.
+pub fn create_workspaces(c: MainContext, sway: Rc<Sway>) -> gtk::Box {
+  let container = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+  container.set_no_show_all(true);
+
+  c.spawn_local_with_priority(
+    PRIORITY_DEFAULT_IDLE,
+    sway.subscribe().for_each(clone!(c, sway, container => move |workspaces| {
+      for child in container.get_children() {
+        container.remove(&child);
+      }
+
+      let outputs = group_by_output(&workspaces);
+      let show_outputs = outputs.len() > 1;
+      for (output, workspaces) in outputs {
+        container.add(&create_output_workspaces(&c, &sway, output, &workspaces, show_outputs));
+      }
+      if workspaces.is_empty() {
+        container.hide();
+      } else {
+        container.show_all();
+      }
+
+      future::ready(())
+    })),
+  );
+
+  container
+}